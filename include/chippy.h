@@ -0,0 +1,58 @@
+/* C interface to the chippy emulator core, built with `cargo build --lib --no-default-features --features capi` */
+#ifndef CHIPPY_H
+#define CHIPPY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Size of the frame buffer in pixels, lores pixels are two by two */
+#define CHIPPY_WIDTH 128
+#define CHIPPY_HEIGHT 64
+
+/* Bytes of the screen packed to one bit per pixel */
+#define CHIPPY_PACKED_SIZE (CHIPPY_WIDTH * CHIPPY_HEIGHT / 8)
+/* Row by row, the leftmost pixel in the highest bit */
+#define CHIPPY_LAYOUT_ROWS 0
+/* SSD1306 pages of eight rows, one byte per column with the top pixel in the lowest bit */
+#define CHIPPY_LAYOUT_PAGES 1
+
+#define CHIPPY_PRESET_DEFAULT 0
+#define CHIPPY_PRESET_CHIP8 1
+#define CHIPPY_PRESET_SUPERCHIP 2
+#define CHIPPY_PRESET_ETI660 3
+
+typedef struct Chippy Chippy;
+
+/* Creates a machine with the default quirks and no program */
+Chippy *chippy_new(uint64_t seed);
+/* Frees a machine, null is ignored */
+void chippy_free(Chippy *chippy);
+
+/* Loads a program and restarts it, returns 0 on success and -1 if it doesn't fit in memory */
+int chippy_load_rom(Chippy *chippy, const uint8_t *rom, size_t length);
+/* Selects one of the CHIPPY_PRESET_* quirk sets, returns -1 for an unknown preset.
+ * Presets loading programs at another address move the loaded program there and restart it. */
+int chippy_set_preset(Chippy *chippy, int preset);
+
+/* Runs up to `instructions` instructions and ticks the timers once, call it 60 times a second.
+ * Returns 1 if the screen changed and -1 if the program crashed the interpreter. */
+int chippy_step(Chippy *chippy, size_t instructions);
+/* Presses (non-zero) or releases keypad key 0 to 15 */
+void chippy_set_key(Chippy *chippy, uint8_t key, int pressed);
+
+/* CHIPPY_WIDTH * CHIPPY_HEIGHT RGBA pixels, valid until the next call on the same machine */
+const uint8_t *chippy_framebuffer(Chippy *chippy);
+/* Writes CHIPPY_PACKED_SIZE bytes of the screen in a CHIPPY_LAYOUT_* layout, returns -1 for an unknown layout */
+int chippy_framebuffer_packed(const Chippy *chippy, int layout, uint8_t *buffer);
+int chippy_sound_playing(const Chippy *chippy);
+int chippy_has_exited(const Chippy *chippy);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
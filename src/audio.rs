@@ -0,0 +1,109 @@
+pub mod null;
+#[cfg(feature = "sound")]
+pub mod cpal;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod midi;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod command;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+
+/// Frequency of the plain buzzer tone in Hz
+pub const TONE_FREQUENCY: f32 = 440.0;
+/// How many patterns sinks mix together at most, with the extended multi-voice XO-Chip audio
+pub const MAX_VOICES: usize = 4;
+
+
+/// Receives the sound output of the machine.
+/// Frontends pick the implementation for their platform and feed it the buzzer state.
+pub trait AudioSink {
+    /// Turns the buzzer on or off, called whenever the sound timer starts or stops running
+    fn set_buzzer(&mut self, on: bool);
+    /// Makes the buzzer play XO-Chip sample buffers mixed together instead of the plain tone,
+    /// or the plain tone again with none. Only the first [`MAX_VOICES`] are played.
+    fn set_patterns(&mut self, patterns: &[Pattern]);
+    /// Output volume between 0.0 and 1.0
+    fn set_volume(&mut self, volume: f32);
+}
+
+
+/// An XO-Chip audio pattern of 128 one-bit samples, looped while the buzzer is on
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub samples: [u8; 16],
+    /// Playback rate in samples per second
+    pub rate: f32,
+}
+impl Pattern {
+    pub const LENGTH: usize = 128;
+    /// The pitch XO-Chip starts with, playing 4000 samples per second
+    pub const DEFAULT_PITCH: u8 = 64;
+
+    /// A pattern played at the rate XO-Chip derives from its pitch register
+    pub fn with_pitch(samples: [u8; 16], pitch: u8) -> Self {
+        Self {
+            samples,
+            rate: pitch_rate(pitch),
+        }
+    }
+
+    /// The sample at `index`, wrapping around the end of the pattern
+    pub fn sample(&self, index: usize) -> bool {
+        let index = index % Self::LENGTH;
+        self.samples[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+}
+
+
+/// Playback rate in samples per second that XO-Chip plays patterns at for a pitch register value.
+/// Each step of the register is a 48th of an octave, with 64 at 4000 samples per second.
+pub fn pitch_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+
+/// Mixes the patterns of all voices into one signal, for sinks that compute their own samples
+#[derive(Clone, Debug)]
+pub struct Mixer {
+    /// The pattern of each voice with its position in samples
+    voices: Vec<(Pattern, f32)>,
+}
+impl Mixer {
+    pub fn new() -> Self {
+        Self {
+            voices: Vec::with_capacity(MAX_VOICES),
+        }
+    }
+
+    /// Plays `patterns` from now on. Voices keep their position, so replacing
+    /// a pattern with the next part of a longer sound doesn't click.
+    pub fn set_patterns(&mut self, patterns: &[Pattern]) {
+        let patterns = &patterns[..patterns.len().min(MAX_VOICES)];
+        self.voices.truncate(patterns.len());
+        for (i, &pattern) in patterns.iter().enumerate() {
+            match self.voices.get_mut(i) {
+                Some(voice) => voice.0 = pattern,
+                None => self.voices.push((pattern, 0.0)),
+            }
+        }
+    }
+    /// Whether no pattern is playing, so the plain tone should be
+    pub fn is_empty(&self) -> bool {
+        self.voices.is_empty()
+    }
+
+    /// Advances every voice by one sample at `sample_rate` and returns the mix between -1.0 and 1.0
+    pub fn sample(&mut self, sample_rate: f32) -> f32 {
+        let mut sum = 0.0;
+        for (pattern, phase) in &mut self.voices {
+            *phase = (*phase + pattern.rate / sample_rate) % Pattern::LENGTH as f32;
+            sum += if pattern.sample(*phase as usize) { 1.0 } else { -1.0 };
+        }
+        sum / self.voices.len().max(1) as f32
+    }
+}
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,56 @@
+use std::process::{Child, Command};
+use tracing::error;
+use super::{AudioSink, Pattern};
+
+
+/// Runs an external command whenever the buzzer starts and another when it stops,
+/// for sound hardware chippy has no driver for
+pub struct CommandSink {
+    on: String,
+    off: String,
+    muted: bool,
+    /// Whether the on command ran last, so the off command only runs after it
+    sounding: bool,
+    /// Commands that were started and have yet to be reaped
+    running: Vec<Child>,
+}
+impl CommandSink {
+    /// Commands are split at whitespace into the program and its arguments, an empty one does nothing
+    pub fn new(on: impl Into<String>, off: impl Into<String>) -> Self {
+        Self {
+            on: on.into(),
+            off: off.into(),
+            muted: false,
+            sounding: false,
+            running: Vec::new(),
+        }
+    }
+}
+impl AudioSink for CommandSink {
+    fn set_buzzer(&mut self, on: bool) {
+        self.running.retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+        // Muted buzzers don't start, and only started ones are stopped, so scripts see pairs
+        let on = on && !self.muted;
+        if on == self.sounding {
+            return;
+        }
+        self.sounding = on;
+        let command = if on { &self.on } else { &self.off };
+        let mut words = command.split_whitespace();
+        let Some(program) = words.next() else {
+            return;
+        };
+        // Not waited for, the emulator shouldn't stall on a slow command
+        match Command::new(program).args(words).spawn() {
+            Ok(child) => self.running.push(child),
+            Err(e) => error!("Could not run buzzer command '{}': {}", command, e),
+        }
+    }
+    fn set_patterns(&mut self, _patterns: &[Pattern]) {}
+    fn set_volume(&mut self, volume: f32) {
+        self.muted = volume <= 0.0;
+        if self.muted {
+            self.set_buzzer(false);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::{error::Error, time::Duration, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, Ordering}}};
+use ::cpal::{traits::{DeviceTrait, HostTrait, StreamTrait}, BufferSize, FromSample, SampleFormat, SizedSample, Stream, StreamConfig, SupportedBufferSize, Device};
+use tracing::{debug, error};
+use super::{AudioSink, Mixer, Pattern, MAX_VOICES, TONE_FREQUENCY};
+
+/// How long the tone takes to fade in and out, so starting and stopping it doesn't click
+const RAMP_SECONDS: f32 = 0.003;
+
+
+/// Plays the buzzer on the default output device of the system
+pub struct CpalSink {
+    shared: Arc<Shared>,
+    _stream: Stream,
+}
+impl CpalSink {
+    /// Opens the stream with `buffer_size` sample frames per buffer, clamped to what the device supports,
+    /// or the device's default buffer size. Every beep lasts at least `min_beep`, however briefly the buzzer is on.
+    pub fn new(buffer_size: Option<u32>, min_beep: Duration) -> Result<Self, Box<dyn Error>> {
+        let shared = Arc::new(Shared {
+            playing: AtomicBool::new(false),
+            started: AtomicBool::new(false),
+            min_beep,
+            amplitude: AtomicU32::new(0),
+            patterns: Mutex::new(Vec::with_capacity(MAX_VOICES)),
+        });
+        let stream = open_stream(shared.clone(), buffer_size)?;
+
+        Ok(Self {
+            shared,
+            _stream: stream,
+        })
+    }
+}
+impl AudioSink for CpalSink {
+    fn set_buzzer(&mut self, on: bool) {
+        self.shared.playing.store(on, Ordering::Relaxed);
+        if on {
+            self.shared.started.store(true, Ordering::Relaxed);
+        }
+    }
+    fn set_patterns(&mut self, patterns: &[Pattern]) {
+        let mut shared = self.shared.patterns.lock().unwrap();
+        shared.clear();
+        shared.extend_from_slice(&patterns[..patterns.len().min(MAX_VOICES)]);
+    }
+    fn set_volume(&mut self, volume: f32) {
+        self.shared.amplitude.store(volume.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+}
+
+
+struct Shared {
+    playing: AtomicBool,
+    /// Set when the buzzer turns on, so beeps shorter than an audio buffer aren't missed
+    started: AtomicBool,
+    min_beep: Duration,
+    /// Bits of an f32, since there is no atomic float type
+    amplitude: AtomicU32,
+    patterns: Mutex<Vec<Pattern>>,
+}
+impl Shared {
+    fn amplitude(&self) -> f32 {
+        f32::from_bits(self.amplitude.load(Ordering::Relaxed))
+    }
+}
+
+
+fn open_stream(shared: Arc<Shared>, buffer_size: Option<u32>) -> Result<Stream, Box<dyn Error>> {
+    let host = ::cpal::default_host();
+    let device = host.default_output_device().ok_or("no output device available")?;
+    let supported = device.default_output_config()?;
+    let format = supported.sample_format();
+    let buffer_size = match (buffer_size, supported.buffer_size()) {
+        (None, _) => BufferSize::Default,
+        (Some(frames), SupportedBufferSize::Range { min, max }) => BufferSize::Fixed(frames.clamp(*min, *max)),
+        (Some(frames), SupportedBufferSize::Unknown) => BufferSize::Fixed(frames),
+    };
+    let mut config: StreamConfig = supported.into();
+    config.buffer_size = buffer_size;
+    if let BufferSize::Fixed(frames) = config.buffer_size {
+        debug!(frames, latency_ms = frames as f32 * 1000.0 / config.sample_rate.0 as f32, "Audio buffer size");
+    }
+
+    let stream = match format {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, shared)?,
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, shared)?,
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, shared)?,
+        format => return Err(format!("unsupported sample format {}", format).into()),
+    };
+    stream.play()?;
+
+    Ok(stream)
+}
+
+fn build_stream<T>(device: &Device, config: &StreamConfig, shared: Arc<Shared>) -> Result<Stream, Box<dyn Error>>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let sample_rate = config.sample_rate.0 as f32;
+    // Position within the tone period, the mixer keeps track of the patterns
+    let mut phase = 0.0;
+    let mut mixer = Mixer::new();
+    // Envelope of the tone between 0.0 and 1.0, and the sample frames it stays on at least
+    let mut gain: f32 = 0.0;
+    let ramp_step = 1.0 / (RAMP_SECONDS * sample_rate);
+    let min_frames = (shared.min_beep.as_secs_f32() * sample_rate) as usize;
+    let mut held = 0;
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let playing = shared.playing.load(Ordering::Relaxed);
+            if shared.started.swap(false, Ordering::Relaxed) {
+                held = min_frames;
+            }
+            let amplitude = shared.amplitude();
+            // Never block the audio thread, keep the last patterns if they are being replaced
+            if let Ok(patterns) = shared.patterns.try_lock() {
+                mixer.set_patterns(&patterns);
+            }
+
+            for frame in data.chunks_mut(channels) {
+                let level = if mixer.is_empty() {
+                    phase = (phase + TONE_FREQUENCY / sample_rate) % 1.0;
+                    if phase < 0.5 { 1.0 } else { -1.0 }
+                }
+                else {
+                    mixer.sample(sample_rate)
+                };
+                let target = if playing || held > 0 { 1.0 } else { 0.0 };
+                held = held.saturating_sub(1);
+                gain += (target - gain).clamp(-ramp_step, ramp_step);
+                let value = level * amplitude * gain;
+
+                for sample in frame {
+                    *sample = T::from_sample(value);
+                }
+            }
+        },
+        |e| error!("Audio output error: {}", e),
+        None,
+    )?;
+
+    Ok(stream)
+}
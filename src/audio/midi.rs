@@ -0,0 +1,62 @@
+use std::{fs::{File, OpenOptions}, io::{self, Write}, path::Path};
+use tracing::error;
+use super::{AudioSink, Pattern};
+
+
+/// Plays the buzzer as a MIDI note on a raw MIDI device, like `/dev/midi1` or `/dev/snd/midiC1D0`.
+/// XO-Chip patterns can't be expressed as a note, so they play as the plain note.
+pub struct MidiSink {
+    device: File,
+    /// Channel 0 to 15, shown as 1 to 16 by most instruments
+    channel: u8,
+    note: u8,
+    velocity: u8,
+    sounding: bool,
+}
+impl MidiSink {
+    pub fn open(path: impl AsRef<Path>, channel: u8, note: u8) -> io::Result<Self> {
+        let device = OpenOptions::new().write(true).open(path)?;
+        Ok(Self {
+            device,
+            channel: channel & 0x0F,
+            note: note & 0x7F,
+            velocity: 0x7F,
+            sounding: false,
+        })
+    }
+
+    fn send(&mut self, message: [u8; 3]) {
+        if let Err(e) = self.device.write_all(&message).and_then(|()| self.device.flush()) {
+            error!("Could not write to MIDI device: {}", e);
+        }
+    }
+}
+impl AudioSink for MidiSink {
+    fn set_buzzer(&mut self, on: bool) {
+        // A note with velocity 0 would count as note off, so muted buzzers don't sound at all
+        let on = on && self.velocity > 0;
+        if on == self.sounding {
+            return;
+        }
+        self.sounding = on;
+        if on {
+            self.send([0x90 | self.channel, self.note, self.velocity]);
+        }
+        else {
+            self.send([0x80 | self.channel, self.note, 0]);
+        }
+    }
+    fn set_patterns(&mut self, _patterns: &[Pattern]) {}
+    fn set_volume(&mut self, volume: f32) {
+        self.velocity = (volume.clamp(0.0, 1.0) * 127.0).round() as u8;
+        // Muting mid-beep releases the note rather than leaving it held
+        if self.velocity == 0 {
+            self.set_buzzer(false);
+        }
+    }
+}
+impl Drop for MidiSink {
+    fn drop(&mut self) {
+        self.set_buzzer(false);
+    }
+}
@@ -0,0 +1,10 @@
+use super::{AudioSink, Pattern};
+
+
+/// Discards all sound, for frontends without audio output
+pub struct NullSink;
+impl AudioSink for NullSink {
+    fn set_buzzer(&mut self, _on: bool) {}
+    fn set_patterns(&mut self, _patterns: &[Pattern]) {}
+    fn set_volume(&mut self, _volume: f32) {}
+}
@@ -0,0 +1,114 @@
+use wasm_bindgen::JsValue;
+use web_sys::{AudioBufferSourceNode, AudioContext, GainNode, OscillatorNode, OscillatorType};
+use super::{AudioSink, Pattern, MAX_VOICES, TONE_FREQUENCY};
+
+/// The range of sample rates browsers accept for audio buffers
+const MIN_BUFFER_RATE: f32 = 3000.0;
+const MAX_BUFFER_RATE: f32 = 768000.0;
+/// Time constant of the gain's approach to a new level, so starting and stopping the tone doesn't click
+const RAMP_SECONDS: f64 = 0.001;
+
+
+/// Plays the buzzer through the WebAudio API of the browser
+pub struct WebAudioSink {
+    context: AudioContext,
+    gain: GainNode,
+    oscillator: OscillatorNode,
+    /// Play the patterns instead of the oscillator while there are any,
+    /// each through a gain that shares the volume among the voices
+    sources: Vec<(AudioBufferSourceNode, GainNode)>,
+    volume: f32,
+    on: bool,
+}
+impl WebAudioSink {
+    pub fn new() -> Result<Self, JsValue> {
+        let context = AudioContext::new()?;
+        let gain = context.create_gain()?;
+        gain.gain().set_value(0.0);
+        gain.connect_with_audio_node(&context.destination())?;
+
+        let oscillator = context.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Square);
+        oscillator.frequency().set_value(TONE_FREQUENCY);
+        oscillator.connect_with_audio_node(&gain)?;
+        oscillator.start()?;
+
+        Ok(Self {
+            context,
+            gain,
+            oscillator,
+            sources: Vec::new(),
+            volume: 0.0,
+            on: false,
+        })
+    }
+
+    fn update_gain(&self) {
+        let gain = if self.on { self.volume } else { 0.0 };
+        let now = self.context.current_time();
+        if self.gain.gain().set_target_at_time(gain, now, RAMP_SECONDS).is_err() {
+            self.gain.gain().set_value(gain);
+        }
+    }
+
+    fn play_patterns(&mut self, patterns: &[Pattern]) -> Result<(), JsValue> {
+        for (source, gain) in self.sources.drain(..) {
+            #[allow(deprecated)]
+            source.stop()?;
+            source.disconnect()?;
+            gain.disconnect()?;
+        }
+
+        let patterns = &patterns[..patterns.len().min(MAX_VOICES)];
+        if patterns.is_empty() {
+            self.oscillator.connect_with_audio_node(&self.gain)?;
+            return Ok(());
+        }
+        self.oscillator.disconnect()?;
+
+        for pattern in patterns {
+            let source = self.pattern_source(pattern)?;
+            let gain = self.context.create_gain()?;
+            gain.gain().set_value(1.0 / patterns.len() as f32);
+            gain.connect_with_audio_node(&self.gain)?;
+            source.connect_with_audio_node(&gain)?;
+            source.start()?;
+            self.sources.push((source, gain));
+        }
+        Ok(())
+    }
+
+    /// A source looping `pattern`, not yet connected or started
+    fn pattern_source(&self, pattern: &Pattern) -> Result<AudioBufferSourceNode, JsValue> {
+        let rate = pattern.rate.clamp(MIN_BUFFER_RATE, MAX_BUFFER_RATE);
+        let buffer = self.context.create_buffer(1, Pattern::LENGTH as u32, rate)?;
+        let samples: Vec<f32> = (0..Pattern::LENGTH)
+            .map(|i| if pattern.sample(i) { 1.0 } else { -1.0 })
+            .collect();
+        buffer.copy_to_channel(&samples, 0)?;
+
+        let source = self.context.create_buffer_source()?;
+        source.set_buffer(Some(&buffer));
+        source.set_loop(true);
+        Ok(source)
+    }
+}
+impl AudioSink for WebAudioSink {
+    fn set_buzzer(&mut self, on: bool) {
+        if on {
+            // Browsers keep the context suspended until the page was interacted with
+            let _ = self.context.resume();
+        }
+        self.on = on;
+        self.update_gain();
+    }
+    fn set_patterns(&mut self, patterns: &[Pattern]) {
+        if let Err(e) = self.play_patterns(patterns) {
+            web_sys::console::error_2(&"Could not play audio patterns:".into(), &e);
+        }
+    }
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        self.update_gain();
+    }
+}
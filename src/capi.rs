@@ -0,0 +1,171 @@
+//! A C ABI to the emulator core, for embedding chippy in C and C++ frontends.
+//!
+//! The declarations are in `include/chippy.h`. Every function takes the pointer returned by
+//! `chippy_new`, which must not be used after `chippy_free`.
+
+use std::{ffi::c_int, panic::{self, AssertUnwindSafe}, slice};
+use crate::emulator::{comp_mode::{CompatibilityMode, CompBuilder}, keys::Keys, machine::Machine, palette::Palette, screen::{HEIGHT, PACKED_SIZE, WIDTH}};
+
+
+/// A machine together with the state a frontend would otherwise have to keep
+pub struct Chippy {
+    machine: Machine,
+    comp: CompatibilityMode,
+    keys: Keys,
+    palette: Palette,
+    /// The screen as RGBA bytes, updated by `chippy_framebuffer`
+    frame: Vec<u8>,
+}
+
+
+/// Creates a machine with the default quirks and no program
+#[no_mangle]
+pub extern "C" fn chippy_new(seed: u64) -> *mut Chippy {
+    let comp = CompBuilder::new().build();
+    let mut machine = Machine::new(seed);
+    machine.init_instruction_pointer(comp.program_start);
+    machine.load_sprites();
+
+    let chippy = Chippy {
+        machine,
+        comp,
+        keys: Keys::new(),
+        palette: Palette::default(),
+        frame: vec![0; WIDTH * HEIGHT * 4],
+    };
+    Box::into_raw(Box::new(chippy))
+}
+
+/// Frees a machine created by `chippy_new`. Passing null does nothing.
+///
+/// # Safety
+/// `chippy` must be null or a pointer returned by `chippy_new` that wasn't freed yet
+#[no_mangle]
+pub unsafe extern "C" fn chippy_free(chippy: *mut Chippy) {
+    if !chippy.is_null() {
+        drop(Box::from_raw(chippy));
+    }
+}
+
+/// Loads `length` bytes at `rom` as the program and restarts it.
+/// Returns 0 on success and -1 if the program doesn't fit in memory.
+///
+/// # Safety
+/// `chippy` must be a live machine and `rom` must point to `length` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chippy_load_rom(chippy: *mut Chippy, rom: *const u8, length: usize) -> c_int {
+    let chippy = &mut *chippy;
+    let rom = if length == 0 { &[] } else { slice::from_raw_parts(rom, length) };
+    match chippy.machine.load_program(rom, chippy.comp.program_start as usize) {
+        Ok(()) => {
+            chippy.machine.reset();
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Selects the quirks of a preset, 0 for the defaults, 1 for CHIP-8, 2 for SuperChip and 3 for ETI-660.
+/// A preset loading programs elsewhere moves the loaded program there and restarts it.
+/// Returns -1 for an unknown preset.
+///
+/// # Safety
+/// `chippy` must be a live machine
+#[no_mangle]
+pub unsafe extern "C" fn chippy_set_preset(chippy: *mut Chippy, preset: c_int) -> c_int {
+    let builder = match preset {
+        0 => CompBuilder::new(),
+        1 => CompBuilder::chip8_preset(),
+        2 => CompBuilder::superchip_preset(),
+        3 => CompBuilder::eti660_preset(),
+        _ => return -1,
+    };
+    let chippy = &mut *chippy;
+    let comp = builder.build();
+    if comp.program_start != chippy.comp.program_start {
+        let program = chippy.machine.program().to_vec();
+        if chippy.machine.load_program(&program, comp.program_start as usize).is_err() {
+            return -1;
+        }
+        chippy.machine.reset();
+    }
+    chippy.comp = comp;
+    0
+}
+
+/// Executes up to `instructions` instructions, then counts the timers down once.
+/// Meant to be called 60 times a second. Returns 1 if the screen changed, 0 otherwise,
+/// and -1 if the program crashed the interpreter, for example with an illegal instruction.
+///
+/// # Safety
+/// `chippy` must be a live machine
+#[no_mangle]
+pub unsafe extern "C" fn chippy_step(chippy: *mut Chippy, instructions: usize) -> c_int {
+    let chippy = &mut *chippy;
+    // Panics must not unwind into the caller's frames
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        chippy.machine.run_frame(instructions, &chippy.comp, &chippy.keys)
+    }));
+    match result {
+        Ok(result) => result.screen_changed as c_int,
+        Err(_) => -1,
+    }
+}
+
+/// Presses (`pressed` non-zero) or releases keypad key `key`, which must be below 16
+///
+/// # Safety
+/// `chippy` must be a live machine
+#[no_mangle]
+pub unsafe extern "C" fn chippy_set_key(chippy: *mut Chippy, key: u8, pressed: c_int) {
+    if key < 16 {
+        (*chippy).keys.set_key(key, pressed != 0);
+    }
+}
+
+/// The screen as `CHIPPY_WIDTH` by `CHIPPY_HEIGHT` RGBA pixels. The buffer stays valid until
+/// the next call on the same machine.
+///
+/// # Safety
+/// `chippy` must be a live machine
+#[no_mangle]
+pub unsafe extern "C" fn chippy_framebuffer(chippy: *mut Chippy) -> *const u8 {
+    let chippy = &mut *chippy;
+    chippy.machine.screen().render_to_pixel_buffer(&mut chippy.frame, &chippy.palette);
+    chippy.frame.as_ptr()
+}
+
+/// Writes the screen packed to one bit per pixel into the `CHIPPY_PACKED_SIZE` bytes at `buffer`,
+/// in one of the `CHIPPY_LAYOUT_*` layouts. Returns 0 on success and -1 for an unknown layout.
+///
+/// # Safety
+/// `chippy` must be a live machine and `buffer` must point to `CHIPPY_PACKED_SIZE` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chippy_framebuffer_packed(chippy: *const Chippy, layout: c_int, buffer: *mut u8) -> c_int {
+    let screen = (*chippy).machine.screen();
+    let buffer = &mut *(buffer as *mut [u8; PACKED_SIZE]);
+    match layout {
+        0 => screen.pack_rows(buffer),
+        1 => screen.pack_pages(buffer),
+        _ => return -1,
+    }
+    0
+}
+
+/// 1 while the buzzer should sound, 0 otherwise
+///
+/// # Safety
+/// `chippy` must be null or a live machine
+#[no_mangle]
+pub unsafe extern "C" fn chippy_sound_playing(chippy: *const Chippy) -> c_int {
+    chippy.as_ref().is_some_and(|c| c.machine.is_sound_playing()) as c_int
+}
+
+/// 1 once the program ran a SuperChip exit instruction, 0 otherwise
+///
+/// # Safety
+/// `chippy` must be null or a live machine
+#[no_mangle]
+pub unsafe extern "C" fn chippy_has_exited(chippy: *const Chippy) -> c_int {
+    chippy.as_ref().is_some_and(|c| c.machine.has_exited()) as c_int
+}
@@ -0,0 +1,106 @@
+use crate::{debugger::Debugger, emulator::machine::Machine};
+
+
+/// Classic cheat functionality: RAM search and frozen addresses
+pub struct Cheats {
+    pub search: Option<Search>,
+    frozen: Vec<Freeze>,
+}
+impl Cheats {
+    pub fn new() -> Self {
+        Self {
+            search: None,
+            frozen: Vec::new(),
+        }
+    }
+
+    /// Starts a new RAM search with every address as a candidate
+    pub fn start_search(&mut self, machine: &Machine) {
+        self.search = Some(Search::new(machine));
+    }
+
+    /// Keeps `address` at `value`, replacing an earlier freeze of the same address
+    pub fn freeze(&mut self, address: u16, value: u8) {
+        self.unfreeze(address);
+        self.frozen.push(Freeze { address, value });
+    }
+    pub fn unfreeze(&mut self, address: u16) {
+        self.frozen.retain(|f| f.address != address);
+    }
+    pub fn frozen(&self) -> &[Freeze] {
+        &self.frozen
+    }
+
+    /// Rewrites every frozen address that the program changed, called once per frame
+    pub fn apply(&self, machine: &mut Machine, debugger: &mut Debugger) {
+        for freeze in &self.frozen {
+            if machine.memory()[freeze.address as usize] != freeze.value {
+                debugger.poke(machine, freeze.address, freeze.value);
+            }
+        }
+    }
+}
+
+
+/// Narrows down which addresses hold some value by comparing memory over time
+pub struct Search {
+    /// Addresses that matched every filter so far
+    candidates: Vec<u16>,
+    /// The memory contents when the last filter was applied
+    previous: Vec<u8>,
+}
+impl Search {
+    fn new(machine: &Machine) -> Self {
+        let memory = machine.memory();
+        Self {
+            candidates: (0..memory.len()).map(|address| address as u16).collect(),
+            previous: memory.to_vec(),
+        }
+    }
+
+    pub fn filter(&mut self, machine: &Machine, filter: Filter) {
+        let memory = machine.memory();
+        let previous = &self.previous;
+        self.candidates.retain(|&address| {
+            let address = address as usize;
+            filter.matches(previous[address], memory[address])
+        });
+        self.previous = memory.to_vec();
+    }
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// The byte currently holds this value
+    Equal(u8),
+    /// The byte changed since the last filter
+    Changed,
+    /// The byte is the same as at the last filter
+    Unchanged,
+    /// The byte grew since the last filter
+    Increased,
+    /// The byte shrank since the last filter
+    Decreased,
+}
+impl Filter {
+    fn matches(self, previous: u8, current: u8) -> bool {
+        match self {
+            Filter::Equal(value) => current == value,
+            Filter::Changed => current != previous,
+            Filter::Unchanged => current == previous,
+            Filter::Increased => current > previous,
+            Filter::Decreased => current < previous,
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Freeze {
+    pub address: u16,
+    pub value: u8,
+}
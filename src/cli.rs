@@ -0,0 +1,226 @@
+use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing::{error, Level};
+use crate::{debugger::symbols::Symbols, screenshots::Screenshots, trace::TraceFormat};
+
+const DEFAULT_PROGRAM: &str = "./programs/rockto.ch8";
+
+
+#[derive(Debug, Parser)]
+#[command(version, about = "A CHIP-8, SuperChip and XO-Chip emulator", args_conflicts_with_subcommands = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The program to run
+    #[arg(default_value = DEFAULT_PROGRAM)]
+    pub program: PathBuf,
+
+    /// Apply an IPS or offset+bytes patch file to the program, can be given multiple times
+    #[arg(long, value_name = "FILE")]
+    pub patch: Vec<PathBuf>,
+
+    /// Seed of the random number generator, random by default, or 0 with --deterministic
+    #[arg(long, value_name = "N")]
+    pub seed: Option<u64>,
+    /// Run exactly one timer tick per emulated frame, so runs with the same seed and inputs
+    /// behave identically. Frames that fall behind are dropped instead of catching up on timers.
+    #[arg(long)]
+    pub deterministic: bool,
+
+    /// Run without a window as fast as possible, exiting with V0 as exit code once the
+    /// program executes the exit instruction, or with code 101 if emulation fails
+    #[arg(long)]
+    pub headless: bool,
+    /// Stop a headless run after this many frames, exiting with code 124
+    #[arg(long, value_name = "N", requires = "headless")]
+    pub frames: Option<u64>,
+    /// Stop a headless run once the program writes to this address, exiting with the written
+    /// value as exit code
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_address, requires = "headless")]
+    pub result_address: Option<u16>,
+    /// Print the screen when a headless run stops
+    #[arg(long, requires = "headless")]
+    pub dump_screen: bool,
+    /// Format of the dumped screen, the image formats are written to stdout as binary
+    #[arg(long, value_enum, default_value_t = DumpFormat::Text, requires = "dump_screen")]
+    pub dump_format: DumpFormat,
+    /// Print the machine state as JSON when a headless run stops
+    #[arg(long, requires = "headless")]
+    pub dump_state: bool,
+
+    /// Accept remote viewers on this address, like 0.0.0.0:7530, who see the screen and can press keys
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "headless")]
+    pub host: Option<String>,
+    /// Accept key presses as OSC or text datagrams on this UDP address, like 0.0.0.0:9000
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "headless")]
+    pub udp_input: Option<String>,
+
+    /// Compile straight-line code to native code in a headless run.
+    /// Compiled instructions don't show up in traces or crash reports.
+    #[cfg(feature = "jit")]
+    #[arg(long, requires = "headless")]
+    pub jit: bool,
+
+    /// Write a trace of every executed instruction to this file
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = TraceFormat::Csv)]
+    pub trace_format: TraceFormat,
+
+    /// Write statistics about the run to this file on exit, as a CSV row if it ends in .csv, otherwise as JSON
+    #[arg(long, value_name = "FILE")]
+    pub stats: Option<PathBuf>,
+
+    /// Write which bytes of the ROM were executed to this file on exit, as a coloured disassembly if it ends in .html, otherwise as text
+    #[arg(long, value_name = "FILE")]
+    pub coverage: Option<PathBuf>,
+
+    /// Keep the FX75/FX85 flags in this file instead of one per ROM in ./flags. Headless runs
+    /// only keep flags with this option.
+    #[arg(long, value_name = "FILE")]
+    pub flags: Option<PathBuf>,
+
+    /// Name addresses in backtraces of emulation errors after the labels in this file,
+    /// one hexadecimal address and name per line
+    #[arg(long, value_name = "FILE")]
+    pub symbols: Option<PathBuf>,
+
+    /// Save a screenshot every N frames, counted from the start of the run
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), requires = "screenshot_dir")]
+    pub screenshot_every: Option<u64>,
+    /// Directory the screenshots are saved to as numbered PPM images, created if missing
+    #[arg(long, value_name = "DIR", requires = "screenshot_every")]
+    pub screenshot_dir: Option<PathBuf>,
+
+    /// Write how many instructions ran in every call stack to this file on exit, as folded
+    /// stacks for flame graph tools. Instructions compiled by --jit are not counted.
+    #[arg(long, value_name = "FILE")]
+    pub profile: Option<PathBuf>,
+
+    /// Most verbose level of log messages written to stderr: error, warn, info, debug or trace
+    #[arg(long, value_name = "LEVEL", default_value_t = Level::WARN, global = true)]
+    pub log_level: Level,
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum DumpFormat {
+    /// One character per pixel
+    Text,
+    /// Unicode half blocks, 128x32 characters
+    HalfBlock,
+    /// Unicode braille patterns, 64x16 characters
+    Braille,
+    /// Black and white PBM image
+    Pbm,
+    /// PPM image in the palette's colours
+    Ppm,
+}
+
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the program in an interactive machine monitor on the terminal
+    Monitor {
+        program: PathBuf,
+        /// Apply an IPS or offset+bytes patch file to the program, can be given multiple times
+        #[arg(long, value_name = "FILE")]
+        patch: Vec<PathBuf>,
+    },
+    /// Show the screen of a chippy started with --host and play along
+    View {
+        /// Address of the host, like 192.168.0.10:7530
+        address: String,
+    },
+    /// Run several programs at once, tiled in one window
+    Mosaic {
+        #[arg(required = true)]
+        programs: Vec<PathBuf>,
+        /// Apply an IPS or offset+bytes patch file to every program, can be given multiple times
+        #[arg(long, value_name = "FILE")]
+        patch: Vec<PathBuf>,
+        /// Run every program once with each quirk preset instead of with its usual settings
+        #[arg(long)]
+        compare_presets: bool,
+        /// Number of tiles per row, by default the grid is about square
+        #[arg(long, value_name = "N")]
+        columns: Option<usize>,
+    },
+    /// Translate the program into standalone Rust source running on chippy's screen and keys
+    Transpile {
+        program: PathBuf,
+        /// Apply an IPS or offset+bytes patch file to the program, can be given multiple times
+        #[arg(long, value_name = "FILE")]
+        patch: Vec<PathBuf>,
+        /// Write the source to this file instead of stdout
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Disassemble the program, telling code from data and labelling jump, call and sprite targets
+    Disasm {
+        program: PathBuf,
+        /// Apply an IPS or offset+bytes patch file to the program, can be given multiple times
+        #[arg(long, value_name = "FILE")]
+        patch: Vec<PathBuf>,
+        /// Write a navigable HTML report with cross-reference links and sprite previews
+        #[arg(long)]
+        html: bool,
+        /// Show how often every instruction was executed in this trace, as written by --trace
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+        /// Write the disassembly to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Write the control-flow graph of the program as a Graphviz file
+    Cfg {
+        program: PathBuf,
+        /// Apply an IPS or offset+bytes patch file to the program, can be given multiple times
+        #[arg(long, value_name = "FILE")]
+        patch: Vec<PathBuf>,
+        /// Write the graph to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+
+impl Args {
+    /// The seed to start the machine with, if the arguments fix one
+    pub fn machine_seed(&self) -> Option<u64> {
+        self.seed.or(self.deterministic.then_some(0))
+    }
+
+    /// Loads the symbol file, without symbols if there is none or it can't be read
+    pub fn symbols(&self) -> Symbols {
+        let Some(path) = &self.symbols else {
+            return Symbols::default();
+        };
+        Symbols::load(path).unwrap_or_else(|e| {
+            error!("Could not load symbols from {}: {}", path.display(), e);
+            Symbols::default()
+        })
+    }
+
+    /// Creates the screenshot directory if screenshots were asked for
+    pub fn screenshots(&self) -> Option<Screenshots> {
+        let (Some(dir), Some(every)) = (&self.screenshot_dir, self.screenshot_every) else {
+            return None;
+        };
+        match Screenshots::new(dir, every) {
+            Ok(screenshots) => Some(screenshots),
+            Err(e) => {
+                error!("Could not create screenshot directory {}: {}", dir.display(), e);
+                None
+            }
+        }
+    }
+}
+
+
+/// Parses a hexadecimal address, with or without `0x` prefix
+fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|e| e.to_string())
+}
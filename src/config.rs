@@ -0,0 +1,255 @@
+use std::{fs, io, path::Path};
+use serde::{Serialize, Deserialize};
+use tracing::warn;
+use crate::{emulator::{palette::{self, Palette}, comp_mode::*}, hotkeys::Hotkeys, keymap::Keymap, renderer::{ScaleFilter, ScaleMode}, sound::AudioOutput};
+
+pub const CONFIG_PATH: &str = "./chippy.toml";
+pub const CUSTOM_PALETTE: &str = "custom";
+
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Output volume between 0.0 and 1.0
+    pub volume: f32,
+    pub muted: bool,
+    /// Where the buzzer is played, `{ kind = "speaker" }`, `{ kind = "midi", device = "/dev/midi1" }`
+    /// or `{ kind = "command", on = "...", off = "..." }`.
+    /// The speaker takes an optional `buffer_size` in sample frames to trade crackle for latency,
+    /// and `min_beep_ms`, the shortest beep it plays.
+    pub audio_output: AudioOutput,
+    /// Pause emulation and audio while the window is unfocused
+    pub pause_on_focus_loss: bool,
+    /// How many instructions are executed per 60 Hz frame
+    pub instructions_per_frame: usize,
+    /// How many 60 Hz frames may run without being rendered when the host falls behind,
+    /// 0 runs exactly one frame per rendered one
+    pub frame_skip: u32,
+    pub quirks: Quirks,
+    /// Writes to this address are printed as text output, if set
+    pub console_address: Option<u16>,
+    pub scale_filter: ScaleFilter,
+    pub scale_mode: ScaleMode,
+    /// Key of a palette preset, or "custom" to use `custom_palette`
+    pub palette: String,
+    pub custom_palette: Option<Palette>,
+    pub keymap: Keymap,
+    pub hotkeys: Hotkeys,
+    /// Open the debugger in a window of its own instead of on top of the display
+    pub separate_debugger_window: bool,
+    /// Minutes of rewind history kept in temporary files on top of the last minute, which is kept
+    /// in memory. 0 keeps only that minute.
+    pub rewind_minutes: usize,
+    /// Where the window was when chippy last exited
+    pub window: WindowGeometry,
+}
+impl Config {
+    /// Loads the config at `path`, falling back to the defaults if it is missing or malformed.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                warn!("Could not read config file {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+
+        match toml::from_str(&text) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Could not parse config file {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text = toml::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    /// Keys of all selectable palettes, in the order they are cycled through
+    pub fn palette_keys(&self) -> Vec<&str> {
+        let mut keys: Vec<_> = palette::PRESETS.iter().map(|p| p.key).collect();
+        if self.custom_palette.is_some() {
+            keys.push(CUSTOM_PALETTE);
+        }
+        keys
+    }
+    /// The display name and colours of the selected palette
+    pub fn palette(&self) -> (&str, Palette) {
+        if let (CUSTOM_PALETTE, Some(custom)) = (self.palette.as_str(), self.custom_palette) {
+            return ("Custom", custom);
+        }
+
+        let preset = palette::preset(&self.palette).unwrap_or(&palette::PRESETS[0]);
+        (preset.name, preset.palette)
+    }
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            volume: 0.5,
+            muted: false,
+            audio_output: AudioOutput::default(),
+            pause_on_focus_loss: true,
+            instructions_per_frame: 10,
+            frame_skip: 0,
+            quirks: Quirks::default(),
+            console_address: None,
+            scale_filter: ScaleFilter::Nearest,
+            scale_mode: ScaleMode::PixelPerfect,
+            palette: palette::PRESETS[0].key.to_string(),
+            custom_palette: None,
+            keymap: Keymap::default(),
+            hotkeys: Hotkeys::default(),
+            separate_debugger_window: false,
+            rewind_minutes: 0,
+            window: WindowGeometry::default(),
+        }
+    }
+}
+
+
+/// Size, position and fullscreen state of the main window, restored on startup
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowGeometry {
+    /// Inner size in logical pixels, the default size if unset
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    /// Position of the top left corner in physical pixels, chosen by the system if unset
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub fullscreen: bool,
+}
+
+
+/// A compatibility preset with optional overrides for individual quirks
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    pub preset: Option<QuirkPreset>,
+    pub shift: Option<ShiftMode>,
+    pub load_store: Option<LoadStoreMode>,
+    pub address_space: Option<AddressSpace>,
+    pub allowed_instructions: Option<AllowedInstructions>,
+    pub jump_mode: Option<RelativeJumpMode>,
+    pub collisions: Option<CollisionEnumeration>,
+    pub lores_scroll: Option<LoresScrollMode>,
+    pub large_sprites: Option<LargeSpriteMode>,
+    pub add_i_overflow: Option<AddIOverflow>,
+    pub random: Option<RandomMode>,
+    pub draw_origin: Option<DrawOrigin>,
+    /// Wrap or clip sprites at the left and right edges
+    pub horizontal_edge: Option<SpriteEdge>,
+    /// Wrap or clip sprites at the top and bottom edges
+    pub vertical_edge: Option<SpriteEdge>,
+    /// Execute the 01XY and 0200 debug print pseudo-instructions
+    pub debug_print: Option<bool>,
+    /// XO-Chip audio patterns played at once, 1 to 4
+    pub audio_voices: Option<u8>,
+    /// Lores resolution, "64x32", "64x48" or "64x64"
+    pub lores_size: Option<LoresSize>,
+    /// Address programs are loaded at, like 0x600 for ETI-660 programs
+    pub program_start: Option<u16>,
+}
+impl Quirks {
+    /// Layers `other` on top of these quirks.
+    /// A preset in `other` replaces everything set here.
+    pub fn merged(&self, other: &Quirks) -> Quirks {
+        let base = if other.preset.is_some() { &Quirks::default() } else { self };
+        Quirks {
+            preset: other.preset.or(base.preset),
+            shift: other.shift.or(base.shift),
+            load_store: other.load_store.or(base.load_store),
+            address_space: other.address_space.or(base.address_space),
+            allowed_instructions: other.allowed_instructions.or(base.allowed_instructions),
+            jump_mode: other.jump_mode.or(base.jump_mode),
+            collisions: other.collisions.or(base.collisions),
+            lores_scroll: other.lores_scroll.or(base.lores_scroll),
+            large_sprites: other.large_sprites.or(base.large_sprites),
+            add_i_overflow: other.add_i_overflow.or(base.add_i_overflow),
+            random: other.random.or(base.random),
+            draw_origin: other.draw_origin.or(base.draw_origin),
+            horizontal_edge: other.horizontal_edge.or(base.horizontal_edge),
+            vertical_edge: other.vertical_edge.or(base.vertical_edge),
+            debug_print: other.debug_print.or(base.debug_print),
+            audio_voices: other.audio_voices.or(base.audio_voices),
+            lores_size: other.lores_size.or(base.lores_size),
+            program_start: other.program_start.or(base.program_start),
+        }
+    }
+
+    pub fn build(&self) -> CompatibilityMode {
+        let mut builder = match self.preset.unwrap_or(QuirkPreset::SuperChip) {
+            QuirkPreset::Chip8 => CompBuilder::chip8_preset(),
+            QuirkPreset::SuperChip => CompBuilder::superchip_preset(),
+            QuirkPreset::Eti660 => CompBuilder::eti660_preset(),
+        };
+        if let Some(shift) = self.shift {
+            builder = builder.with_shift(shift);
+        }
+        if let Some(load_store) = self.load_store {
+            builder = builder.with_load_store(load_store);
+        }
+        if let Some(space) = self.address_space {
+            builder = builder.with_address_space(space);
+        }
+        if let Some(allowed) = self.allowed_instructions {
+            builder = builder.with_allowed_instructions(allowed);
+        }
+        if let Some(mode) = self.jump_mode {
+            builder = builder.with_jump_mode(mode);
+        }
+        if let Some(collisions) = self.collisions {
+            builder = builder.with_collisions(collisions);
+        }
+        if let Some(mode) = self.lores_scroll {
+            builder = builder.with_lores_scroll(mode);
+        }
+        if let Some(mode) = self.large_sprites {
+            builder = builder.with_large_sprites(mode);
+        }
+        if let Some(mode) = self.add_i_overflow {
+            builder = builder.with_add_i_overflow(mode);
+        }
+        if let Some(mode) = self.random {
+            builder = builder.with_random(mode);
+        }
+        if let Some(mode) = self.draw_origin {
+            builder = builder.with_draw_origin(mode);
+        }
+        if let Some(edge) = self.horizontal_edge {
+            builder = builder.with_horizontal_edge(edge);
+        }
+        if let Some(edge) = self.vertical_edge {
+            builder = builder.with_vertical_edge(edge);
+        }
+        if let Some(enabled) = self.debug_print {
+            builder = builder.with_debug_print(enabled);
+        }
+        if let Some(voices) = self.audio_voices {
+            builder = builder.with_audio_voices(voices);
+        }
+        if let Some(size) = self.lores_size {
+            builder = builder.with_lores_size(size);
+        }
+        if let Some(start) = self.program_start {
+            builder = builder.with_program_start(start);
+        }
+        builder.build()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuirkPreset {
+    /// Behave like the original Chip8 interpreter
+    Chip8,
+    /// Behave like SuperChip 1.1
+    SuperChip,
+    /// Behave like the ETI-660, with a 64x48 screen and programs at 0x600
+    Eti660,
+}
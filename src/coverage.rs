@@ -0,0 +1,125 @@
+//! Which bytes of the ROM were executed during a session, to find untested branches and dead code.
+//!
+//! The report is plain text, or an HTML disassembly with executed and unexecuted code coloured
+//! if the file ends in `.html`. Bytes count as executed if they belong to an instruction that
+//! ran, skipped instructions don't.
+
+use std::{fmt::Write as _, fs, io, path::Path};
+use crate::emulator::instruction::disassemble;
+
+
+/// Collects executed addresses over a whole session
+pub struct Coverage {
+    /// Whether an instruction starting at each address was executed
+    executed: Vec<bool>,
+}
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            executed: vec![false; 1 << 16],
+        }
+    }
+
+    /// Forgets what was executed, when a different ROM is loaded
+    pub fn clear(&mut self) {
+        self.executed.fill(false);
+    }
+    pub fn record(&mut self, addresses: &[u16]) {
+        for &address in addresses {
+            self.executed[address as usize] = true;
+        }
+    }
+
+    /// Writes the coverage of `program`, loaded at `start`, as HTML or text depending on the extension
+    pub fn write(&self, path: &Path, rom: &Path, program: &[u8], start: usize) -> io::Result<()> {
+        let lines = self.lines(program, start);
+        let report = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("html")) {
+            self.html(rom, program, start, &lines)
+        }
+        else {
+            self.text(rom, program, start, &lines)
+        };
+        fs::write(path, report)
+    }
+
+    /// Whether the byte at `address` belongs to an executed instruction
+    fn covers(&self, address: usize) -> bool {
+        self.executed[address] || address.checked_sub(1).is_some_and(|previous| self.executed[previous])
+    }
+
+    fn summary(&self, rom: &Path, program: &[u8], start: usize) -> String {
+        let covered = (start..start + program.len()).filter(|&a| self.covers(a)).count();
+        let percent = if program.is_empty() { 0.0 } else { covered as f64 * 100.0 / program.len() as f64 };
+        format!("Coverage of {}: {} of {} bytes executed ({:.1}%)", rom.display(), covered, program.len(), percent)
+    }
+
+    /// The program disassembled as executed, following the executed instructions where they
+    /// don't line up with the linear disassembly
+    fn lines(&self, program: &[u8], start: usize) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < program.len() {
+            let address = start + offset;
+            let (text, length) = if !self.executed[address] && self.executed.get(address + 1) == Some(&true) {
+                (format!("DB 0x{:02X}", program[offset]), 1)
+            }
+            else {
+                disassemble(&program[offset..])
+            };
+            lines.push(Line { address, text, executed: self.executed[address] });
+            offset += length as usize;
+        }
+        lines
+    }
+
+    fn text(&self, rom: &Path, program: &[u8], start: usize, lines: &[Line]) -> String {
+        let mut report = self.summary(rom, program, start);
+        report.push_str("\n\nNever executed:\n");
+        let mut address = start;
+        let end = start + program.len();
+        while address < end {
+            if self.covers(address) {
+                address += 1;
+                continue;
+            }
+            let first = address;
+            while address < end && !self.covers(address) {
+                address += 1;
+            }
+            writeln!(report, "  {:03X}-{:03X} ({} bytes)", first, address - 1, address - first).unwrap();
+        }
+
+        report.push('\n');
+        for line in lines {
+            let marker = if line.executed { '+' } else { '-' };
+            writeln!(report, "{} {:03X}  {}", marker, line.address, line.text).unwrap();
+        }
+        report
+    }
+
+    fn html(&self, rom: &Path, program: &[u8], start: usize, lines: &[Line]) -> String {
+        let mut body = String::new();
+        for line in lines {
+            let class = if line.executed { "executed" } else { "unexecuted" };
+            writeln!(body, "<span class=\"{}\">{:03X}  {}</span>", class, line.address, escape_html(&line.text)).unwrap();
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+            .executed {{ background: #c8f0c8; }}\n.unexecuted {{ background: #f4c8c8; }}\n\
+            span {{ display: block; }}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<pre>\n{body}</pre>\n</body>\n</html>\n",
+            title = escape_html(&self.summary(rom, program, start)),
+        )
+    }
+}
+
+
+struct Line {
+    address: usize,
+    text: String,
+    executed: bool,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,154 @@
+//! Crash reports for programs that make the emulator core fail.
+//!
+//! The core panics on anything it cannot emulate, so frontends run frames through
+//! [`run_frame`], which catches the panic and writes the machine state to a report file.
+//! [`install_panic_hook`] has to be called once at startup for reports to say where the panic was raised.
+
+use std::{cell::{Cell, RefCell}, fmt::{self, Display, Formatter}, io::{self, BufWriter, Write}, panic::{self, AssertUnwindSafe}, path::PathBuf, sync::Once};
+use tracing::error;
+use crate::{debugger::{Debugger, symbols::Symbols}, emulator::{comp_mode::CompatibilityMode, instruction::disassemble, keys::Keys, machine::{Machine, panic_message}}};
+
+/// How many instructions are disassembled before and after the PC
+const CONTEXT_LINES: usize = 8;
+
+thread_local! {
+    /// Whether this thread is running a frame whose panics end up in a crash report
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// Where the last caught panic on this thread was raised
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+
+/// Replaces the panic hook with one that keeps quiet about panics caught for crash reports,
+/// only noting where they were raised. Panics anywhere else are reported as usual.
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() {
+                LOCATION.set(info.location().map(|l| l.to_string()));
+            }
+            else {
+                default_hook(info);
+            }
+        }));
+    });
+}
+
+
+/// Runs one frame like [`Debugger::run`], writing a crash report if the machine fails
+pub fn run_frame(debugger: &mut Debugger, machine: &mut Machine, comp: &CompatibilityMode, keys: &Keys, budget: usize) -> Result<(), Crash> {
+    run_with(debugger, machine, |debugger, machine| debugger.run(machine, comp, keys, budget))
+}
+
+/// Runs `run` on the machine, writing a crash report if the machine fails
+pub fn run_with(debugger: &mut Debugger, machine: &mut Machine, run: impl FnOnce(&mut Debugger, &mut Machine)) -> Result<(), Crash> {
+    // The report replaces the usual panic output, only the location is kept for it
+    LOCATION.set(None);
+    let catching = CATCHING.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        run(debugger, machine);
+    }));
+    CATCHING.set(catching);
+
+    let Err(payload) = result else {
+        return Ok(());
+    };
+
+    let message = panic_message(payload);
+
+    debugger.pause();
+    let location = LOCATION.take().unwrap_or_else(|| String::from("unknown location"));
+    let backtrace = backtrace(machine, debugger.symbols());
+    let report = match write_report(&message, &location, &backtrace, machine, debugger) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            error!("Could not write crash report: {}", e);
+            None
+        }
+    };
+    Err(Crash { message, report, backtrace })
+}
+
+
+/// An emulation error and where its report was written, if that worked
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Crash {
+    pub message: String,
+    pub report: Option<PathBuf>,
+    /// The PC and the call sites on the stack, innermost first, see [`backtrace`]
+    pub backtrace: Vec<String>,
+}
+impl Display for Crash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Emulation error: {}", self.message)?;
+        if let Some(path) = &self.report {
+            write!(f, " (crash report written to {})", path.display())?;
+        }
+        for frame in &self.backtrace {
+            write!(f, "\n    {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// The instructions before and after the PC with their addresses
+pub fn disassembly_around_pc(machine: &Machine) -> Vec<(usize, String)> {
+    let start = (machine.pc() as usize).saturating_sub(CONTEXT_LINES * 2);
+    (0..=CONTEXT_LINES * 2)
+        .map(|line| start + line * 2)
+        .take_while(|&address| address < machine.memory().len())
+        .map(|address| (address, disassemble(&machine.memory()[address..]).0))
+        .collect()
+}
+
+/// Where the machine is, followed by the calls that led there, innermost first.
+/// Addresses are named after the closest label in `symbols` if there is one.
+pub fn backtrace(machine: &Machine, symbols: &Symbols) -> Vec<String> {
+    let frame = |address: u16| match symbols.describe(address) {
+        Some(name) => format!("{:03X} {}", address, name),
+        None => format!("{:03X}", address),
+    };
+    // The stack holds return addresses, the calls are just before them
+    let calls = machine.stack().iter().rev().map(|&address| format!("called from {}", frame(address.wrapping_sub(2))));
+    std::iter::once(format!("at {}", frame(machine.pc()))).chain(calls).collect()
+}
+
+fn write_report(message: &str, location: &str, backtrace: &[String], machine: &Machine, debugger: &Debugger) -> io::Result<PathBuf> {
+    let (path, file) = crate::create_timestamped_file("chippy-crash")?;
+    let mut out = BufWriter::new(file);
+
+    writeln!(out, "chippy {} crash report", env!("CARGO_PKG_VERSION"))?;
+    writeln!(out, "{}", message)?;
+    writeln!(out, "Raised at {}", location)?;
+
+    writeln!(out, "\n== Registers")?;
+    for (x, value) in machine.registers().iter().enumerate() {
+        write!(out, "V{:X}={:02X}{}", x, value, if x % 8 == 7 { "\n" } else { " " })?;
+    }
+    writeln!(out, "PC={:03X} I={:03X} DT={:02X} ST={:02X}", machine.pc(), machine.i(), machine.delay_timer(), machine.sound_timer())?;
+
+    writeln!(out, "\n== Backtrace, innermost first")?;
+    for frame in backtrace {
+        writeln!(out, "  {}", frame)?;
+    }
+
+    writeln!(out, "\n== Disassembly around PC")?;
+    for (address, text) in disassembly_around_pc(machine) {
+        let marker = if address == machine.pc() as usize { ">" } else { " " };
+        writeln!(out, "{} {:03X}  {}", marker, address, text)?;
+    }
+
+    writeln!(out, "\n== Last executed instructions, oldest first")?;
+    for address in debugger.recent_steps() {
+        writeln!(out, "  {:03X}  {}", address, disassemble(&machine.memory()[address as usize..]).0)?;
+    }
+
+    writeln!(out, "\n== Screen")?;
+    machine.screen().write(&mut out)?;
+
+    out.flush()?;
+    Ok(path)
+}
@@ -0,0 +1,311 @@
+use std::{collections::{BTreeMap, VecDeque}, ops::RangeInclusive};
+use tracing::error;
+use crate::emulator::{machine::{Machine, MemoryWrite}, comp_mode::CompatibilityMode, keys::Keys, instruction::Instruction};
+
+use heatmap::Heatmap;
+use history::History;
+use profile::Profile;
+use symbols::Symbols;
+use crate::trace::Tracer;
+#[cfg(feature = "jit")]
+use crate::emulator::jit::Jit;
+
+pub mod expression;
+pub mod heatmap;
+pub mod history;
+pub mod profile;
+pub mod search;
+pub mod symbols;
+
+
+/// How many of the most recently executed addresses are remembered
+const RECENT_STEPS: usize = 32;
+
+
+/// Decides after every executed instruction whether execution should pause again
+pub type StopCondition = Box<dyn FnMut(&Machine) -> bool>;
+
+
+/// Controls how the frontend's instruction budget is spent on the machine
+pub struct Debugger {
+    mode: Mode,
+    history: History,
+    heatmap: Heatmap,
+    /// How often the program wrote to every address this session
+    write_heat: Heatmap,
+    /// Writes to every address since the last frame started, or while paused since the last one ran
+    frame_writes: BTreeMap<u16, u64>,
+    tracer: Option<Tracer>,
+    recent: VecDeque<u16>,
+    symbols: Symbols,
+    profile: Option<Profile>,
+}
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Running,
+            history: History::new(),
+            heatmap: Heatmap::new(),
+            write_heat: Heatmap::new(),
+            frame_writes: BTreeMap::new(),
+            tracer: None,
+            recent: VecDeque::with_capacity(RECENT_STEPS),
+            symbols: Symbols::default(),
+            profile: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(self.mode, Mode::Paused)
+    }
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+    pub fn resume(&mut self) {
+        self.mode = Mode::Running;
+    }
+
+    /// Runs until `condition` holds after an executed instruction
+    pub fn run_until(&mut self, condition: StopCondition) {
+        self.mode = Mode::Until(condition);
+    }
+    pub fn step_into(&mut self) {
+        self.run_until(Box::new(|_| true));
+    }
+    /// Steps a single instruction, treating a subroutine call as one step
+    pub fn step_over(&mut self, machine: &Machine) {
+        if let Some(Instruction::Call(_)) = machine.next_instruction() {
+            let depth = machine.stack().len();
+            self.run_until(Box::new(move |m| m.stack().len() <= depth));
+        }
+        else {
+            self.step_into();
+        }
+    }
+    /// Runs until the current subroutine returns
+    pub fn step_out(&mut self, machine: &Machine) {
+        let depth = machine.stack().len();
+        if depth == 0 {
+            self.resume();
+        }
+        else {
+            self.run_until(Box::new(move |m| m.stack().len() < depth));
+        }
+    }
+
+    /// Runs until just before the next executed instruction of `class`. Skipped instructions don't count.
+    pub fn run_to_next(&mut self, class: InstructionClass) {
+        self.run_until(Box::new(move |m| !m.is_skipping() && m.next_instruction().is_some_and(|i| class.matches(&i))));
+    }
+    /// Runs until the PC leaves `range`
+    pub fn run_while_pc_in(&mut self, range: RangeInclusive<u16>) {
+        self.run_until(Box::new(move |m| !range.contains(&m.pc())));
+    }
+    /// Runs until the end of the next call to `run`
+    pub fn run_to_frame_end(&mut self) {
+        self.mode = Mode::UntilFrameEnd;
+    }
+
+    /// Rewinds the machine to just before the last executed instruction
+    pub fn step_back(&mut self, machine: &mut Machine, comp: &CompatibilityMode) -> bool {
+        self.pause();
+        self.history.step_back(machine, comp)
+    }
+    /// Rewinds the machine to the start of the current frame, or of the previous one if no
+    /// instruction has been executed in this frame yet
+    pub fn frame_back(&mut self, machine: &mut Machine, comp: &CompatibilityMode) -> bool {
+        self.pause();
+        self.history.frame_back(machine, comp)
+    }
+    /// Forgets the recorded history, needed when the machine is replaced
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+    /// Keeps up to `minutes` of history beyond what fits in memory in temporary files
+    pub fn set_rewind_minutes(&mut self, minutes: usize) {
+        self.history.set_spill_minutes(minutes);
+    }
+
+    /// How often every address was executed
+    pub fn heatmap(&self) -> &Heatmap {
+        &self.heatmap
+    }
+    pub fn clear_heatmap(&mut self) {
+        self.heatmap.clear();
+    }
+
+    /// Counts writes the machine made, as taken from its write log
+    pub fn record_writes(&mut self, writes: &[MemoryWrite]) {
+        for write in writes {
+            self.write_heat.record(write.address);
+            *self.frame_writes.entry(write.address).or_default() += 1;
+        }
+    }
+    /// How often every address was written this session
+    pub fn write_heat(&self) -> &Heatmap {
+        &self.write_heat
+    }
+    /// How often every address written in the last frame was written in it
+    pub fn frame_writes(&self) -> &BTreeMap<u16, u64> {
+        &self.frame_writes
+    }
+    pub fn clear_write_heat(&mut self) {
+        self.write_heat.clear();
+        self.frame_writes.clear();
+    }
+
+    /// Addresses of the most recently executed instructions, oldest first
+    pub fn recent_steps(&self) -> impl Iterator<Item = u16> + '_ {
+        self.recent.iter().copied()
+    }
+
+    /// Names of addresses in the program, used in backtraces
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// Starts or stops counting executed instructions by call stack, discarding the previous profile
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = enabled.then(Profile::new);
+    }
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Starts writing every executed instruction to `tracer`
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Decrements the timers of the machine, recording it in the history
+    pub fn tick(&mut self, machine: &mut Machine) {
+        machine.decrement_counters();
+        self.history.record_tick();
+    }
+
+    /// Writes a byte to the memory of the machine, recording it in the history
+    pub fn poke(&mut self, machine: &mut Machine, address: u16, value: u8) {
+        machine.poke(address, value);
+        self.history.record_poke(address, value);
+    }
+
+    /// Executes up to `budget` instructions, stopping early if the debugger pauses.
+    /// Each call counts as one frame.
+    pub fn run(&mut self, machine: &mut Machine, comp: &CompatibilityMode, keys: &Keys, budget: usize) {
+        if self.is_paused() {
+            return;
+        }
+        self.start_frame(machine);
+
+        for _ in 0..budget {
+            if self.is_paused() || machine.has_exited() {
+                return;
+            }
+            if machine.is_blocked(keys) {
+                break;
+            }
+            self.step(machine, comp, keys);
+        }
+        self.end_frame();
+    }
+    /// Like [`Debugger::run`], but runs the code `jit` compiled natively. Only the instructions
+    /// left to the interpreter are recorded, and the debugger can only pause between them.
+    #[cfg(feature = "jit")]
+    pub fn run_jit(&mut self, jit: &mut Jit, machine: &mut Machine, comp: &CompatibilityMode, keys: &Keys, budget: usize) {
+        if self.is_paused() {
+            return;
+        }
+        self.start_frame(machine);
+        jit.run_with(machine, comp, keys, budget, |machine| {
+            self.step(machine, comp, keys);
+            !self.is_paused()
+        });
+        self.end_frame();
+    }
+    fn start_frame(&mut self, machine: &Machine) {
+        self.history.record_frame(machine);
+        self.frame_writes.clear();
+    }
+    fn end_frame(&mut self) {
+        if let Mode::UntilFrameEnd = self.mode {
+            self.mode = Mode::Paused;
+        }
+    }
+    /// Executes and records one instruction, pausing if the stop condition holds after it
+    fn step(&mut self, machine: &mut Machine, comp: &CompatibilityMode, keys: &Keys) {
+        self.record_step(machine, keys);
+        machine.decode_and_execute(comp, keys);
+
+        if let Mode::Until(condition) = &mut self.mode {
+            if condition(machine) {
+                self.mode = Mode::Paused;
+            }
+        }
+    }
+    fn record_step(&mut self, machine: &Machine, keys: &Keys) {
+        self.history.record_step(keys);
+        if !machine.is_skipping() {
+            self.heatmap.record(machine.pc());
+        }
+        if self.recent.len() == RECENT_STEPS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(machine.pc());
+        if let Some(profile) = &mut self.profile {
+            profile.record(machine);
+        }
+
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = tracer.record(machine) {
+                error!("Could not write trace, stopping: {}", e);
+                self.tracer = None;
+            }
+        }
+    }
+}
+
+
+/// A kind of instruction the debugger can run to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InstructionClass {
+    Draw,
+    Call,
+    Random,
+    /// Instructions writing to memory, FX33 and FX55
+    Store,
+}
+impl InstructionClass {
+    pub const ALL: [InstructionClass; 4] = [InstructionClass::Draw, InstructionClass::Call, InstructionClass::Random, InstructionClass::Store];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InstructionClass::Draw => "draw",
+            InstructionClass::Call => "call",
+            InstructionClass::Random => "random",
+            InstructionClass::Store => "store",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<InstructionClass> {
+        Self::ALL.into_iter().find(|class| class.name() == name)
+    }
+
+    pub fn matches(self, instruction: &Instruction) -> bool {
+        match self {
+            InstructionClass::Draw => matches!(instruction, Instruction::Draw(..)),
+            InstructionClass::Call => matches!(instruction, Instruction::Call(_)),
+            InstructionClass::Random => matches!(instruction, Instruction::Random(..)),
+            InstructionClass::Store => matches!(instruction, Instruction::StoreBCD(_) | Instruction::Store(_)),
+        }
+    }
+}
+
+
+enum Mode {
+    Running,
+    Paused,
+    Until(StopCondition),
+    UntilFrameEnd,
+}
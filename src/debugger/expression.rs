@@ -0,0 +1,331 @@
+use std::{fmt::{self, Display, Formatter}, iter::Peekable};
+use crate::emulator::machine::Machine;
+
+
+/// An expression over the machine state, like `memory[I + 1]`, `V[3] + V4` or `stack.len() > 2`.
+/// Comparisons and `&&`, `||` are 1 if they hold and 0 otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Register(u8),
+    /// `V[x]`, the register whose number `x` evaluates to
+    IndexedRegister(Box<Expr>),
+    /// `stack.len()`, the number of return addresses on the stack
+    StackDepth,
+    /// `stack[n]`, the return address `n` entries from the bottom of the stack
+    Stack(Box<Expr>),
+    I,
+    Pc,
+    DelayTimer,
+    SoundTimer,
+    Memory(Box<Expr>),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, ExprError> {
+        let mut parser = Parser { tokens: tokenize(text)?.into_iter().peekable() };
+        let expr = parser.expression(0)?;
+        match parser.tokens.next() {
+            None => Ok(expr),
+            Some(token) => Err(ExprError(format!("unexpected {}", token))),
+        }
+    }
+
+    pub fn eval(&self, machine: &Machine) -> Result<i64, ExprError> {
+        Ok(match self {
+            Expr::Number(n) => *n,
+            Expr::Register(x) => machine.registers()[*x as usize] as i64,
+            Expr::IndexedRegister(x) => {
+                let x = x.eval(machine)?;
+                let value = usize::try_from(x).ok().and_then(|x| machine.registers().get(x));
+                match value {
+                    Some(&value) => value as i64,
+                    None => return Err(ExprError(format!("there is no register V[{}]", x))),
+                }
+            }
+            Expr::StackDepth => machine.stack().len() as i64,
+            Expr::Stack(index) => {
+                let index = index.eval(machine)?;
+                let address = usize::try_from(index).ok().and_then(|i| machine.stack().get(i));
+                match address {
+                    Some(&address) => address as i64,
+                    None => return Err(ExprError(format!("stack entry {} is out of range", index))),
+                }
+            }
+            Expr::I => machine.i() as i64,
+            Expr::Pc => machine.pc() as i64,
+            Expr::DelayTimer => machine.delay_timer() as i64,
+            Expr::SoundTimer => machine.sound_timer() as i64,
+            Expr::Memory(address) => {
+                let address = address.eval(machine)?;
+                let byte = usize::try_from(address).ok().and_then(|a| machine.memory().get(a));
+                match byte {
+                    Some(&byte) => byte as i64,
+                    None => return Err(ExprError(format!("address {:#X} is out of range", address))),
+                }
+            }
+            Expr::Negate(e) => e.eval(machine)?.wrapping_neg(),
+            Expr::Not(e) => !e.eval(machine)?,
+            Expr::Binary(op, a, b) => op.apply(a.eval(machine)?, b.eval(machine)?)?,
+        })
+    }
+
+    /// Whether the expression is non-zero, as a breakpoint condition. Conditions that can't be
+    /// evaluated count as met, so a broken condition stops execution instead of never doing so.
+    pub fn holds(&self, machine: &Machine) -> bool {
+        !matches!(self.eval(machine), Ok(0))
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Xor,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
+impl BinaryOp {
+    fn from_token(token: &Token) -> Option<BinaryOp> {
+        use BinaryOp::*;
+        Some(match token {
+            Token::Symbol("*") => Mul,
+            Token::Symbol("/") => Div,
+            Token::Symbol("%") => Rem,
+            Token::Symbol("+") => Add,
+            Token::Symbol("-") => Sub,
+            Token::Symbol("<<") => Shl,
+            Token::Symbol(">>") => Shr,
+            Token::Symbol("<") => Less,
+            Token::Symbol("<=") => LessEqual,
+            Token::Symbol(">") => Greater,
+            Token::Symbol(">=") => GreaterEqual,
+            Token::Symbol("==") => Equal,
+            Token::Symbol("!=") => NotEqual,
+            Token::Symbol("&") => And,
+            Token::Symbol("^") => Xor,
+            Token::Symbol("|") => Or,
+            Token::Symbol("&&") => LogicalAnd,
+            Token::Symbol("||") => LogicalOr,
+            _ => return None,
+        })
+    }
+    /// Higher binds tighter
+    fn precedence(self) -> u8 {
+        use BinaryOp::*;
+        match self {
+            Mul | Div | Rem => 9,
+            Add | Sub => 8,
+            Shl | Shr => 7,
+            Less | LessEqual | Greater | GreaterEqual => 6,
+            Equal | NotEqual => 5,
+            And => 4,
+            Xor => 3,
+            Or => 2,
+            LogicalAnd => 1,
+            LogicalOr => 0,
+        }
+    }
+    fn apply(self, a: i64, b: i64) -> Result<i64, ExprError> {
+        use BinaryOp::*;
+        Ok(match self {
+            Mul => a.wrapping_mul(b),
+            Div => a.checked_div(b).ok_or_else(|| ExprError("division by zero".into()))?,
+            Rem => a.checked_rem(b).ok_or_else(|| ExprError("division by zero".into()))?,
+            Add => a.wrapping_add(b),
+            Sub => a.wrapping_sub(b),
+            Shl => a.wrapping_shl(b as u32),
+            Shr => a.wrapping_shr(b as u32),
+            Less => (a < b) as i64,
+            LessEqual => (a <= b) as i64,
+            Greater => (a > b) as i64,
+            GreaterEqual => (a >= b) as i64,
+            Equal => (a == b) as i64,
+            NotEqual => (a != b) as i64,
+            And => a & b,
+            Xor => a ^ b,
+            Or => a | b,
+            LogicalAnd => (a != 0 && b != 0) as i64,
+            LogicalOr => (a != 0 || b != 0) as i64,
+        })
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExprError(pub String);
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Name(name) => write!(f, "name '{}'", name),
+            Token::Symbol(s) => write!(f, "'{}'", s),
+        }
+    }
+}
+
+/// Longer symbols come first, so they aren't split into shorter ones
+static SYMBOLS: &[&str] = &[
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "<", ">", "*", "/", "%", "+", "-", "&", "^", "|", "~", "(", ")", "[", "]", ".",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, ExprError> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_ascii_digit() {
+            let length = word_length(rest);
+            tokens.push(Token::Number(parse_number(&rest[..length])?));
+            length
+        }
+        else if c.is_ascii_alphabetic() || c == '_' {
+            let length = word_length(rest);
+            tokens.push(Token::Name(rest[..length].to_string()));
+            length
+        }
+        else {
+            let Some(&symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) else {
+                return Err(ExprError(format!("unexpected character '{}'", c)));
+            };
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+fn word_length(text: &str) -> usize {
+    text.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(text.len())
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number
+pub fn parse_number(text: &str) -> Result<i64, ExprError> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| ExprError(format!("invalid number '{}'", text)))
+}
+
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+impl Parser {
+    fn expression(&mut self, min_precedence: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.unary()?;
+
+        while let Some(op) = self.tokens.peek().and_then(BinaryOp::from_token) {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.tokens.next();
+            let rhs = self.expression(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        match self.tokens.next() {
+            Some(Token::Symbol("-")) => Ok(Expr::Negate(Box::new(self.unary()?))),
+            Some(Token::Symbol("~")) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Symbol("(")) => {
+                let expr = self.expression(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) => self.name(&name),
+            Some(token) => Err(ExprError(format!("unexpected {}", token))),
+            None => Err(ExprError("unexpected end of expression".into())),
+        }
+    }
+    fn name(&mut self, name: &str) -> Result<Expr, ExprError> {
+        let lower = name.to_ascii_lowercase();
+        Ok(match lower.as_str() {
+            "i" => Expr::I,
+            "pc" => Expr::Pc,
+            "dt" => Expr::DelayTimer,
+            "st" => Expr::SoundTimer,
+            "memory" | "mem" => Expr::Memory(Box::new(self.index()?)),
+            "v" => Expr::IndexedRegister(Box::new(self.index()?)),
+            "stack" => match self.tokens.next() {
+                Some(Token::Symbol("[")) => {
+                    let index = self.expression(0)?;
+                    self.expect("]")?;
+                    Expr::Stack(Box::new(index))
+                }
+                Some(Token::Symbol(".")) => {
+                    match self.tokens.next() {
+                        Some(Token::Name(method)) if method == "len" => {}
+                        _ => return Err(ExprError("expected 'len()' after 'stack.'".into())),
+                    }
+                    self.expect("(")?;
+                    self.expect(")")?;
+                    Expr::StackDepth
+                }
+                _ => return Err(ExprError("expected '[' or '.len()' after 'stack'".into())),
+            },
+            _ => match register_index(&lower) {
+                Some(x) => Expr::Register(x),
+                None => return Err(ExprError(format!("unknown name '{}'", name))),
+            },
+        })
+    }
+    /// An expression in square brackets
+    fn index(&mut self) -> Result<Expr, ExprError> {
+        self.expect("[")?;
+        let index = self.expression(0)?;
+        self.expect("]")?;
+        Ok(index)
+    }
+    fn expect(&mut self, symbol: &str) -> Result<(), ExprError> {
+        match self.tokens.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            Some(token) => Err(ExprError(format!("expected '{}', found {}", symbol, token))),
+            None => Err(ExprError(format!("expected '{}'", symbol))),
+        }
+    }
+}
+
+fn register_index(name: &str) -> Option<u8> {
+    let digit = name.strip_prefix('v')?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
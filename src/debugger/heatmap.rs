@@ -0,0 +1,75 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+
+/// How many addresses make up one row of the heatmap image
+const IMAGE_COLUMNS: usize = 64;
+/// Side length of the square of pixels representing one address
+const CELL_SIZE: usize = 8;
+
+
+/// Counts how often something happened at every address, like the instruction there
+/// being executed or the program writing to it
+pub struct Heatmap {
+    counts: Vec<u64>,
+    max: u64,
+}
+impl Heatmap {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; 1 << 16],
+            max: 0,
+        }
+    }
+
+    pub fn record(&mut self, address: u16) {
+        let count = &mut self.counts[address as usize];
+        *count += 1;
+        self.max = self.max.max(*count);
+    }
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.max = 0;
+    }
+
+    pub fn count(&self, address: u16) -> u64 {
+        self.counts[address as usize]
+    }
+    /// Colour of `address`, black if its count is 0
+    pub fn color(&self, address: u16) -> [u8; 3] {
+        heat_color(self.count(address), self.max)
+    }
+
+    /// Writes the heatmap as a PPM image, one cell per address and `IMAGE_COLUMNS` addresses per row,
+    /// up to the last address counted
+    pub fn write_image(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let last = self.counts.iter().rposition(|&c| c != 0).unwrap_or(0);
+        let rows = last / IMAGE_COLUMNS + 1;
+        let width = IMAGE_COLUMNS * CELL_SIZE;
+        let height = rows * CELL_SIZE;
+
+        let mut out = BufWriter::new(File::create(path)?);
+        write!(out, "P6\n{} {}\n255\n", width, height)?;
+        for y in 0..height {
+            for x in 0..width {
+                let address = (y / CELL_SIZE) * IMAGE_COLUMNS + x / CELL_SIZE;
+                out.write_all(&self.color(address as u16))?;
+            }
+        }
+        out.flush()
+    }
+}
+
+
+/// Maps a count onto a black, blue, red, yellow gradient on a logarithmic scale
+pub fn heat_color(count: u64, max: u64) -> [u8; 3] {
+    if count == 0 {
+        return [0, 0, 0];
+    }
+
+    let heat = ((count as f64).ln_1p() / (max as f64).ln_1p()) as f32;
+    let stops = [[0.0, 0.0, 160.0], [220.0, 0.0, 0.0], [255.0, 255.0, 0.0]];
+    let scaled = heat * (stops.len() - 1) as f32;
+    let index = (scaled as usize).min(stops.len() - 2);
+    let t = scaled - index as f32;
+    let (a, b) = (stops[index], stops[index + 1]);
+    [0, 1, 2].map(|i| (a[i] + (b[i] - a[i]) * t) as u8)
+}
@@ -0,0 +1,258 @@
+use std::{collections::VecDeque, env, fs::{self, File, OpenOptions}, io::{self, Read, Seek, SeekFrom, Write}, path::PathBuf, process, sync::atomic::{AtomicUsize, Ordering}};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec};
+use serde::{Serialize, Deserialize};
+use tracing::error;
+use crate::{emulator::{machine::Machine, comp_mode::CompatibilityMode, keys::Keys}, save_state};
+
+/// How many frames pass between two snapshots
+const CHECKPOINT_INTERVAL: usize = 30;
+/// How many snapshots are kept in memory, older ones are spilled to disk or discarded
+const MAX_CHECKPOINTS: usize = 120;
+/// How many checkpoints go into each spill file, a minute of play at 60 frames per second
+const SEGMENT_CHECKPOINTS: usize = 3600 / CHECKPOINT_INTERVAL;
+/// Deflate level of spilled checkpoints, the events of a checkpoint compress well
+const SPILL_LEVEL: u8 = 1;
+
+
+/// Records the execution of the machine so it can be replayed up to any earlier point
+pub struct History {
+    checkpoints: VecDeque<Checkpoint>,
+    frames_since_checkpoint: usize,
+    /// Where checkpoints go once they no longer fit in memory, if anywhere
+    spill: Option<Spill>,
+}
+impl History {
+    pub fn new() -> Self {
+        Self {
+            checkpoints: VecDeque::new(),
+            frames_since_checkpoint: 0,
+            spill: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.frames_since_checkpoint = 0;
+        if let Some(spill) = &mut self.spill {
+            spill.clear();
+        }
+    }
+    /// Keeps up to `minutes` of checkpoints that no longer fit in memory in temporary files,
+    /// so rewinding reaches that much further back. 0 discards them instead.
+    pub fn set_spill_minutes(&mut self, minutes: usize) {
+        self.spill = (minutes > 0).then(|| Spill::new(minutes));
+    }
+
+    pub fn record_frame(&mut self, machine: &Machine) {
+        if self.checkpoints.is_empty() || self.frames_since_checkpoint >= CHECKPOINT_INTERVAL {
+            if self.checkpoints.len() >= MAX_CHECKPOINTS {
+                let oldest = self.checkpoints.pop_front().unwrap();
+                if let Some(spill) = &mut self.spill {
+                    if let Err(e) = spill.push(&oldest) {
+                        error!("Could not spill rewind history to disk, older history is discarded: {}", e);
+                        self.spill = None;
+                    }
+                }
+            }
+            self.checkpoints.push_back(Checkpoint {
+                snapshot: save_state::snapshot(machine),
+                events: Vec::new(),
+            });
+            self.frames_since_checkpoint = 0;
+        }
+
+        self.frames_since_checkpoint += 1;
+        self.push(Event::Frame);
+    }
+    pub fn record_step(&mut self, keys: &Keys) {
+        self.push(Event::Step(*keys));
+    }
+    pub fn record_tick(&mut self) {
+        self.push(Event::Tick);
+    }
+    pub fn record_poke(&mut self, address: u16, value: u8) {
+        self.push(Event::Poke(address, value));
+    }
+    fn push(&mut self, event: Event) {
+        if let Some(checkpoint) = self.checkpoints.back_mut() {
+            checkpoint.events.push(event);
+        }
+    }
+
+    /// Rewinds to just before the last recorded instruction
+    pub fn step_back(&mut self, machine: &mut Machine, comp: &CompatibilityMode) -> bool {
+        self.rewind(machine, comp, |events| events.iter().rposition(Event::is_step))
+    }
+    /// Rewinds to the start of the frame containing the last recorded instruction
+    pub fn frame_back(&mut self, machine: &mut Machine, comp: &CompatibilityMode) -> bool {
+        self.rewind(machine, comp, |events| {
+            let last_step = events.iter().rposition(Event::is_step)?;
+            events[..last_step].iter().rposition(|e| matches!(e, Event::Frame))
+        })
+    }
+
+    /// Truncates the history at the position `find` returns and replays up to it.
+    /// Checkpoints without such a position are discarded.
+    fn rewind(&mut self, machine: &mut Machine, comp: &CompatibilityMode, find: impl Fn(&[Event]) -> Option<usize>) -> bool {
+        while let Some(checkpoint) = self.last_checkpoint() {
+            if let Some(position) = find(&checkpoint.events) {
+                checkpoint.events.truncate(position);
+                *machine = checkpoint.replay(machine, comp);
+                // The output of replayed instructions was already shown
+                machine.take_debug_output();
+                self.frames_since_checkpoint = checkpoint.events.iter().filter(|e| matches!(e, Event::Frame)).count();
+                return true;
+            }
+            self.checkpoints.pop_back();
+        }
+
+        false
+    }
+
+    /// The newest checkpoint, read back from disk once the ones in memory are used up
+    fn last_checkpoint(&mut self) -> Option<&mut Checkpoint> {
+        if self.checkpoints.is_empty() {
+            match self.spill.as_mut().map(Spill::pop) {
+                Some(Ok(Some(checkpoint))) => self.checkpoints.push_back(checkpoint),
+                Some(Err(e)) => {
+                    error!("Could not read rewind history from disk: {}", e);
+                    self.spill.as_mut().unwrap().clear();
+                }
+                Some(Ok(None)) | None => (),
+            }
+        }
+        self.checkpoints.back_mut()
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    /// The machine compressed, see [`save_state::snapshot`]
+    snapshot: Vec<u8>,
+    /// Everything that happened to the machine since the snapshot was taken
+    events: Vec<Event>,
+}
+impl Checkpoint {
+    /// Restores the snapshot with the settings of `current` and replays the events on it
+    fn replay(&self, current: &Machine, comp: &CompatibilityMode) -> Machine {
+        let mut machine = save_state::restore_snapshot(&self.snapshot).expect("snapshots taken in this session are valid");
+        machine.adopt_runtime_settings(current);
+        for event in &self.events {
+            match event {
+                Event::Frame => (),
+                Event::Step(keys) => machine.decode_and_execute(comp, keys),
+                Event::Tick => machine.decrement_counters(),
+                Event::Poke(address, value) => machine.poke(*address, *value),
+            }
+        }
+        machine
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+enum Event {
+    /// Start of a frame
+    Frame,
+    /// An executed instruction, with the keys held at the time
+    Step(Keys),
+    /// A decrement of the timers
+    Tick,
+    /// A byte written to memory from outside the program
+    Poke(u16, u8),
+}
+impl Event {
+    fn is_step(&self) -> bool {
+        matches!(self, Event::Step(_))
+    }
+}
+
+
+/// Checkpoints moved out of memory, in temporary files of [`SEGMENT_CHECKPOINTS`] each.
+/// Whole files are deleted once they are older than the history should reach.
+struct Spill {
+    /// Oldest first, only the last one is still being filled
+    segments: VecDeque<Segment>,
+    max_segments: usize,
+}
+impl Spill {
+    fn new(minutes: usize) -> Self {
+        Self {
+            segments: VecDeque::new(),
+            max_segments: minutes,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    fn push(&mut self, checkpoint: &Checkpoint) -> io::Result<()> {
+        if self.segments.back().is_none_or(|s| s.index.len() >= SEGMENT_CHECKPOINTS) {
+            if self.segments.len() >= self.max_segments {
+                self.segments.pop_front();
+            }
+            self.segments.push_back(Segment::create()?);
+        }
+
+        let data = bincode::serialize(checkpoint).map_err(io::Error::other)?;
+        self.segments.back_mut().unwrap().push(&compress_to_vec(&data, SPILL_LEVEL))
+    }
+    /// Removes the newest checkpoint from disk
+    fn pop(&mut self) -> io::Result<Option<Checkpoint>> {
+        let Some(segment) = self.segments.back_mut() else {
+            return Ok(None);
+        };
+        let data = segment.pop()?;
+        if segment.index.is_empty() {
+            self.segments.pop_back();
+        }
+
+        let data = decompress_to_vec(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+        bincode::deserialize(&data).map(Some).map_err(io::Error::other)
+    }
+}
+
+
+/// A temporary file of compressed checkpoints, deleted when dropped
+struct Segment {
+    file: File,
+    path: PathBuf,
+    /// Offset and length of every checkpoint in the file, oldest first
+    index: Vec<(u64, usize)>,
+}
+impl Segment {
+    fn create() -> io::Result<Self> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("chippy-rewind-{}-{}.bin", process::id(), CREATED.fetch_add(1, Ordering::Relaxed));
+        let path = env::temp_dir().join(name);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+        Ok(Self {
+            file,
+            path,
+            index: Vec::with_capacity(SEGMENT_CHECKPOINTS),
+        })
+    }
+
+    fn push(&mut self, data: &[u8]) -> io::Result<()> {
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(data)?;
+        self.index.push((offset, data.len()));
+        Ok(())
+    }
+    /// Reads the last checkpoint and cuts it off the file, there must be one
+    fn pop(&mut self) -> io::Result<Vec<u8>> {
+        let (offset, length) = self.index.pop().unwrap();
+        let mut data = vec![0; length];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut data)?;
+        self.file.set_len(offset)?;
+        Ok(data)
+    }
+}
+impl Drop for Segment {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,67 @@
+//! Where the program spends its instructions, by call stack, for flame graphs.
+//!
+//! Profiles are written in the folded stack format read by flamegraph.pl, inferno and speedscope:
+//! one line per distinct stack with its frames from the outermost, separated by semicolons,
+//! followed by the number of instructions executed there. The frames are the subroutines on the
+//! stack, named after their entry point, and the address of the instruction itself.
+
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}, path::Path};
+use crate::emulator::{instruction::{Address, Instruction}, machine::Machine};
+use super::symbols::Symbols;
+
+
+/// Executed instructions counted by return addresses on the stack and PC
+pub struct Profile {
+    samples: HashMap<Vec<u16>, u64>,
+    /// The stack and PC of the current instruction, kept to avoid allocating for known stacks
+    key: Vec<u16>,
+}
+impl Profile {
+    pub fn new() -> Self {
+        Self {
+            samples: HashMap::new(),
+            key: Vec::new(),
+        }
+    }
+
+    /// Counts the instruction the machine is about to execute
+    pub fn record(&mut self, machine: &Machine) {
+        self.key.clear();
+        self.key.extend_from_slice(machine.stack());
+        self.key.push(machine.pc());
+        match self.samples.get_mut(&self.key[..]) {
+            Some(count) => *count += 1,
+            None => {
+                self.samples.insert(self.key.clone(), 1);
+            }
+        }
+    }
+
+    /// Writes the folded stacks, finding the called subroutines in the machine's memory and
+    /// naming addresses after `symbols` where possible
+    pub fn write(&self, path: &Path, machine: &Machine, symbols: &Symbols) -> io::Result<()> {
+        let name = |address: u16, prefix: &str| symbols.describe(address).unwrap_or_else(|| format!("{}{:03X}", prefix, address));
+        let mut lines: Vec<_> = self.samples.iter().map(|(key, &count)| {
+            let (&pc, stack) = key.split_last().unwrap();
+            let mut frames = vec![String::from("main")];
+            for &return_address in stack {
+                let call_site = return_address.wrapping_sub(2);
+                let called = match machine.memory().get(call_site as usize..).and_then(Instruction::decode) {
+                    Some(Instruction::Call(Address(target))) => target,
+                    // Overwritten since, the call site is the best guess left
+                    _ => call_site,
+                };
+                frames.push(name(called, "sub_"));
+            }
+            frames.push(name(pc, ""));
+            format!("{} {}", frames.join(";"), count)
+        }).collect();
+        lines.sort();
+
+        let mut out = BufWriter::new(File::create(path)?);
+        for line in lines {
+            writeln!(out, "{}", line)?;
+        }
+        out.flush()
+    }
+}
@@ -0,0 +1,55 @@
+//! Finding byte patterns and values in memory, to locate sprite data and variables.
+
+use super::expression::parse_number;
+
+
+/// A sequence of bytes to look for, where `None` matches any byte
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pattern(pub Vec<Option<u8>>);
+impl Pattern {
+    /// Parses hexadecimal bytes like `F0 90 ?? 90` or `F090??90`, `??` matching any byte
+    pub fn parse(text: &str) -> Result<Pattern, String> {
+        let digits: String = text.split_whitespace().collect();
+        if digits.is_empty() {
+            return Err("expected bytes to find".into());
+        }
+        if !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+            return Err(format!("'{}' is not a whole number of bytes", text.trim()));
+        }
+
+        let bytes = (0..digits.len()).step_by(2).map(|i| {
+            let byte = &digits[i..i + 2];
+            if byte == "??" {
+                Ok(None)
+            }
+            else {
+                u8::from_str_radix(byte, 16).map(Some).map_err(|_| format!("invalid byte '{}'", byte))
+            }
+        });
+        Ok(Pattern(bytes.collect::<Result<_, _>>()?))
+    }
+    /// The bytes of `value` in the program's big-endian order: one byte up to 255, two up to 65535
+    pub fn value(text: &str) -> Result<Pattern, String> {
+        let value = parse_number(text.trim()).map_err(|e| e.to_string())?;
+        match u8::try_from(value) {
+            Ok(byte) => Ok(Pattern(vec![Some(byte)])),
+            Err(_) => match u16::try_from(value) {
+                Ok(word) => Ok(Pattern(word.to_be_bytes().map(Some).to_vec())),
+                Err(_) => Err(format!("{} does not fit in two bytes", value)),
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Addresses of every match in `memory`, matches may overlap
+    pub fn find(&self, memory: &[u8]) -> Vec<u16> {
+        memory.windows(self.len())
+            .enumerate()
+            .filter(|(_, bytes)| bytes.iter().zip(&self.0).all(|(&b, p)| p.is_none_or(|p| p == b)))
+            .map(|(address, _)| address as u16)
+            .collect()
+    }
+}
@@ -0,0 +1,57 @@
+use std::{collections::BTreeMap, fmt::{self, Display, Formatter}, fs, io, path::Path};
+
+
+/// Names of addresses in the program, for backtraces.
+///
+/// Symbol files have one label per line, a hexadecimal address followed by the name, like
+/// `2A4 draw_player`. Empty lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+impl Symbols {
+    pub fn load(path: &Path) -> Result<Self, SymbolError> {
+        let text = fs::read_to_string(path).map_err(SymbolError::Io)?;
+        Self::parse(&text)
+    }
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut names = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || SymbolError::Invalid(number + 1);
+            let (address, name) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let digits = address.strip_prefix("0x").unwrap_or(address);
+            let address = u16::from_str_radix(digits, 16).map_err(|_| invalid())?;
+            names.insert(address, name.trim().to_string());
+        }
+        Ok(Self { names })
+    }
+
+    /// `address` as an offset from the closest label at or before it, like `draw_player+6`
+    pub fn describe(&self, address: u16) -> Option<String> {
+        let (&label, name) = self.names.range(..=address).next_back()?;
+        match address - label {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+{:X}", name, offset)),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum SymbolError {
+    Io(io::Error),
+    /// The line with this number is not an address followed by a name
+    Invalid(usize),
+}
+impl Display for SymbolError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SymbolError::Io(e) => write!(f, "{}", e),
+            SymbolError::Invalid(line) => write!(f, "line {} is not an address followed by a name", line),
+        }
+    }
+}
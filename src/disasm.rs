@@ -0,0 +1,289 @@
+//! Annotated disassemblies of ROMs, as text or as a navigable HTML report.
+//!
+//! Code is told apart from data by following the program from its start like the control-flow
+//! graph does, bytes that are never reached count as data. Targets of jumps, calls and `LD I`
+//! get labels listing where they are referenced from, and data loaded into I is previewed as
+//! sprites, 8 pixels wide. With a trace, every instruction shows how often it was executed.
+
+use std::{collections::{BTreeMap, HashMap}, fmt::Write as _, fs, io::{self, Write}, path::{Path, PathBuf}, process::ExitCode};
+use tracing::error;
+use crate::{config::{Config, CONFIG_PATH}, emulator::instruction::{Address, Instruction}, flow_graph::FlowGraph, trace};
+
+/// Bytes per line of data
+const DATA_COLUMNS: usize = 8;
+/// How many rows of a sprite are previewed at most
+const MAX_SPRITE_ROWS: usize = 32;
+/// Side length of a previewed sprite pixel in the HTML report
+const SPRITE_SCALE: usize = 4;
+
+
+/// Disassembles the program at `path` and writes the listing to `output`, or stdout without one
+pub fn run(path: &Path, patches: &[PathBuf], html: bool, trace: Option<&Path>, output: Option<&Path>) -> ExitCode {
+    let program = match crate::read_program(path, patches) {
+        Ok(program) => program,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let comp = crate::load_rom_settings(&Config::load(CONFIG_PATH), path, &program).quirks.build();
+    let machine = match crate::new_machine(&program, &comp, None) {
+        Ok(machine) => machine,
+        Err(e) => {
+            error!("Could not load {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let counts = match trace.map(trace::read_counts).transpose() {
+        Ok(counts) => counts,
+        Err(e) => {
+            error!("Could not read trace {}: {}", trace.unwrap().display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let end = machine.program_start() + program.len();
+    let listing = Listing::build(&machine.memory()[..end], comp.program_start, counts);
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let text = if html { listing.html(&name) } else { listing.text() };
+    let result = match output {
+        Some(output) => fs::write(output, text),
+        None => io::stdout().write_all(text.as_bytes()),
+    };
+    if let Err(e) = result {
+        error!("Could not write disassembly: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Reference {
+    Jump,
+    Call,
+    /// `LD I` pointing at the address
+    Load,
+}
+impl Reference {
+    fn name(self) -> &'static str {
+        match self {
+            Reference::Jump => "jump",
+            Reference::Call => "call",
+            Reference::Load => "load",
+        }
+    }
+}
+
+enum Entry {
+    Code { address: u16, bytes: Vec<u8>, instruction: Instruction },
+    /// Bytes that are never executed, a sprite if I is pointed at them
+    Data { address: u16, bytes: Vec<u8>, sprite: bool },
+}
+impl Entry {
+    fn address(&self) -> u16 {
+        match *self {
+            Entry::Code { address, .. } | Entry::Data { address, .. } => address,
+        }
+    }
+}
+
+
+struct Listing {
+    entries: Vec<Entry>,
+    labels: BTreeMap<u16, String>,
+    /// Where every referenced address is referenced from
+    references: BTreeMap<u16, Vec<(u16, Reference)>>,
+    /// Executions of every address, if a trace was given
+    counts: Option<HashMap<u16, u64>>,
+}
+impl Listing {
+    fn build(memory: &[u8], start: u16, counts: Option<HashMap<u16, u64>>) -> Self {
+        let graph = FlowGraph::build(memory, start);
+        let instructions = graph.instructions();
+
+        let mut references: BTreeMap<u16, Vec<(u16, Reference)>> = BTreeMap::new();
+        for (&address, instruction) in instructions {
+            let reference = match *instruction {
+                Instruction::Jump(Address(target)) => (target, Reference::Jump),
+                Instruction::Call(Address(target)) => (target, Reference::Call),
+                Instruction::LoadI(Address(target)) => (target, Reference::Load),
+                _ => continue,
+            };
+            references.entry(reference.0).or_default().push((address, reference.1));
+        }
+
+        let mut labels = BTreeMap::new();
+        for &target in references.keys() {
+            let prefix = if graph.subroutines().contains(&target) {
+                "sub"
+            }
+            else if instructions.contains_key(&target) {
+                "L"
+            }
+            else {
+                "data"
+            };
+            labels.insert(target, format!("{}_{:03X}", prefix, target));
+        }
+
+        let is_loaded = |address: u16| references.get(&address).is_some_and(|r| r.iter().any(|&(_, kind)| kind == Reference::Load));
+        let mut entries = Vec::new();
+        let mut address = start as usize;
+        while address < memory.len() {
+            if let Some(&instruction) = instructions.get(&(address as u16)) {
+                let length = (instruction.length() as usize).min(memory.len() - address);
+                entries.push(Entry::Code { address: address as u16, bytes: memory[address..address + length].to_vec(), instruction });
+                address += length;
+                continue;
+            }
+
+            // Data runs up to the next code or the next sprite
+            let first = address;
+            address += 1;
+            while address < memory.len() && !instructions.contains_key(&(address as u16)) && !is_loaded(address as u16) {
+                address += 1;
+            }
+            entries.push(Entry::Data { address: first as u16, bytes: memory[first..address].to_vec(), sprite: is_loaded(first as u16) });
+        }
+
+        Self { entries, labels, references, counts }
+    }
+
+    fn code_bytes(&self) -> usize {
+        self.entries.iter().map(|e| match e {
+            Entry::Code { bytes, .. } => bytes.len(),
+            Entry::Data { .. } => 0,
+        }).sum()
+    }
+    fn data_bytes(&self) -> usize {
+        self.entries.iter().map(|e| match e {
+            Entry::Code { .. } => 0,
+            Entry::Data { bytes, .. } => bytes.len(),
+        }).sum()
+    }
+
+    fn count(&self, address: u16) -> Option<u64> {
+        self.counts.as_ref().map(|counts| counts.get(&address).copied().unwrap_or(0))
+    }
+    /// The label the operand of `instruction` refers to
+    fn target_label(&self, instruction: &Instruction) -> Option<(u16, &str)> {
+        let (Instruction::Jump(Address(target)) | Instruction::Call(Address(target)) | Instruction::LoadI(Address(target))) = *instruction else {
+            return None;
+        };
+        self.labels.get(&target).map(|label| (target, label.as_str()))
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "; {} bytes of code, {} bytes of data", self.code_bytes(), self.data_bytes()).unwrap();
+        for entry in &self.entries {
+            let address = entry.address();
+            if let Some(label) = self.labels.get(&address) {
+                let sources: Vec<_> = self.references[&address].iter()
+                    .map(|&(from, kind)| format!("{:03X} ({})", from, kind.name()))
+                    .collect();
+                writeln!(text, "\n{}:    ; from {}", label, sources.join(", ")).unwrap();
+            }
+
+            match entry {
+                Entry::Code { bytes, instruction, .. } => {
+                    let hex: Vec<_> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                    write!(text, "  {:03X}  {:<5}  {}", address, hex.join(""), instruction).unwrap();
+                    if let Some(count) = self.count(address) {
+                        write!(text, "    ; {}x", count).unwrap();
+                    }
+                    text.push('\n');
+                }
+                Entry::Data { bytes, sprite, .. } => {
+                    for (row, chunk) in bytes.chunks(DATA_COLUMNS).enumerate() {
+                        let hex: Vec<_> = chunk.iter().map(|b| format!("0x{:02X}", b)).collect();
+                        writeln!(text, "  {:03X}  DB {}", address as usize + row * DATA_COLUMNS, hex.join(", ")).unwrap();
+                    }
+                    if *sprite {
+                        for &byte in bytes.iter().take(MAX_SPRITE_ROWS) {
+                            let pixels: String = (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+                            writeln!(text, "       ; {}", pixels).unwrap();
+                        }
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    fn html(&self, name: &str) -> String {
+        let mut body = String::new();
+        for entry in &self.entries {
+            let address = entry.address();
+            // Links go to the label if there is one, so it stays in view
+            let mut anchor = format!(" id=\"a{:03X}\"", address);
+            if let Some(label) = self.labels.get(&address) {
+                let sources: Vec<_> = self.references[&address].iter()
+                    .map(|&(from, kind)| format!("<a href=\"#a{:03X}\">{:03X}</a> ({})", from, from, kind.name()))
+                    .collect();
+                writeln!(body, "<div class=\"label\"{}>{}: <span class=\"refs\">from {}</span></div>", anchor, label, sources.join(", ")).unwrap();
+                anchor.clear();
+            }
+
+            match entry {
+                Entry::Code { bytes, instruction, .. } => {
+                    let hex: Vec<_> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                    let count = match self.count(address) {
+                        Some(count) => format!("<span class=\"count\">{:>8}</span> ", count),
+                        None => String::new(),
+                    };
+                    let target = match self.target_label(instruction) {
+                        Some((target, label)) => format!(" <a href=\"#a{:03X}\">{}</a>", target, label),
+                        None => String::new(),
+                    };
+                    writeln!(
+                        body, "<div class=\"code\"{}>{}{:03X}  {:<5}  {}{}</div>",
+                        anchor, count, address, hex.join(""), escape_html(&instruction.to_string()), target,
+                    ).unwrap();
+                }
+                Entry::Data { bytes, sprite, .. } => {
+                    let indent = if self.counts.is_some() { " ".repeat(9) } else { String::new() };
+                    for (row, chunk) in bytes.chunks(DATA_COLUMNS).enumerate() {
+                        let hex: Vec<_> = chunk.iter().map(|b| format!("0x{:02X}", b)).collect();
+                        let id = if row == 0 { anchor.as_str() } else { "" };
+                        writeln!(body, "<div class=\"data\"{}>{}{:03X}  DB {}</div>", id, indent, address as usize + row * DATA_COLUMNS, hex.join(", ")).unwrap();
+                    }
+                    if *sprite {
+                        writeln!(body, "<div class=\"sprite\">{}</div>", sprite_svg(bytes)).unwrap();
+                    }
+                }
+            }
+        }
+
+        let summary = format!("{} bytes of code, {} bytes of data", self.code_bytes(), self.data_bytes());
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+            body {{ font-family: monospace; }}\ndiv {{ white-space: pre; }}\n\
+            .label {{ margin-top: 1em; font-weight: bold; }}\n.refs {{ font-weight: normal; color: #666; }}\n\
+            .data {{ color: #888; }}\n.count {{ color: #a40; }}\n.sprite {{ margin: 0.25em 0 0.25em 6ch; }}\n\
+            :target {{ background: #ff8; }}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{summary}</p>\n{body}</body>\n</html>\n",
+            title = escape_html(name),
+        )
+    }
+}
+
+/// An SVG image of the bytes as a sprite, 8 pixels wide
+fn sprite_svg(bytes: &[u8]) -> String {
+    let rows = bytes.len().min(MAX_SPRITE_ROWS);
+    let mut svg = format!(
+        "<svg width=\"{}\" height=\"{}\" viewBox=\"0 0 8 {}\" shape-rendering=\"crispEdges\" style=\"background: #000\">",
+        8 * SPRITE_SCALE, rows * SPRITE_SCALE, rows,
+    );
+    for (y, &byte) in bytes.iter().take(rows).enumerate() {
+        for x in (0..8).filter(|x| byte & (0x80 >> x) != 0) {
+            write!(svg, "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"#fff\"/>", x, y).unwrap();
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -3,3 +3,7 @@ pub mod machine;
 pub mod instruction;
 pub mod comp_mode;
 pub mod keys;
+pub mod palette;
+#[cfg(feature = "jit")]
+pub mod jit;
+mod big_array;
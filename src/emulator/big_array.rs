@@ -0,0 +1,27 @@
+//! Serde helpers for arrays longer than the 32 elements serde supports out of the box
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de::Error};
+
+
+pub fn serialize<S: Serializer, T: Serialize, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error> {
+    array.as_slice().serialize(serializer)
+}
+pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(deserializer: D) -> Result<[T; N], D::Error> {
+    let items = Vec::<T>::deserialize(deserializer)?;
+    let length = items.len();
+    items.try_into().map_err(|_| D::Error::invalid_length(length, &"an array of the right length"))
+}
+
+/// The same for boxed arrays, which are too large to live on the stack
+pub mod boxed {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Serialize, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error> {
+        super::serialize(array, serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(deserializer: D) -> Result<Box<[T; N]>, D::Error> {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        let length = items.len();
+        items.into_boxed_slice().try_into().map_err(|_| D::Error::invalid_length(length, &"an array of the right length"))
+    }
+}
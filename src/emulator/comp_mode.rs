@@ -1,4 +1,5 @@
-use super::instruction::Instruction;
+use serde::{Serialize, Deserialize};
+use super::{instruction::Instruction, screen::HEIGHT};
 
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -9,6 +10,25 @@ pub struct CompatibilityMode {
     pub allowed_instructions: AllowedInstructions,
     pub jump_mode: RelativeJumpMode,
     pub collisions: CollisionEnumeration,
+    pub lores_scroll: LoresScrollMode,
+    pub large_sprites: LargeSpriteMode,
+    pub add_i_overflow: AddIOverflow,
+    pub random: RandomMode,
+    pub draw_origin: DrawOrigin,
+    /// What happens to sprites crossing the left or right edge of the screen
+    pub horizontal_edge: SpriteEdge,
+    /// What happens to sprites crossing the top or bottom edge of the screen
+    pub vertical_edge: SpriteEdge,
+    /// Whether the debug print pseudo-instructions are executed rather than rejected
+    pub debug_print: bool,
+    /// How many XO-Chip audio patterns play at once, mixed together, with F002 loading one for each voice
+    /// from consecutive 16 byte blocks at I.
+    /// 1 is the single voice of the original XO-Chip, more are the extended audio of newer Octo versions.
+    pub audio_voices: u8,
+    /// Resolution of lores mode
+    pub lores_size: LoresSize,
+    /// Where programs are loaded and start executing
+    pub program_start: u16,
 }
 
 
@@ -25,10 +45,45 @@ impl CompBuilder {
                 allowed_instructions: AllowedInstructions::Original,
                 jump_mode: RelativeJumpMode::Original,
                 collisions: CollisionEnumeration::Original,
+                lores_scroll: LoresScrollMode::Whole,
+                large_sprites: LargeSpriteMode::SuperChip,
+                add_i_overflow: AddIOverflow::Ignore,
+                random: RandomMode::Modern,
+                draw_origin: DrawOrigin::Wrap,
+                horizontal_edge: SpriteEdge::Auto,
+                vertical_edge: SpriteEdge::Auto,
+                debug_print: false,
+                audio_voices: 1,
+                lores_size: LoresSize::Standard,
+                program_start: 0x200,
             }
         }
     }
 
+    pub fn chip8_preset() -> Self {
+        Self {
+            comp: CompatibilityMode {
+                shift: ShiftMode::Original,
+                load_store: LoadStoreMode::Original,
+                address_space: AddressSpace::Original,
+                allowed_instructions: AllowedInstructions::Original,
+                jump_mode: RelativeJumpMode::Original,
+                collisions: CollisionEnumeration::Original,
+                lores_scroll: LoresScrollMode::Whole,
+                large_sprites: LargeSpriteMode::SuperChip,
+                add_i_overflow: AddIOverflow::Ignore,
+                random: RandomMode::Modern,
+                draw_origin: DrawOrigin::Wrap,
+                horizontal_edge: SpriteEdge::Auto,
+                vertical_edge: SpriteEdge::Auto,
+                debug_print: false,
+                audio_voices: 1,
+                lores_size: LoresSize::Standard,
+                program_start: 0x200,
+            },
+        }
+    }
+
     pub fn superchip_preset() -> Self {
         Self {
             comp: CompatibilityMode {
@@ -38,23 +93,116 @@ impl CompBuilder {
                 allowed_instructions: AllowedInstructions::SuperChip,
                 jump_mode: RelativeJumpMode::SuperChip,
                 collisions: CollisionEnumeration::SuperChip,
+                lores_scroll: LoresScrollMode::Half,
+                large_sprites: LargeSpriteMode::SuperChip,
+                add_i_overflow: AddIOverflow::Ignore,
+                random: RandomMode::Modern,
+                draw_origin: DrawOrigin::Wrap,
+                horizontal_edge: SpriteEdge::Auto,
+                vertical_edge: SpriteEdge::Auto,
+                debug_print: false,
+                audio_voices: 1,
+                lores_size: LoresSize::Standard,
+                program_start: 0x200,
             },
         }
     }
 
+    pub fn with_shift(mut self, mode: ShiftMode) -> Self {
+        self.comp.shift = mode;
+        self
+    }
+    pub fn with_load_store(mut self, mode: LoadStoreMode) -> Self {
+        self.comp.load_store = mode;
+        self
+    }
+    pub fn with_address_space(mut self, space: AddressSpace) -> Self {
+        self.comp.address_space = space;
+        self
+    }
+    pub fn with_allowed_instructions(mut self, allowed: AllowedInstructions) -> Self {
+        self.comp.allowed_instructions = allowed;
+        self
+    }
     pub fn with_jump_mode(mut self, mode: RelativeJumpMode) -> Self {
         self.comp.jump_mode = mode;
         self
     }
+    pub fn with_collisions(mut self, collisions: CollisionEnumeration) -> Self {
+        self.comp.collisions = collisions;
+        self
+    }
+    pub fn with_lores_scroll(mut self, mode: LoresScrollMode) -> Self {
+        self.comp.lores_scroll = mode;
+        self
+    }
+    pub fn with_large_sprites(mut self, mode: LargeSpriteMode) -> Self {
+        self.comp.large_sprites = mode;
+        self
+    }
+    pub fn with_add_i_overflow(mut self, mode: AddIOverflow) -> Self {
+        self.comp.add_i_overflow = mode;
+        self
+    }
+    pub fn with_random(mut self, mode: RandomMode) -> Self {
+        self.comp.random = mode;
+        self
+    }
+    pub fn with_draw_origin(mut self, mode: DrawOrigin) -> Self {
+        self.comp.draw_origin = mode;
+        self
+    }
+    pub fn with_horizontal_edge(mut self, edge: SpriteEdge) -> Self {
+        self.comp.horizontal_edge = edge;
+        self
+    }
+    pub fn with_vertical_edge(mut self, edge: SpriteEdge) -> Self {
+        self.comp.vertical_edge = edge;
+        self
+    }
+
+    /// Behave like the ETI-660's CHIP-8 interpreter, which is the original one
+    /// on a 64x48 screen and loads programs at 0x600
+    pub fn eti660_preset() -> Self {
+        Self::chip8_preset()
+            .with_lores_size(LoresSize::Eti660)
+            .with_program_start(0x600)
+    }
+
+    pub fn with_debug_print(mut self, enabled: bool) -> Self {
+        self.comp.debug_print = enabled;
+        self
+    }
+    /// Clamped to at least one voice and at most [`MAX_VOICES`](crate::audio::MAX_VOICES)
+    pub fn with_audio_voices(mut self, voices: u8) -> Self {
+        self.comp.audio_voices = voices.clamp(1, crate::audio::MAX_VOICES as u8);
+        self
+    }
+
+    pub fn with_lores_size(mut self, size: LoresSize) -> Self {
+        self.comp.lores_size = size;
+        self
+    }
+    pub fn with_program_start(mut self, start: u16) -> Self {
+        self.comp.program_start = start;
+        self
+    }
 
     pub fn build(self) -> CompatibilityMode {
         self.comp
     }
 }
+impl Default for CompBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ShiftMode {
     /// Shift Vy into Vx, leaving Vy unchanged
     Original,
@@ -62,7 +210,8 @@ pub enum ShiftMode {
     SuperChip,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LoadStoreMode {
     /// Leave I incremented after load/store instruction
     Original,
@@ -70,7 +219,8 @@ pub enum LoadStoreMode {
     SuperChip,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AddressSpace {
     /// Treat I as 12-bit pointer, modulo 4096
     Original,
@@ -78,7 +228,8 @@ pub enum AddressSpace {
     XOChip,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AllowedInstructions {
     /// Allow only instructions found on the original Chip8
     Original = 0,
@@ -94,9 +245,19 @@ impl AllowedInstructions {
 
         is >= needs
     }
+
+    /// How many user flags FX75 and FX85 can access
+    pub fn user_flag_count(self) -> usize {
+        match self {
+            AllowedInstructions::Original => 0,
+            AllowedInstructions::SuperChip => 8,
+            AllowedInstructions::XOChip => 16,
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RelativeJumpMode {
     /// Always use V0 as the base register
     Original,
@@ -104,10 +265,106 @@ pub enum RelativeJumpMode {
     SuperChip,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CollisionEnumeration {
     /// Set VF equal to one if collision occured, otherwise 0
     Original,
-    /// Set VF equal to the amount of collisions that occured
+    /// In hires mode, set VF to the number of sprite rows that collided or were clipped at the bottom
+    SuperChip,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoresScrollMode {
+    /// Scroll by whole lores pixels, as SCHIP 1.0 and most modern interpreters do
+    Whole,
+    /// Scroll by half a lores pixel, as SCHIP 1.1 does by scrolling the underlying hires buffer
+    Half,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LargeSpriteMode {
+    /// DXY0 draws 16x16 sprites in hires mode, but 8x16 sprites in lores mode
     SuperChip,
+    /// DXY0 draws 16x16 sprites in both modes
+    XOChip,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddIOverflow {
+    /// FX1E leaves VF unchanged
+    Ignore,
+    /// FX1E sets VF to 1 if I overflows above 0xFFF and to 0 otherwise, as the Amiga interpreter does
+    Amiga,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RandomMode {
+    /// Draw random numbers from a seeded ChaCha generator
+    Modern,
+    /// Use the COSMAC VIP interpreter's routine, which steps a 16-bit seed through the bytes of low memory
+    Vip,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrawOrigin {
+    /// Take the sprite origin modulo the screen size, so a sprite at X 70 in lores appears at 6
+    Wrap,
+    /// Draw from the coordinate as given, so in hires mode a sprite starting off the screen is clipped
+    Raw,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpriteEdge {
+    /// Clip the parts of sprites beyond the edge in hires mode and wrap them around in lores mode
+    Auto,
+    /// Wrap sprites around to the opposite edge
+    Wrap,
+    /// Cut sprites off at the edge
+    Clip,
+}
+impl SpriteEdge {
+    /// Whether sprites are clipped at this edge in the given mode
+    pub fn clips(self, lores: bool) -> bool {
+        match self {
+            SpriteEdge::Auto => !lores,
+            SpriteEdge::Wrap => false,
+            SpriteEdge::Clip => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LoresSize {
+    /// 64x32 pixels, as on the COSMAC VIP
+    #[default]
+    #[serde(rename = "64x32")]
+    Standard,
+    /// 64x48 pixels, as on the ETI-660. There is no whole vertical scale from 48 rows to the
+    /// 64 of the screen, so pixels are only as tall as hires pixels and the bottom rows stay blank.
+    #[serde(rename = "64x48")]
+    Eti660,
+    /// 64x64 pixels, as the two-page display of the VIP's hires CHIP-8 variant. Pixels are only
+    /// as tall as hires pixels.
+    #[serde(rename = "64x64")]
+    Square,
+}
+impl LoresSize {
+    pub fn rows(self) -> usize {
+        match self {
+            LoresSize::Standard => 32,
+            LoresSize::Eti660 => 48,
+            LoresSize::Square => 64,
+        }
+    }
+    /// How many physical screen pixels wide and tall a lores pixel is
+    pub fn scale(self) -> (usize, usize) {
+        (2, HEIGHT / self.rows())
+    }
 }
@@ -1,3 +1,4 @@
+use std::fmt::{self, Display, Formatter};
 use super::comp_mode::AllowedInstructions;
 
 
@@ -52,7 +53,18 @@ pub enum Instruction {
     LoadUserFlags(Register),
 
     // Here begin the XO-Chip instructions
-    // todo
+    /// FN01: Select the bit planes in the mask N for drawing, clearing and scrolling
+    SelectPlanes(Constant),
+    /// F002: Load the 16 bytes at I into the audio pattern buffer, or 16 bytes per voice with multiple voices
+    LoadAudio,
+    /// FX3A: Set the pitch register to VX
+    StorePitch(Register),
+
+    // Here begin chippy's opt-in debugging pseudo-instructions, taken from the 0NNN space
+    /// 01XY: Print the registers VX through VY
+    DebugRegisters(Register, Register),
+    /// 0200: Print the zero-terminated string at I
+    DebugString,
 }
 impl Instruction {
     pub fn decode(bytes: &[u8]) -> Option<Instruction> {
@@ -73,6 +85,8 @@ impl Instruction {
             [0x0, 0x0, 0xF, 0xD] => Instruction::Exit,
             [0x0, 0x0, 0xF, 0xE] => Instruction::LoRes,
             [0x0, 0x0, 0xF, 0xF] => Instruction::HiRes,
+            [0x0, 0x1,   a,   b] => Instruction::DebugRegisters(Register(a), Register(b)),
+            [0x0, 0x2, 0x0, 0x0] => Instruction::DebugString,
             [0x1,   _,   _,   _] => Instruction::Jump(nnn),
             [0x2,   _,   _,   _] => Instruction::Call(nnn),
             [0x3,   _,   _,   _] => Instruction::SkipEqualConstant(x, kk),
@@ -96,6 +110,8 @@ impl Instruction {
             [0xD,   _,   _,   _] => Instruction::Draw(x, y, n),
             [0xE,   _, 0x9, 0xE] => Instruction::SkipPressed(x),
             [0xE,   _, 0xA, 0x1] => Instruction::SkipNotPressed(x),
+            [0xF,   a, 0x0, 0x1] => Instruction::SelectPlanes(Constant(a)),
+            [0xF, 0x0, 0x0, 0x2] => Instruction::LoadAudio,
             [0xF,   _, 0x0, 0x7] => Instruction::LoadDelay(x),
             [0xF,   _, 0x0, 0xA] => Instruction::WaitForKey(x),
             [0xF,   _, 0x1, 0x5] => Instruction::StoreDelay(x),
@@ -104,6 +120,7 @@ impl Instruction {
             [0xF,   _, 0x2, 0x9] => Instruction::LoadSprite(x),
             [0xF,   _, 0x3, 0x0] => Instruction::LoadLargeSprite(x),
             [0xF,   _, 0x3, 0x3] => Instruction::StoreBCD(x),
+            [0xF,   _, 0x3, 0xA] => Instruction::StorePitch(x),
             [0xF,   _, 0x5, 0x5] => Instruction::Store(x),
             [0xF,   _, 0x6, 0x5] => Instruction::Load(x),
             [0xF,   _, 0x7, 0x5] => Instruction::StoreUserFlags(x),
@@ -160,16 +177,99 @@ impl Instruction {
             LoadLargeSprite(_) => SuperChip,
             StoreUserFlags(_) => SuperChip,
             LoadUserFlags(_) => SuperChip,
+
+            SelectPlanes(_) => XOChip,
+            LoadAudio => XOChip,
+            StorePitch(_) => XOChip,
+            DebugRegisters(_, _) => Original,
+            DebugString => Original,
         }
     }
 
+    /// Whether executing the instruction changes what is on the screen or its resolution
+    pub fn changes_screen(&self) -> bool {
+        use Instruction::*;
+        matches!(self, ClearScreen | Draw(..) | ScrollDown(_) | ScrollRight | ScrollLeft | LoRes | HiRes)
+    }
+
+    pub fn is_debug_print(&self) -> bool {
+        matches!(self, Instruction::DebugRegisters(..) | Instruction::DebugString)
+    }
+
     pub fn length(&self) -> u16 {
-        match self {
-            _ => 2,
+        2
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            ClearScreen => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            Jump(nnn) => write!(f, "JP {}", nnn),
+            Call(nnn) => write!(f, "CALL {}", nnn),
+            SkipEqualConstant(x, kk) => write!(f, "SE {}, {}", x, kk),
+            SkipNotEqualConstant(x, kk) => write!(f, "SNE {}, {}", x, kk),
+            SkipEqual(x, y) => write!(f, "SE {}, {}", x, y),
+            Set(x, kk) => write!(f, "LD {}, {}", x, kk),
+            SetSum(x, kk) => write!(f, "ADD {}, {}", x, kk),
+            Mov(x, y) => write!(f, "LD {}, {}", x, y),
+            Or(x, y) => write!(f, "OR {}, {}", x, y),
+            And(x, y) => write!(f, "AND {}, {}", x, y),
+            Xor(x, y) => write!(f, "XOR {}, {}", x, y),
+            Add(x, y) => write!(f, "ADD {}, {}", x, y),
+            Sub(x, y) => write!(f, "SUB {}, {}", x, y),
+            ShiftRight(x, y) => write!(f, "SHR {}, {}", x, y),
+            RevSub(x, y) => write!(f, "SUBN {}, {}", x, y),
+            ShiftLeft(x, y) => write!(f, "SHL {}, {}", x, y),
+            SkipNotEqual(x, y) => write!(f, "SNE {}, {}", x, y),
+            LoadI(nnn) => write!(f, "LD I, {}", nnn),
+            JumpRelative(nnn) => write!(f, "JP V0, {}", nnn),
+            Random(x, kk) => write!(f, "RND {}, {}", x, kk),
+            Draw(x, y, n) => write!(f, "DRW {}, {}, {}", x, y, n.0),
+            SkipPressed(x) => write!(f, "SKP {}", x),
+            SkipNotPressed(x) => write!(f, "SKNP {}", x),
+            LoadDelay(x) => write!(f, "LD {}, DT", x),
+            WaitForKey(x) => write!(f, "LD {}, K", x),
+            StoreDelay(x) => write!(f, "LD DT, {}", x),
+            StoreSound(x) => write!(f, "LD ST, {}", x),
+            AddI(x) => write!(f, "ADD I, {}", x),
+            LoadSprite(x) => write!(f, "LD F, {}", x),
+            StoreBCD(x) => write!(f, "LD B, {}", x),
+            Store(x) => write!(f, "LD [I], {}", x),
+            Load(x) => write!(f, "LD {}, [I]", x),
+            ScrollDown(n) => write!(f, "SCD {}", n.0),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LoRes => write!(f, "LOW"),
+            HiRes => write!(f, "HIGH"),
+            LoadLargeSprite(x) => write!(f, "LD HF, {}", x),
+            StoreUserFlags(x) => write!(f, "LD R, {}", x),
+            LoadUserFlags(x) => write!(f, "LD {}, R", x),
+            SelectPlanes(n) => write!(f, "PLANE {}", n.0),
+            LoadAudio => write!(f, "AUDIO [I]"),
+            StorePitch(x) => write!(f, "PITCH {}", x),
+            DebugRegisters(x, y) => write!(f, "DBG {}-{}", x, y),
+            DebugString => write!(f, "DBG [I]"),
         }
     }
 }
 
+/// Disassembles the instruction at the start of `bytes`, returning its text and length.
+/// Bytes that don't form a valid instruction are shown as data.
+pub fn disassemble(bytes: &[u8]) -> (String, u16) {
+    match bytes {
+        [] => (String::from("??"), 1),
+        &[byte] => (format!("DB 0x{:02X}", byte), 1),
+        _ => match Instruction::decode(bytes) {
+            Some(i) => (i.to_string(), i.length()),
+            None => (format!("DW 0x{:02X}{:02X}", bytes[0], bytes[1]), 2),
+        },
+    }
+}
+
 fn extract_x(bytes: &[u8]) -> Register {
     let x = bytes[0] & 0x0F;
     Register(x)
@@ -208,3 +308,19 @@ pub struct Constant(pub u8);
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Address(pub u16);
+
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "V{:X}", self.0)
+    }
+}
+impl Display for Constant {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "0x{:02X}", self.0)
+    }
+}
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "0x{:03X}", self.0)
+    }
+}
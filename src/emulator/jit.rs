@@ -0,0 +1,361 @@
+//! A just-in-time compiler for straight-line CHIP-8 code.
+//!
+//! Runs of register arithmetic and I updates are compiled to native code with cranelift, everything
+//! else still goes through the interpreter. Compiled blocks remember the bytes they were compiled
+//! from and are checked against memory before they run, so code the program overwrote is
+//! interpreted from then on.
+
+use std::{collections::HashMap, mem};
+use cranelift_codegen::{ir::{condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags, Value}, settings::{self, Configurable}};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+use tracing::{debug, warn};
+use super::{comp_mode::{AddIOverflow, AddressSpace, CompatibilityMode}, instruction::{Address, Constant, Instruction, Register}, keys::Keys, machine::{Machine, StepResult}};
+
+/// Longest run of instructions compiled into one block
+const MAX_BLOCK_LENGTH: usize = 64;
+
+/// A compiled block, called with pointers to the 16 registers and to I
+type BlockFn = unsafe extern "C" fn(*mut u8, *mut u16);
+
+
+struct Block {
+    function: BlockFn,
+    /// The code the block was compiled from
+    bytes: Vec<u8>,
+    /// Number of instructions in the block
+    length: usize,
+}
+
+
+pub struct Jit {
+    module: JITModule,
+    /// Compiled blocks by start address, `None` where the code can't be compiled or was modified
+    blocks: HashMap<u16, Option<Block>>,
+    /// The compatibility mode the blocks were compiled for
+    comp: Option<CompatibilityMode>,
+}
+impl Jit {
+    pub fn new() -> Result<Self, String> {
+        let mut flags = settings::builder();
+        flags.set("use_colocated_libcalls", "false").map_err(|e| e.to_string())?;
+        flags.set("is_pic", "false").map_err(|e| e.to_string())?;
+        let isa = cranelift_native::builder()?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            module: JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())),
+            blocks: HashMap::new(),
+            comp: None,
+        })
+    }
+
+    /// Executes up to `budget` instructions like [`Machine::step_n`], running compiled blocks where possible
+    pub fn run(&mut self, machine: &mut Machine, comp: &CompatibilityMode, keys: &Keys, budget: usize) -> StepResult {
+        self.run_with(machine, comp, keys, budget, |machine| {
+            machine.decode_and_execute(comp, keys);
+            true
+        })
+    }
+    /// Like [`Jit::run`], but has `interpret` execute the single instructions that aren't compiled,
+    /// so a debugger can record them. It returns whether to go on.
+    pub fn run_with(&mut self, machine: &mut Machine, comp: &CompatibilityMode, keys: &Keys, budget: usize, mut interpret: impl FnMut(&mut Machine) -> bool) -> StepResult {
+        if self.comp != Some(*comp) {
+            self.blocks.clear();
+            self.comp = Some(*comp);
+        }
+
+        let mut result = StepResult::default();
+        while result.executed < budget && !machine.has_exited() && !machine.is_blocked(keys) {
+            let remaining = budget - result.executed;
+            if let Some(length) = self.run_block(machine, comp, remaining) {
+                result.executed += length;
+                continue;
+            }
+
+            let changes_screen = !machine.is_skipping() && machine.next_instruction().is_some_and(|i| i.changes_screen());
+            let go_on = interpret(machine);
+            result.executed += 1;
+            result.screen_changed |= changes_screen;
+            if !go_on {
+                break;
+            }
+        }
+        result.exited = machine.has_exited();
+        result.sound_playing = machine.is_sound_playing();
+        result
+    }
+
+    /// Runs the block at the PC if there is one that fits in `remaining` instructions,
+    /// returning how many instructions it executed
+    fn run_block(&mut self, machine: &mut Machine, comp: &CompatibilityMode, remaining: usize) -> Option<usize> {
+        if machine.is_skipping() || machine.waiting_for_key().is_some() {
+            return None;
+        }
+        let pc = machine.pc();
+        if !self.blocks.contains_key(&pc) {
+            let block = self.compile(machine.memory(), pc, comp);
+            self.blocks.insert(pc, block);
+        }
+
+        let entry = self.blocks.get_mut(&pc).unwrap();
+        let block = entry.as_ref()?;
+        if block.length > remaining {
+            return None;
+        }
+        let start = pc as usize;
+        if machine.memory()[start..start + block.bytes.len()] != block.bytes[..] {
+            debug!("Code at {:03X} was modified, interpreting it from now on", pc);
+            *entry = None;
+            return None;
+        }
+
+        let (function, length) = (block.function, block.length);
+        let (registers, i) = machine.jit_state();
+        // SAFETY: blocks only access the 16 registers and I through the pointers they are given
+        unsafe { function(registers.as_mut_ptr(), i) };
+        machine.set_pc(pc + length as u16 * 2);
+        machine.count_compiled(pc, length);
+        Some(length)
+    }
+
+    fn compile(&mut self, memory: &[u8], pc: u16, comp: &CompatibilityMode) -> Option<Block> {
+        let start = pc as usize;
+        let instructions: Vec<_> = memory[start..].chunks_exact(2)
+            .take(MAX_BLOCK_LENGTH)
+            .map_while(|bytes| Instruction::decode(bytes).filter(is_compilable))
+            .collect();
+        if instructions.is_empty() {
+            return None;
+        }
+
+        match self.build(&instructions, comp) {
+            Ok(function) => Some(Block {
+                function,
+                bytes: memory[start..start + instructions.len() * 2].to_vec(),
+                length: instructions.len(),
+            }),
+            Err(e) => {
+                warn!("Could not compile block at {:03X}: {}", pc, e);
+                None
+            }
+        }
+    }
+
+    fn build(&mut self, instructions: &[Instruction], comp: &CompatibilityMode) -> Result<BlockFn, String> {
+        let mut context = self.module.make_context();
+        let pointer = self.module.target_config().pointer_type();
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context.func.signature.params.push(AbiParam::new(pointer));
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let mut emitter = Emitter {
+            registers: builder.block_params(entry)[0],
+            i: builder.block_params(entry)[1],
+            builder,
+        };
+        for instruction in instructions {
+            emitter.emit(instruction, comp);
+        }
+        emitter.builder.ins().return_(&[]);
+        emitter.builder.finalize();
+
+        let id = self.module.declare_anonymous_function(&context.func.signature).map_err(|e| e.to_string())?;
+        self.module.define_function(id, &mut context).map_err(|e| e.to_string())?;
+        self.module.clear_context(&mut context);
+        self.module.finalize_definitions().map_err(|e| e.to_string())?;
+
+        let code = self.module.get_finalized_function(id);
+        // SAFETY: the function was built with the signature of `BlockFn`
+        Ok(unsafe { mem::transmute::<*const u8, BlockFn>(code) })
+    }
+}
+
+
+/// Whether the JIT can compile the instruction. Anything touching memory, the screen, timers
+/// or control flow is left to the interpreter.
+fn is_compilable(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(instruction, Set(..) | SetSum(..) | Mov(..) | Or(..) | And(..) | Xor(..) | Add(..) | Sub(..) | RevSub(..) | LoadI(_) | AddI(_))
+}
+
+
+/// Translates instructions into cranelift IR, reading and writing the registers in memory
+struct Emitter<'a> {
+    builder: FunctionBuilder<'a>,
+    registers: Value,
+    i: Value,
+}
+impl Emitter<'_> {
+    fn emit(&mut self, instruction: &Instruction, comp: &CompatibilityMode) {
+        use Instruction::*;
+        match *instruction {
+            Set(x, Constant(kk)) => {
+                let value = self.builder.ins().iconst(types::I8, kk as i64);
+                self.store(x, value);
+            }
+            SetSum(x, Constant(kk)) => {
+                let vx = self.load(x);
+                let sum = self.builder.ins().iadd_imm(vx, kk as i64);
+                self.store(x, sum);
+            }
+            Mov(x, y) => {
+                let vy = self.load(y);
+                self.store(x, vy);
+            }
+            Or(x, y) => self.binary(x, y, |b, vx, vy| b.ins().bor(vx, vy)),
+            And(x, y) => self.binary(x, y, |b, vx, vy| b.ins().band(vx, vy)),
+            Xor(x, y) => self.binary(x, y, |b, vx, vy| b.ins().bxor(vx, vy)),
+            Add(x, y) => {
+                let (vx, vy) = (self.load(x), self.load(y));
+                let sum = self.builder.ins().iadd(vx, vy);
+                let carry = self.builder.ins().icmp(IntCC::UnsignedLessThan, sum, vx);
+                self.store(x, sum);
+                self.store(Register(0xF), carry);
+            }
+            Sub(x, y) => self.subtract(x, x, y),
+            RevSub(x, y) => self.subtract(x, y, x),
+            LoadI(Address(nnn)) => {
+                let value = self.builder.ins().iconst(types::I16, nnn as i64);
+                self.store_i(value);
+            }
+            AddI(x) => {
+                let vx = self.load(x);
+                let vx = self.builder.ins().uextend(types::I16, vx);
+                let i = self.builder.ins().load(types::I16, MemFlags::trusted(), self.i, 0);
+                let mut sum = self.builder.ins().iadd(i, vx);
+                if comp.add_i_overflow == AddIOverflow::Amiga {
+                    let overflow = self.builder.ins().icmp_imm(IntCC::UnsignedGreaterThan, sum, 0xFFF);
+                    self.store(Register(0xF), overflow);
+                }
+                if comp.address_space == AddressSpace::Original {
+                    sum = self.builder.ins().band_imm(sum, 0xFFF);
+                }
+                self.store_i(sum);
+            }
+            _ => unreachable!("{:?} is not compilable", instruction),
+        }
+    }
+
+    /// VX = minuend - subtrahend, with VF set if there was no borrow
+    fn subtract(&mut self, x: Register, minuend: Register, subtrahend: Register) {
+        let (a, b) = (self.load(minuend), self.load(subtrahend));
+        let difference = self.builder.ins().isub(a, b);
+        let no_borrow = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, a, b);
+        self.store(x, difference);
+        self.store(Register(0xF), no_borrow);
+    }
+    fn binary(&mut self, x: Register, y: Register, op: impl FnOnce(&mut FunctionBuilder, Value, Value) -> Value) {
+        let (vx, vy) = (self.load(x), self.load(y));
+        let result = op(&mut self.builder, vx, vy);
+        self.store(x, result);
+    }
+
+    fn load(&mut self, register: Register) -> Value {
+        self.builder.ins().load(types::I8, MemFlags::trusted(), self.registers, register.0 as i32)
+    }
+    fn store(&mut self, register: Register, value: Value) {
+        self.builder.ins().store(MemFlags::trusted(), value, self.registers, register.0 as i32);
+    }
+    fn store_i(&mut self, value: Value) {
+        self.builder.ins().store(MemFlags::trusted(), value, self.i, 0);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::comp_mode::CompBuilder;
+
+    fn machine(program: &[u8]) -> Machine {
+        let mut machine = Machine::new(0);
+        machine.load_sprites();
+        machine.load_program(program, 0x200).unwrap();
+        machine.init_instruction_pointer(0x200);
+        machine
+    }
+
+    /// Runs `instructions` of `program` compiled and interpreted, checking that both end in the same state
+    fn assert_matches_interpreter(program: &[u8], comp: &CompatibilityMode) -> Machine {
+        let instructions = program.len() / 2;
+        let keys = Keys::new();
+
+        let mut interpreted = machine(program);
+        interpreted.step_n(instructions, comp, &keys);
+
+        let mut compiled = machine(program);
+        let mut jit = Jit::new().unwrap();
+        let result = jit.run(&mut compiled, comp, &keys, instructions);
+        assert_eq!(result.executed, instructions);
+
+        assert_eq!(compiled.registers(), interpreted.registers(), "registers of {:02X?}", program);
+        assert_eq!(compiled.i(), interpreted.i(), "I of {:02X?}", program);
+        assert_eq!(compiled.pc(), interpreted.pc(), "PC of {:02X?}", program);
+        compiled
+    }
+
+    #[test]
+    fn add_sets_the_carry_like_the_interpreter() {
+        let comp = CompBuilder::new().build();
+        // With and without carry
+        let machine = assert_matches_interpreter(&[0x60, 0xFF, 0x61, 0x02, 0x80, 0x14], &comp);
+        assert_eq!(machine.registers()[0xF], 1);
+        let machine = assert_matches_interpreter(&[0x60, 0x10, 0x61, 0x02, 0x80, 0x14], &comp);
+        assert_eq!(machine.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn sub_and_rev_sub_set_the_borrow_like_the_interpreter() {
+        let comp = CompBuilder::new().build();
+        for operation in [0x15, 0x17] {
+            for (a, b) in [(0x05, 0x07), (0x07, 0x05), (0x05, 0x05)] {
+                assert_matches_interpreter(&[0x60, a, 0x61, b, 0x80, operation], &comp);
+            }
+        }
+    }
+
+    #[test]
+    fn add_i_overflows_like_the_interpreter() {
+        // I = 0xFF0 + 0x20 crosses 0xFFF
+        let program = [0xAF, 0xF0, 0x60, 0x20, 0xF0, 0x1E];
+        let amiga = || CompBuilder::new().with_add_i_overflow(AddIOverflow::Amiga);
+
+        let machine = assert_matches_interpreter(&program, &amiga().build());
+        assert_eq!(machine.registers()[0xF], 1);
+        assert_eq!(machine.i(), 0x010);
+
+        let machine = assert_matches_interpreter(&program, &amiga().with_address_space(AddressSpace::XOChip).build());
+        assert_eq!(machine.registers()[0xF], 1);
+        assert_eq!(machine.i(), 0x1010);
+
+        let machine = assert_matches_interpreter(&program, &CompBuilder::new().with_address_space(AddressSpace::Original).build());
+        assert_eq!(machine.registers()[0xF], 0);
+        assert_eq!(machine.i(), 0x010);
+    }
+
+    #[test]
+    fn modified_blocks_are_interpreted() {
+        let comp = CompBuilder::new().build();
+        let keys = Keys::new();
+        let mut machine = machine(&[0x60, 0x01, 0x61, 0x02]);
+        let mut jit = Jit::new().unwrap();
+
+        jit.run(&mut machine, &comp, &keys, 2);
+        assert_eq!(&machine.registers()[..2], &[0x01, 0x02]);
+
+        machine.poke(0x201, 0x05);
+        machine.set_pc(0x200);
+        jit.run(&mut machine, &comp, &keys, 2);
+        assert_eq!(&machine.registers()[..2], &[0x05, 0x02]);
+        assert!(jit.blocks[&0x200].is_none());
+    }
+}
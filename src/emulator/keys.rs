@@ -1,5 +1,7 @@
+use serde::{Serialize, Deserialize};
 
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Keys {
     key_values: [bool; 16],
 }
@@ -13,8 +15,17 @@ impl Keys {
         assert!(k < 16);
         self.key_values[k as usize]
     }
+    pub fn any_pressed(&self) -> bool {
+        self.key_values.contains(&true)
+    }
     pub fn set_key(&mut self, k: u8, pressed: bool) {
         assert!(k < 16);
         self.key_values[k as usize] = pressed;
     }
 }
+impl Default for Keys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
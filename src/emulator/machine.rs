@@ -1,30 +1,109 @@
-use std::{io::{Write, self, stderr}, ops::{Index, IndexMut}};
+use std::{any::Any, collections::BTreeSet, fmt::{self, Display, Formatter}, io::{Write, self}, ops::{Index, IndexMut, Range}, panic::{self, AssertUnwindSafe}, sync::Arc};
 use rand::prelude::*;
-use super::{screen::Screen, instruction::{Instruction, Address, Register, Constant}, comp_mode::{CompatibilityMode, ShiftMode, LoadStoreMode, AddressSpace, RelativeJumpMode}, keys::Keys};
+use rand_chacha::ChaCha12Rng;
+use serde::{Serialize, Deserialize};
+use tracing::{debug, info};
+use crate::audio::Pattern;
+use super::{screen::{Screen, SpriteDraw, MAX_SPRITE_SIZE, PLANES, WIDTH}, instruction::{Instruction, Address, Register, Constant}, comp_mode::{CompatibilityMode, AddIOverflow, DrawOrigin, RandomMode, ShiftMode, LoadStoreMode, AddressSpace, RelativeJumpMode, LoresScrollMode, LoresSize, CollisionEnumeration}, keys::Keys, big_array};
 
 const MEMORY_SIZE: usize = 2usize.pow(16);
+/// The most user flags any variant has
+const USER_FLAGS: usize = 16;
+/// Bytes of an XO-Chip audio pattern, one bit per sample
+pub const PATTERN_SIZE: usize = 16;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Machine {
     cpu: CPU,
     stack: Vec<u16>,
+    /// Set once the program executed the exit instruction
+    exited: bool,
+    /// The register an FX0A instruction will store the next pressed key in
+    waiting_for_key: Option<u8>,
+    /// Persistent flag registers of SuperChip and XO-Chip, kept across resets
+    user_flags: [u8; USER_FLAGS],
+    #[serde(with = "big_array::boxed")]
     memory: Box<[u8; MEMORY_SIZE]>,
     screen: Screen,
-    rng: StdRng,
+    rng: ChaCha12Rng,
+    /// State of the COSMAC VIP random routine, low byte first
+    vip_seed: [u8; 2],
+    /// The XO-Chip audio pattern of each voice last loaded by F002, the buzzer plays the plain tone until then
+    audio_patterns: Vec<[u8; PATTERN_SIZE]>,
+    #[serde(skip)]
+    draw_log: Option<Vec<SpriteDraw>>,
+    #[serde(skip)]
+    write_log: Option<Vec<MemoryWrite>>,
+    /// Addresses of executed instructions, for coverage
+    #[serde(skip)]
+    execution_log: Option<Vec<u16>>,
+    /// Lines printed by debug print instructions that the frontend has yet to show
+    #[serde(skip)]
+    debug_output: Vec<String>,
+    /// Changes of the screen mode that the frontend has yet to take
+    #[serde(skip)]
+    screen_events: Vec<ScreenEvent>,
+    /// Handlers for opcodes the interpreter doesn't know, registered by library users
+    #[serde(skip)]
+    extensions: Vec<OpcodeExtension>,
+    /// Activity since the frontend last took the counters
+    #[serde(skip)]
+    counters: Counters,
+    /// Instructions executed by [`Machine::run_until`] since its last frame ended
+    #[serde(skip)]
+    frame_progress: usize,
+    /// Resolution of lores mode in the quirks of the last executed instruction, for resets and dumps
+    #[serde(skip)]
+    lores_size: LoresSize,
+    /// The program as last loaded, so it can be restored on reset
+    program: Vec<u8>,
+    program_start: usize,
 }
 impl Machine {
     pub fn new(rng_seed: u64) -> Machine {
         Machine {
             cpu: CPU::new(),
             stack: Vec::new(),
+            exited: false,
+            waiting_for_key: None,
+            user_flags: [0; USER_FLAGS],
             memory: Box::new([0; MEMORY_SIZE]),
             screen: Screen::new(),
-            rng: StdRng::seed_from_u64(rng_seed),
+            rng: ChaCha12Rng::seed_from_u64(rng_seed),
+            vip_seed: [rng_seed as u8, (rng_seed >> 8) as u8],
+            audio_patterns: Vec::new(),
+            draw_log: None,
+            write_log: None,
+            debug_output: Vec::new(),
+            execution_log: None,
+            screen_events: Vec::new(),
+            extensions: Vec::new(),
+            counters: Counters::default(),
+            frame_progress: 0,
+            lores_size: LoresSize::Standard,
+            program: Vec::new(),
+            program_start: 0,
         }
     }
 
     pub fn decode_and_execute(&mut self, comp: &CompatibilityMode, keys: &Keys) {
+        if self.exited {
+            return;
+        }
+        if self.waiting_for_key.is_some() {
+            self.poll_key(keys);
+            return;
+        }
+
+        if let Some((opcode, handler)) = self.extension_at_pc(comp) {
+            self.execute_extension(opcode, handler);
+            return;
+        }
+
         let instruction = self.decode();
         self.assert_legal(&instruction, comp);
+        self.lores_size = comp.lores_size;
+        self.counters.instructions += 1;
 
         self.cpu.ip += instruction.length();
 
@@ -32,21 +111,131 @@ impl Machine {
         self.cpu.skip = false;
 
         if !skip {
+            if let Some(log) = &mut self.execution_log {
+                log.push(self.cpu.ip - instruction.length());
+            }
             self.execute(instruction, comp, keys);
         }
     }
+    /// Executes up to `n` instructions, stopping early if the program exits or waits for a key
+    pub fn step_n(&mut self, n: usize, comp: &CompatibilityMode, keys: &Keys) -> StepResult {
+        let mut result = StepResult::default();
+        for _ in 0..n {
+            if self.exited || self.is_blocked(keys) {
+                break;
+            }
+            let changes_screen = !self.cpu.skip && self.next_instruction().is_some_and(|i| i.changes_screen());
+            self.decode_and_execute(comp, keys);
+            result.executed += 1;
+            result.screen_changed |= changes_screen;
+        }
+        result.exited = self.exited;
+        result.sound_playing = self.is_sound_playing();
+        result
+    }
+    /// Runs one frame of `instructions` instructions, then ticks the timers once
+    pub fn run_frame(&mut self, instructions: usize, comp: &CompatibilityMode, keys: &Keys) -> StepResult {
+        let mut result = self.step_n(instructions, comp, keys);
+        self.decrement_counters();
+        result.sound_playing = self.is_sound_playing();
+        result
+    }
+
+    /// Executes instructions until one of the stop conditions holds, the program exits, waits for a key
+    /// or fails. Failures that would otherwise panic are caught and returned as [`StopReason::Error`],
+    /// leaving the machine as the failing instruction left it; the panic hook still sees them.
+    pub fn run_until(&mut self, comp: &CompatibilityMode, keys: &Keys, condition: &StopCondition) -> (StopReason, StepResult) {
+        let mut result = StepResult::default();
+        let reason = panic::catch_unwind(AssertUnwindSafe(|| self.run_until_unguarded(comp, keys, condition, &mut result)))
+            .unwrap_or_else(|payload| StopReason::Error(panic_message(payload)));
+        result.exited = self.exited;
+        result.sound_playing = self.is_sound_playing();
+        (reason, result)
+    }
+    fn run_until_unguarded(&mut self, comp: &CompatibilityMode, keys: &Keys, condition: &StopCondition, result: &mut StepResult) -> StopReason {
+        loop {
+            if self.exited {
+                return StopReason::Exited;
+            }
+            if let Some(instructions) = condition.frame {
+                // A blocked program can't use the rest of its frame, the timers go on regardless
+                if self.frame_progress >= instructions || self.is_blocked(keys) {
+                    self.frame_progress = 0;
+                    self.decrement_counters();
+                    return StopReason::FrameEnd;
+                }
+            }
+            if self.is_blocked(keys) {
+                return StopReason::Blocked;
+            }
+            if result.executed > 0 && condition.breakpoints.contains(&self.cpu.ip) {
+                return StopReason::Breakpoint(self.cpu.ip);
+            }
+            if condition.instructions.is_some_and(|n| result.executed >= n) {
+                return StopReason::Instructions;
+            }
+
+            let draws = self.counters.draws;
+            let changes_screen = !self.cpu.skip && self.next_instruction().is_some_and(|i| i.changes_screen());
+            self.decode_and_execute(comp, keys);
+            self.frame_progress += 1;
+            result.executed += 1;
+            result.screen_changed |= changes_screen;
+
+            if condition.draw && self.counters.draws != draws {
+                return StopReason::Draw;
+            }
+        }
+    }
+
+    /// Finds the registered handler for the opcode at the PC, if it isn't a known instruction.
+    /// Debug prints only count as instructions while they are enabled, so extensions may claim them otherwise.
+    fn extension_at_pc(&self, comp: &CompatibilityMode) -> Option<(u16, OpcodeHandler)> {
+        if self.extensions.is_empty() {
+            return None;
+        }
+        let ip = self.cpu.ip as usize;
+        let bytes = self.memory.get(ip..ip + 2)?;
+        if Instruction::decode(bytes).is_some_and(|i| !i.is_debug_print() || comp.debug_print) {
+            return None;
+        }
+        let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+        self.extensions.iter()
+            .rev()
+            .find(|e| opcode & e.mask == e.pattern)
+            .map(|e| (opcode, e.handler.clone()))
+    }
+    fn execute_extension(&mut self, opcode: u16, handler: OpcodeHandler) {
+        self.counters.instructions += 1;
+        self.cpu.ip += 2;
+
+        let skip = self.cpu.skip;
+        self.cpu.skip = false;
+
+        if !skip {
+            if let Some(log) = &mut self.execution_log {
+                log.push(self.cpu.ip - 2);
+            }
+            handler(self, opcode);
+        }
+    }
     fn decode(&self) -> Instruction {
         let instruction = Instruction::decode(&self.memory[self.cpu.ip as usize..]);
 
         let Some(instruction) = instruction else {
             let ip = self.cpu.ip as usize;
-            panic!("Invalid instruction at {:x?}", &self.memory[ip..ip+4]);
+            let bytes = &self.memory[ip..(ip + 2).min(self.memory.len())];
+            panic!("Invalid instruction {:02X?} at address {:03X}", bytes, ip);
         };
 
         instruction
     }
     fn assert_legal(&self, i: &Instruction, comp: &CompatibilityMode) {
         let allowed = comp.allowed_instructions;
+        if i.is_debug_print() && !comp.debug_print {
+            let ip = self.cpu.ip as usize;
+            panic!("Debug print {:?} at address {:x} used without enabling debug prints", i, ip);
+        }
         if !allowed.is_legal(i) {
             let ip = self.cpu.ip as usize;
             panic!("Instruction {:?} at address {:x} is not legal in compatibility mode {:?}", i, ip, allowed);
@@ -58,6 +247,10 @@ impl Machine {
             ClearScreen => self.exec_clear_screen(),
             Return => self.exec_return(),
             HiRes => self.exec_hires(),
+            LoRes => self.exec_lores(),
+            ScrollDown(n) => self.exec_scroll_down(n, comp),
+            ScrollRight => self.exec_scroll_right(comp),
+            ScrollLeft => self.exec_scroll_left(comp),
             Jump(nnn) => self.exec_jump(nnn),
             Call(nnn) => self.exec_call(nnn),
             SkipEqualConstant(x, kk) => self.exec_skip_equal_constant(x, kk),
@@ -77,8 +270,8 @@ impl Machine {
             SkipNotEqual(x, y) => self.exec_skip_not_equal(x, y),
             LoadI(nnn) => self.exec_load_i(nnn),
             JumpRelative(nnn) => self.exec_jump_relative(nnn, comp),
-            Random(x, kk) => self.exec_random(x, kk),
-            Draw(x, y, n) => self.exec_draw(x, y, n),
+            Random(x, kk) => self.exec_random(x, kk, comp),
+            Draw(x, y, n) => self.exec_draw(x, y, n, comp),
             SkipNotPressed(x) => self.exec_skip_not_pressed(x, keys),
             LoadDelay(x) => self.exec_load_delay(x),
             WaitForKey(x) => self.exec_wait_for_key(x, keys),
@@ -90,13 +283,16 @@ impl Machine {
             StoreBCD(x) => self.exec_store_bcd(x),
             Store(x) => self.exec_store(x, comp),
             Load(x) => self.exec_load(x, comp),
-            StoreUserFlags(x) => self.exec_store_user_flags(x),
-            LoadUserFlags(x) => self.exec_load_user_flags(x),
+            StoreUserFlags(x) => self.exec_store_user_flags(x, comp),
+            LoadUserFlags(x) => self.exec_load_user_flags(x, comp),
+            SelectPlanes(n) => self.exec_select_planes(n),
+            LoadAudio => self.exec_load_audio(comp),
+            StorePitch(x) => self.exec_store_pitch(x),
+            Exit => self.exec_exit(),
+            DebugRegisters(x, y) => self.exec_debug_registers(x, y),
+            DebugString => self.exec_debug_string(),
 
-            _ => {
-                self.screen.write(stderr()).unwrap();
-                panic!("Unimplemented instruction {:x?} at address {:x}", i, self.cpu.ip - i.length());
-            },
+            _ => panic!("Unimplemented instruction {:x?} at address {:x}", i, self.cpu.ip - i.length()),
         }
     }
 
@@ -108,7 +304,43 @@ impl Machine {
         self.cpu.ip = ip;
     }
     fn exec_hires(&mut self) {
-        self.screen.enable_hires();
+        debug!(address = self.cpu.ip - 2, "Switched to hires mode");
+        if self.screen.is_lowres() {
+            self.screen.enable_hires();
+            self.record_resolution();
+        }
+    }
+    fn exec_lores(&mut self) {
+        debug!(address = self.cpu.ip - 2, "Switched to lores mode");
+        if !self.screen.is_lowres() {
+            self.screen.disable_hires();
+            self.record_resolution();
+        }
+    }
+    fn record_resolution(&mut self) {
+        let (width, height) = self.screen.size(self.lores_size);
+        self.screen_events.push(ScreenEvent::Resolution { address: self.cpu.ip - 2, width, height });
+    }
+    fn exec_scroll_down(&mut self, n: Constant, comp: &CompatibilityMode) {
+        let rows = n.0 as usize * self.scroll_scale(comp).1;
+        self.screen.scroll_down(rows);
+    }
+    fn exec_scroll_right(&mut self, comp: &CompatibilityMode) {
+        let columns = 4 * self.scroll_scale(comp).0;
+        self.screen.scroll_right(columns);
+    }
+    fn exec_scroll_left(&mut self, comp: &CompatibilityMode) {
+        let columns = 4 * self.scroll_scale(comp).0;
+        self.screen.scroll_left(columns);
+    }
+    /// How many physical pixels one pixel of scrolling moves the screen, horizontally and vertically
+    fn scroll_scale(&self, comp: &CompatibilityMode) -> (usize, usize) {
+        if self.screen.is_lowres() && comp.lores_scroll == LoresScrollMode::Whole {
+            comp.lores_size.scale()
+        }
+        else {
+            (1, 1)
+        }
     }
     fn exec_jump(&mut self, nnn: Address) {
         self.cpu.ip = nnn.0;
@@ -196,25 +428,47 @@ impl Machine {
         let x = self.cpu.registers[x as usize] as u16;
         self.cpu.ip = nnn + x;
     }
-    fn exec_random(&mut self, x: Register, kk: Constant) {
+    fn exec_random(&mut self, x: Register, kk: Constant, comp: &CompatibilityMode) {
         let kk = kk.0;
-        let value = self.rng.gen::<u8>() & kk;
-        self.cpu[x] = value;
+        let random = match comp.random {
+            RandomMode::Modern => self.rng.gen::<u8>(),
+            RandomMode::Vip => self.vip_random(),
+        };
+        self.cpu[x] = random & kk;
+    }
+    /// The VIP increments the low seed byte, then adds the byte of the interpreter page it points to
+    /// onto the high byte. chippy keeps the font rather than the interpreter in low memory,
+    /// so the numbers differ from real hardware, but are just as predictable.
+    fn vip_random(&mut self) -> u8 {
+        let [low, high] = &mut self.vip_seed;
+        *low = low.wrapping_add(1);
+        *high = high.wrapping_add(self.memory[*low as usize]);
+        *high
     }
-    fn exec_draw(&mut self, x: Register, y: Register, n: Constant) {
-        let x = self.cpu[x] as usize;
-        let y = self.cpu[y] as usize;
+    fn exec_draw(&mut self, x: Register, y: Register, n: Constant, comp: &CompatibilityMode) {
+        let mut x = self.cpu[x] as usize;
+        let mut y = self.cpu[y] as usize;
+        if comp.draw_origin == DrawOrigin::Wrap {
+            let (width, height) = self.screen.size(comp.lores_size);
+            x %= width;
+            y %= height;
+        }
+        // Sprites at the top of memory wrap around to the bottom, like everything read through I
         let i = self.cpu.i as usize;
-        let sprite = &self.memory[i..];
-
-        let collisions = self.screen.draw_sprite(sprite, x, y, n.0 as usize);
+        let sprite: [u8; PLANES * MAX_SPRITE_SIZE] = std::array::from_fn(|offset| self.memory[(i + offset) % MEMORY_SIZE]);
 
-        if self.screen.is_lowres() && collisions != 0 {
-            self.cpu.registers[0xF] = 1;
+        if let Some(log) = &mut self.draw_log {
+            log.push(self.screen.sprite_draw(x, y, n.0 as usize, comp.large_sprites, comp.lores_size));
         }
-        else if !self.screen.is_lowres() {
-            self.cpu.registers[0xF] = collisions.max(255) as u8;
+        let result = self.screen.draw_sprite(&sprite, x, y, n.0 as usize, comp.large_sprites, [comp.horizontal_edge, comp.vertical_edge], comp.lores_size);
+        self.counters.draws += 1;
+
+        self.cpu.registers[0xF] = if comp.collisions == CollisionEnumeration::SuperChip && !self.screen.is_lowres() {
+            (result.collided_rows + result.clipped_rows) as u8
         }
+        else {
+            (result.collided_rows != 0) as u8
+        };
     }
     fn exec_skip_not_pressed(&mut self, x: Register, keys: &Keys) {
         let x = self.cpu[x];
@@ -226,13 +480,18 @@ impl Machine {
         self.cpu[x] = self.cpu.delay_timer;
     }
     fn exec_wait_for_key(&mut self, x: Register, keys: &Keys) {
-        for k in 0..16 {
-            if keys.is_pressed(k) {
-                self.cpu[x] = k;
-                return;
-            }
+        self.waiting_for_key = Some(x.0);
+        self.poll_key(keys);
+    }
+    /// Completes a waiting FX0A instruction if a key is pressed
+    fn poll_key(&mut self, keys: &Keys) {
+        let Some(x) = self.waiting_for_key else {
+            return;
+        };
+        if let Some(k) = (0..16).find(|&k| keys.is_pressed(k)) {
+            self.cpu[Register(x)] = k;
+            self.waiting_for_key = None;
         }
-        self.cpu.ip -= 2;
     }
     fn exec_store_sound(&mut self, x: Register) {
         self.cpu.sound_timer = self.cpu[x];
@@ -244,6 +503,9 @@ impl Machine {
         let x = self.cpu[x];
         self.cpu.i = self.cpu.i.wrapping_add(x as u16);
 
+        if comp.add_i_overflow == AddIOverflow::Amiga {
+            self.cpu.registers[0xF] = if self.cpu.i > 0xFFF { 1 } else { 0 };
+        }
         if comp.address_space == AddressSpace::Original {
             self.cpu.i %= 4096;
         }
@@ -265,16 +527,16 @@ impl Machine {
         let hundreds = (x / 10 / 10) % 10;
 
         let i = self.cpu.i as usize;
-        self.memory[i + 0] = hundreds;
-        self.memory[i + 1] = tens;
-        self.memory[i + 2] = ones;
+        self.write_memory(i, hundreds);
+        self.write_memory(i + 1, tens);
+        self.write_memory(i + 2, ones);
     }
     fn exec_store(&mut self, x: Register, comp: &CompatibilityMode) {
         let x = x.0 as usize;
         let i = self.cpu.i as usize;
-        let regs = &self.cpu.registers[..=x];
-        let mem = &mut self.memory[i..=i+x];
-        mem.copy_from_slice(regs);
+        for offset in 0..=x {
+            self.write_memory(i + offset, self.cpu.registers[offset]);
+        }
 
         if comp.load_store == LoadStoreMode::Original {
             self.cpu.i += x as u16;
@@ -291,11 +553,62 @@ impl Machine {
             self.cpu.i += x as u16;
         }
     }
-    fn exec_store_user_flags(&mut self, _x: Register) {
+    fn exec_store_user_flags(&mut self, x: Register, comp: &CompatibilityMode) {
+        let x = self.user_flag_range(x, comp);
+        self.user_flags[..=x].copy_from_slice(&self.cpu.registers[..=x]);
+    }
+    fn exec_load_user_flags(&mut self, x: Register, comp: &CompatibilityMode) {
+        let x = self.user_flag_range(x, comp);
+        self.cpu.registers[..=x].copy_from_slice(&self.user_flags[..=x]);
+    }
+    fn user_flag_range(&self, x: Register, comp: &CompatibilityMode) -> usize {
+        let count = comp.allowed_instructions.user_flag_count();
+        let x = x.0 as usize;
+        if x >= count {
+            let ip = self.cpu.ip - 2;
+            panic!("User flag V{:X} at address {:x} is out of range, {:?} only has {} flags", x, ip, comp.allowed_instructions, count);
+        }
+        x
+    }
+    fn exec_select_planes(&mut self, n: Constant) {
+        self.screen.select_planes(n.0);
+    }
+    /// Loads one pattern per voice, stored one after the other like the sprites of multiple planes
+    fn exec_load_audio(&mut self, comp: &CompatibilityMode) {
+        let i = self.cpu.i as usize;
+        self.audio_patterns = (0..comp.audio_voices as usize).map(|voice| {
+            let start = i + voice * PATTERN_SIZE;
+            std::array::from_fn(|offset| self.memory[(start + offset) % MEMORY_SIZE])
+        }).collect();
+    }
+    fn exec_store_pitch(&mut self, x: Register) {
+        self.cpu.pitch = self.cpu[x];
+    }
 
+    fn exec_exit(&mut self) {
+        info!(code = self.cpu.registers[0], "Program exited");
+        self.exited = true;
+    }
+    fn exec_debug_registers(&mut self, x: Register, y: Register) {
+        let ip = self.cpu.ip - 2;
+        let values: Vec<_> = (x.0..=y.0)
+            .map(|r| format!("V{:X}={:02X}", r, self.cpu.registers[r as usize]))
+            .collect();
+        self.debug_output.push(format!("[{:03X}] {}", ip, values.join(" ")));
+    }
+    fn exec_debug_string(&mut self) {
+        let ip = self.cpu.ip - 2;
+        let bytes = self.memory[self.cpu.i as usize..].iter().take_while(|&&b| b != 0);
+        let text: String = bytes.map(|&b| b as char).collect();
+        self.debug_output.push(format!("[{:03X}] {}", ip, text));
     }
-    fn exec_load_user_flags(&mut self, _x: Register) {
 
+    /// Writes a byte on behalf of the program, so peripherals can observe it
+    fn write_memory(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+        if let Some(log) = &mut self.write_log {
+            log.push(MemoryWrite { address: address as u16, value });
+        }
     }
 
     pub fn decrement_counters(&mut self) {
@@ -306,15 +619,70 @@ impl Machine {
             self.cpu.delay_timer -= 1;
         }
     }
+    pub fn is_sound_playing(&self) -> bool {
+        self.cpu.sound_timer != 0
+    }
+    /// The 128 one-bit samples each voice loops while the buzzer sounds, first sample in the highest bit,
+    /// or none while the program hasn't loaded patterns and the plain tone plays
+    pub fn audio_patterns(&self) -> &[[u8; PATTERN_SIZE]] {
+        &self.audio_patterns
+    }
+    /// The XO-Chip pitch register set by FX3A, see [`pitch_rate`](crate::audio::pitch_rate) for the rate it plays patterns at
+    pub fn pitch(&self) -> u8 {
+        self.cpu.pitch
+    }
 
     pub fn init_instruction_pointer(&mut self, ip: u16) {
         self.cpu.ip = ip;
     }
-    pub fn load_program(&mut self, program: &[u8], start: usize) {
+    /// Copies `program` into memory at `start`, failing if it does not fit or would overwrite the font
+    pub fn load_program(&mut self, program: &[u8], start: usize) -> Result<(), LoadError> {
         let size = program.len();
-        let dest = &mut self.memory[start..start+size];
-        let src = program;
-        dest.copy_from_slice(src);
+        let reserved = Self::sprites_end() as usize;
+        if start < reserved {
+            return Err(LoadError::OverlapsReserved { start, reserved });
+        }
+        let available = MEMORY_SIZE.saturating_sub(start);
+        if size > available {
+            return Err(LoadError::TooLarge { size, available });
+        }
+
+        self.copy_program(program, start);
+        self.program = program.to_vec();
+        self.program_start = start;
+        info!(size, start, "Loaded program");
+        Ok(())
+    }
+    fn copy_program(&mut self, program: &[u8], start: usize) {
+        self.memory[start..start + program.len()].copy_from_slice(program);
+    }
+    /// Restarts the loaded program from a clean CPU, stack, screen and memory
+    pub fn reset(&mut self) {
+        debug!("Resetting machine");
+        self.cpu = CPU::new();
+        self.stack.clear();
+        self.exited = false;
+        self.waiting_for_key = None;
+        self.audio_patterns.clear();
+        self.frame_progress = 0;
+        self.memory.fill(0);
+        if !self.screen.is_lowres() {
+            self.screen_events.push(ScreenEvent::Resolution { address: self.program_start as u16, width: WIDTH / 2, height: self.lores_size.rows() });
+        }
+        self.screen = Screen::new();
+        if let Some(log) = &mut self.draw_log {
+            log.clear();
+        }
+        if let Some(log) = &mut self.write_log {
+            log.clear();
+        }
+
+        self.load_sprites();
+        // The program was checked when it was first loaded
+        let program = std::mem::take(&mut self.program);
+        self.copy_program(&program, self.program_start);
+        self.program = program;
+        self.init_instruction_pointer(self.program_start as u16);
     }
     pub fn load_sprites(&mut self) {
         self.load_lowres_sprites();
@@ -326,9 +694,10 @@ impl Machine {
         }
     }
     fn load_hires_sprites(&mut self) {
+        let start = Self::hires_sprite_start() as usize;
         for (i, &b) in SPRITE_BYTES.iter().enumerate() {
-            self.memory[i * 2 + 0] = b;
-            self.memory[i * 2 + 1] = b;
+            self.memory[start + i * 2] = b;
+            self.memory[start + i * 2 + 1] = b;
         }
     }
     fn lores_sprite_start() -> u16 {
@@ -337,6 +706,193 @@ impl Machine {
     fn hires_sprite_start() -> u16 {
         Self::lores_sprite_start() + 5 * 16
     }
+    /// The first address after the font, where programs may start
+    fn sprites_end() -> u16 {
+        Self::hires_sprite_start() + 10 * 16
+    }
+
+    /// Enables or disables recording the area of every executed draw instruction
+    pub fn set_draw_logging(&mut self, enabled: bool) {
+        self.draw_log = enabled.then(Vec::new);
+    }
+    pub fn is_draw_logging(&self) -> bool {
+        self.draw_log.is_some()
+    }
+    /// Returns the draws recorded since the last call, if logging is enabled
+    pub fn take_draws(&mut self) -> Vec<SpriteDraw> {
+        self.draw_log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Enables or disables recording every memory write made by the program
+    pub fn set_write_logging(&mut self, enabled: bool) {
+        self.write_log = enabled.then(Vec::new);
+    }
+    pub fn is_write_logging(&self) -> bool {
+        self.write_log.is_some()
+    }
+    /// Returns the writes recorded since the last call, if logging is enabled
+    pub fn take_writes(&mut self) -> Vec<MemoryWrite> {
+        self.write_log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Enables recording the address of every executed instruction.
+    /// Skipped instructions are not recorded.
+    pub fn set_execution_logging(&mut self, enabled: bool) {
+        self.execution_log = enabled.then(Vec::new);
+    }
+    /// Returns the addresses executed since the last call, in order, if logging is enabled
+    pub fn take_executed(&mut self) -> Vec<u16> {
+        self.execution_log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Executes `handler` for opcodes the interpreter doesn't know whose bits under `mask` equal `pattern`,
+    /// instead of failing on them. Known instructions always take precedence, except for the debug prints
+    /// while they are disabled; among handlers, the one registered last wins.
+    ///
+    /// The handler gets the opcode, with the PC already past it, so it may jump by setting the PC.
+    /// Opcodes are always two bytes long. Handlers survive resets but aren't part of save states,
+    /// and the JIT leaves blocks before custom opcodes, so they always run in the interpreter.
+    pub fn register_opcode(&mut self, mask: u16, pattern: u16, handler: impl Fn(&mut Machine, u16) + Send + Sync + 'static) {
+        self.extensions.push(OpcodeExtension { mask, pattern: pattern & mask, handler: Arc::new(handler) });
+    }
+    /// Removes every handler added with [`Machine::register_opcode`]
+    pub fn clear_opcodes(&mut self) {
+        self.extensions.clear();
+    }
+
+    /// Enables the logs `other` records and takes over its custom opcodes,
+    /// for a machine restored from a state, which includes neither
+    pub fn adopt_runtime_settings(&mut self, other: &Machine) {
+        self.set_draw_logging(other.is_draw_logging());
+        self.set_write_logging(other.is_write_logging());
+        self.set_execution_logging(other.execution_log.is_some());
+        self.extensions = other.extensions.clone();
+    }
+
+    /// Returns the lines printed by debug print instructions since the last call
+    pub fn take_debug_output(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.debug_output)
+    }
+
+    /// Changes of the screen mode since the last call, oldest first
+    pub fn take_screen_events(&mut self) -> Vec<ScreenEvent> {
+        std::mem::take(&mut self.screen_events)
+    }
+    /// The instructions and draws since the last call, for run statistics
+    pub fn take_counters(&mut self) -> Counters {
+        std::mem::take(&mut self.counters)
+    }
+
+    /// Decodes the instruction at the PC without executing it
+    pub fn next_instruction(&self) -> Option<Instruction> {
+        let ip = self.cpu.ip as usize;
+        if ip + 1 >= MEMORY_SIZE {
+            return None;
+        }
+        Instruction::decode(&self.memory[ip..])
+    }
+    pub fn pc(&self) -> u16 {
+        self.cpu.ip
+    }
+    /// Whether the program has executed the exit instruction and stopped
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+    /// The register of the FX0A instruction that is waiting for a key press, if any.
+    /// The PC already points past the instruction.
+    pub fn waiting_for_key(&self) -> Option<Register> {
+        self.waiting_for_key.map(Register)
+    }
+    /// Whether executing would only keep waiting for a key, so the host can skip the rest of the frame
+    pub fn is_blocked(&self, keys: &Keys) -> bool {
+        self.waiting_for_key.is_some() && !keys.any_pressed()
+    }
+    /// The registers and I, for compiled code to work on
+    #[cfg(feature = "jit")]
+    pub(super) fn jit_state(&mut self) -> (&mut [u8; 16], &mut u16) {
+        (&mut self.cpu.registers, &mut self.cpu.i)
+    }
+    /// Counts the instructions compiled code executed from `start` on, and logs them for coverage
+    #[cfg(feature = "jit")]
+    pub(super) fn count_compiled(&mut self, start: u16, instructions: usize) {
+        self.counters.instructions += instructions as u64;
+        if let Some(log) = &mut self.execution_log {
+            log.extend((0..instructions as u16).map(|i| start + i * 2));
+        }
+    }
+    /// Whether the next instruction will be skipped
+    pub fn is_skipping(&self) -> bool {
+        self.cpu.skip
+    }
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.cpu.registers
+    }
+    pub fn i(&self) -> u16 {
+        self.cpu.i
+    }
+    /// The flag registers saved by FX75 and restored by FX85
+    pub fn user_flags(&self) -> &[u8; USER_FLAGS] {
+        &self.user_flags
+    }
+    pub fn delay_timer(&self) -> u8 {
+        self.cpu.delay_timer
+    }
+    pub fn sound_timer(&self) -> u8 {
+        self.cpu.sound_timer
+    }
+    /// The return addresses of all active subroutine calls, innermost last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+    /// The program as it was loaded, before it modified itself
+    pub fn program(&self) -> &[u8] {
+        &self.program
+    }
+    /// The address the program was loaded at
+    pub fn program_start(&self) -> usize {
+        self.program_start
+    }
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..]
+    }
+    /// Writes a byte to memory from outside the emulated program
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    // Writes to the CPU state from outside the emulated program, for debuggers and other tooling
+
+    /// Sets register VX, panicking if `x` is not a register number
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.cpu.registers[x] = value;
+    }
+    pub fn set_i(&mut self, i: u16) {
+        self.cpu.i = i;
+    }
+    /// Continues execution at `pc`, cancelling a pending skip
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.ip = pc;
+        self.cpu.skip = false;
+    }
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.cpu.delay_timer = value;
+    }
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.cpu.sound_timer = value;
+    }
+    /// Sets the first flag registers to `flags`, ignoring any beyond the last one
+    pub fn set_user_flags(&mut self, flags: &[u8]) {
+        let count = flags.len().min(USER_FLAGS);
+        self.user_flags[..count].copy_from_slice(&flags[..count]);
+    }
+    /// Pushes a return address, as if a subroutine was called from just before it
+    pub fn push_stack(&mut self, address: u16) {
+        self.stack.push(address);
+    }
+    /// Pops the innermost return address, if any
+    pub fn pop_stack(&mut self) -> Option<u16> {
+        self.stack.pop()
+    }
 
     pub fn screen(&self) -> &Screen {
         &self.screen
@@ -344,9 +900,231 @@ impl Machine {
     pub fn write_screen<O: Write>(&self, out: O) -> io::Result<()> {
         self.screen.write(out)
     }
+
+    /// A pretty-printed JSON snapshot of the machine, including the memory in `ranges`, clamped to the memory size.
+    /// Unlike save states the format is meant to be read by people and other tools, see [`StateDump`].
+    pub fn dump_json(&self, ranges: &[Range<usize>]) -> String {
+        let dump = StateDump {
+            format: StateDump::FORMAT,
+            pc: self.cpu.ip,
+            i: self.cpu.i,
+            registers: self.cpu.registers,
+            skipping: self.cpu.skip,
+            stack: self.stack.clone(),
+            delay_timer: self.cpu.delay_timer,
+            sound_timer: self.cpu.sound_timer,
+            waiting_for_key: self.waiting_for_key,
+            exited: self.exited,
+            user_flags: self.user_flags,
+            memory: ranges.iter().map(|range| {
+                let bytes = &self.memory[range.start.min(MEMORY_SIZE)..range.end.min(MEMORY_SIZE)];
+                let hex: Vec<_> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                MemoryDump { start: range.start, bytes: hex.join(" ") }
+            }).collect(),
+            screen: ScreenDump {
+                hires: !self.screen.is_lowres(),
+                planes: (0..PLANES).map(|plane| self.screen.bit_rows(plane, self.lores_size)).collect(),
+            },
+        };
+        serde_json::to_string_pretty(&dump).expect("state dumps always serialize")
+    }
+}
+
+
+/// What happened while running a number of instructions
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StepResult {
+    /// Instructions executed, counting skipped ones
+    pub executed: usize,
+    /// Whether any instruction drew, cleared or scrolled the screen or changed its resolution
+    pub screen_changed: bool,
+    /// Whether the program has exited
+    pub exited: bool,
+    /// Whether the sound timer is running at the end
+    pub sound_playing: bool,
+}
+
+
+/// What [`Machine::run_until`] runs until, it stops as soon as any of the set conditions holds
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StopCondition {
+    /// Stop once a frame of this many instructions is over, ticking the timers. Frames carry
+    /// over between calls, so a frame interrupted by another condition is finished by the next call.
+    pub frame: Option<usize>,
+    /// Stop after an instruction drew a sprite
+    pub draw: bool,
+    /// Stop before executing the instruction at any of these addresses, except at the one execution starts at
+    pub breakpoints: BTreeSet<u16>,
+    /// Stop after this many instructions, counting skipped ones
+    pub instructions: Option<usize>,
+}
+impl StopCondition {
+    /// Runs one frame of `instructions` instructions, like [`Machine::run_frame`]
+    pub fn frame(instructions: usize) -> Self {
+        Self {
+            frame: Some(instructions),
+            ..Self::default()
+        }
+    }
+    pub fn instructions(instructions: usize) -> Self {
+        Self {
+            instructions: Some(instructions),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_draw(mut self) -> Self {
+        self.draw = true;
+        self
+    }
+    pub fn with_breakpoints(mut self, breakpoints: impl IntoIterator<Item = u16>) -> Self {
+        self.breakpoints.extend(breakpoints);
+        self
+    }
+}
+
+/// Why [`Machine::run_until`] stopped
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StopReason {
+    /// The frame is over and the timers were ticked
+    FrameEnd,
+    /// An instruction drew a sprite
+    Draw,
+    /// The PC reached this breakpoint, the instruction there has yet to run
+    Breakpoint(u16),
+    /// The instruction limit was reached
+    Instructions,
+    /// The program executed the exit instruction
+    Exited,
+    /// The program waits for a key and no key is pressed
+    Blocked,
+    /// The interpreter failed on an instruction, with the reason
+    Error(String),
+}
+
+/// The message of a caught panic
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    }
+    else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    }
+    else {
+        String::from("unknown error")
+    }
+}
+
+
+/// A change of the screen that frontends may want to react to, see [`Machine::take_screen_events`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ScreenEvent {
+    /// The program switched to a resolution of `width` by `height` pixels
+    Resolution { address: u16, width: usize, height: usize },
+}
+impl Display for ScreenEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ScreenEvent::Resolution { address, width, height } => write!(f, "switched to {}x{} at {:03X}", width, height, address),
+        }
+    }
 }
 
 
+/// Executes a custom opcode, see [`Machine::register_opcode`]
+pub type OpcodeHandler = Arc<dyn Fn(&mut Machine, u16) + Send + Sync>;
+
+#[derive(Clone)]
+struct OpcodeExtension {
+    mask: u16,
+    pattern: u16,
+    handler: OpcodeHandler,
+}
+
+
+/// Activity of the machine, see [`Machine::take_counters`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Counters {
+    /// Instructions executed, counting skipped ones
+    pub instructions: u64,
+    /// Sprites drawn
+    pub draws: u64,
+}
+
+
+/// The JSON document written by [`Machine::dump_json`]. Numbers are plain decimal JSON numbers.
+#[derive(Clone, Debug, Serialize)]
+pub struct StateDump {
+    /// Identifies the document and the version of its layout
+    pub format: &'static str,
+    /// Address of the next instruction
+    pub pc: u16,
+    pub i: u16,
+    /// V0 through VF
+    pub registers: [u8; 16],
+    /// Whether the next instruction will be skipped
+    pub skipping: bool,
+    /// Return addresses of the active subroutine calls, innermost last
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// The register an FX0A instruction is waiting to store a key press in
+    pub waiting_for_key: Option<u8>,
+    /// Whether the program executed the exit instruction
+    pub exited: bool,
+    /// The persistent SuperChip and XO-Chip flag registers
+    pub user_flags: [u8; USER_FLAGS],
+    /// The requested memory ranges
+    pub memory: Vec<MemoryDump>,
+    pub screen: ScreenDump,
+}
+impl StateDump {
+    pub const FORMAT: &'static str = "chippy-state-dump/1";
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MemoryDump {
+    pub start: usize,
+    /// The bytes from `start` on, as space separated hexadecimal pairs
+    pub bytes: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ScreenDump {
+    /// Whether the screen is in 128x64 mode rather than 64x32
+    pub hires: bool,
+    /// The rows of each bit plane at the current resolution, one `0` or `1` per pixel
+    pub planes: Vec<Vec<String>>,
+}
+
+
+/// Why a program could not be loaded into memory
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LoadError {
+    /// The program is longer than the memory after its start address
+    TooLarge { size: usize, available: usize },
+    /// The program would start inside the font at the bottom of memory
+    OverlapsReserved { start: usize, reserved: usize },
+}
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LoadError::TooLarge { size, available } => write!(f, "program is too large, {} bytes but only {} fit in memory", size, available),
+            LoadError::OverlapsReserved { start, reserved } => write!(f, "program start {:#05X} overlaps the reserved area below {:#05X}", start, reserved),
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryWrite {
+    pub address: u16,
+    pub value: u8,
+}
+
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CPU {
     registers: [u8; 16],
     i: u16,
@@ -354,6 +1132,8 @@ pub struct CPU {
     skip: bool,
     sound_timer: u8,
     delay_timer: u8,
+    /// Playback rate of the XO-Chip audio pattern
+    pitch: u8,
 }
 impl CPU {
     pub fn new() -> CPU {
@@ -364,9 +1144,16 @@ impl CPU {
             skip: false,
             sound_timer: 0,
             delay_timer: 0,
+            pitch: Pattern::DEFAULT_PITCH,
         }
     }
 }
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Index<Register> for CPU {
     type Output = u8;
     
@@ -464,3 +1251,55 @@ static SPRITE_BYTES: &[u8] = &[
     0x80,
 ];
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::comp_mode::{AllowedInstructions, CompBuilder};
+
+    /// A machine running `program` with a handler that stores 1 in V0 for every 01XY opcode
+    fn machine_with_extension(program: &[u8]) -> Machine {
+        let mut machine = Machine::new(0);
+        machine.load_sprites();
+        machine.load_program(program, 0x200).unwrap();
+        machine.register_opcode(0xFF00, 0x0100, |machine, _| machine.set_register(0, 1));
+        machine
+    }
+
+    #[test]
+    fn sprites_for_both_planes_wrap_around_the_top_of_memory() {
+        // Select both planes and draw two rows at I
+        let mut machine = machine_with_extension(&[0xF3, 0x01, 0xD0, 0x02]);
+        let comp = CompBuilder::new().with_allowed_instructions(AllowedInstructions::XOChip).build();
+        machine.poke(0xFFFF, 0x80);
+        machine.set_i(0xFFFF);
+        machine.decode_and_execute(&comp, &Keys::new());
+        machine.decode_and_execute(&comp, &Keys::new());
+
+        // The first plane gets the last byte and the first of the font, the second plane the two after
+        let first = machine.screen().bit_rows(0, LoresSize::Standard);
+        let second = machine.screen().bit_rows(1, LoresSize::Standard);
+        assert!(first[0].starts_with("10000000"));
+        assert!(first[1].starts_with("11110000"));
+        assert!(second[0].starts_with("10010000"));
+        assert!(second[1].starts_with("10010000"));
+    }
+
+    #[test]
+    fn extensions_claim_debug_prints_while_disabled() {
+        let mut machine = machine_with_extension(&[0x01, 0x23]);
+        let comp = CompBuilder::new().build();
+        machine.decode_and_execute(&comp, &Keys::new());
+        assert_eq!(machine.registers()[0], 1);
+        assert!(machine.take_debug_output().is_empty());
+    }
+
+    #[test]
+    fn enabled_debug_prints_take_precedence_over_extensions() {
+        let mut machine = machine_with_extension(&[0x01, 0x23]);
+        let comp = CompBuilder::new().with_debug_print(true).build();
+        machine.decode_and_execute(&comp, &Keys::new());
+        assert_eq!(machine.registers()[0], 0);
+        assert_eq!(machine.take_debug_output().len(), 1);
+    }
+}
@@ -0,0 +1,84 @@
+use serde::{Serialize, Deserialize};
+
+
+/// The colours for each of the four values a pixel can take with two bit planes,
+/// starting with the background
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Palette(pub [[u8; 3]; 4]);
+impl Palette {
+    pub fn color(&self, value: u8) -> [u8; 3] {
+        self.0[value as usize]
+    }
+}
+impl Default for Palette {
+    fn default() -> Self {
+        PRESETS[0].palette
+    }
+}
+
+
+pub struct Preset {
+    /// Identifier used in the config file
+    pub key: &'static str,
+    pub name: &'static str,
+    pub palette: Palette,
+}
+
+pub fn preset(key: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|p| p.key == key)
+}
+
+pub static PRESETS: &[Preset] = &[
+    Preset {
+        key: "default",
+        name: "Default",
+        palette: Palette([[0, 0, 0], [255, 255, 255], [0, 255, 0], [128, 240, 128]]),
+    },
+    Preset {
+        key: "classic_green",
+        name: "Classic green",
+        palette: Palette([[0, 0, 0], [51, 255, 51], [0, 128, 0], [153, 255, 153]]),
+    },
+    Preset {
+        key: "amber",
+        name: "Amber",
+        palette: Palette([[0, 0, 0], [255, 176, 0], [153, 102, 0], [255, 215, 102]]),
+    },
+    Preset {
+        key: "lcd",
+        name: "LCD",
+        palette: Palette([[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]]),
+    },
+    Preset {
+        key: "octo",
+        name: "Octo",
+        palette: Palette([[153, 102, 0], [255, 204, 0], [255, 102, 0], [102, 34, 0]]),
+    },
+    Preset {
+        key: "high_contrast",
+        name: "High contrast",
+        palette: Palette([[0, 0, 0], [255, 255, 255], [255, 255, 0], [0, 255, 255]]),
+    },
+    // The following keep the planes apart for colour vision deficiencies, using colours of the Okabe-Ito palette
+    Preset {
+        key: "deuteranopia",
+        name: "Deuteranopia",
+        palette: Palette([[0, 0, 0], [255, 255, 255], [0, 114, 178], [230, 159, 0]]),
+    },
+    Preset {
+        key: "protanopia",
+        name: "Protanopia",
+        palette: Palette([[0, 0, 0], [255, 255, 255], [86, 180, 233], [240, 228, 66]]),
+    },
+    Preset {
+        key: "tritanopia",
+        name: "Tritanopia",
+        palette: Palette([[0, 0, 0], [255, 255, 255], [213, 94, 0], [0, 158, 115]]),
+    },
+    // Shades of grey only, the planes told apart by brightness
+    Preset {
+        key: "monochrome",
+        name: "Monochrome",
+        palette: Palette([[0, 0, 0], [255, 255, 255], [120, 120, 120], [190, 190, 190]]),
+    },
+];
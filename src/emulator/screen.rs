@@ -1,8 +1,12 @@
 use std::io::{Write, self};
+use serde::{Serialize, Deserialize};
+use super::{palette::Palette, comp_mode::{LargeSpriteMode, LoresSize, SpriteEdge}, big_array};
 
-const PLANES: usize = 2;
+pub const PLANES: usize = 2;
+/// Bytes of the largest sprite on one plane, 16 rows of 16 pixels
+pub const MAX_SPRITE_SIZE: usize = 32;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Screen {
     planes: [BitPlane; PLANES],
     plane_selected: [bool; PLANES],
@@ -32,9 +36,64 @@ impl Screen {
         }
     }
 
+    /// Selects the planes set in the low bits of `mask`, the first plane in the lowest bit.
+    /// With none selected, drawing, clearing and scrolling do nothing.
+    pub fn select_planes(&mut self, mask: u8) {
+        self.plane_selected = std::array::from_fn(|i| mask & (1 << i) != 0);
+    }
+    /// The planes that drawing, clearing and scrolling apply to
+    pub fn selected_planes(&self) -> [bool; PLANES] {
+        self.plane_selected
+    }
     pub fn is_lowres(&self) -> bool {
         self.mode == ScreenMode::LowRes
     }
+    /// Width and height in pixels of the current mode, with lores mode being `lores` large
+    pub fn size(&self, lores: LoresSize) -> (usize, usize) {
+        if self.is_lowres() { (WIDTH / 2, lores.rows()) } else { (WIDTH, HEIGHT) }
+    }
+    /// How many physical pixels wide and tall a pixel of the current mode is
+    fn scale(&self, lores: LoresSize) -> (usize, usize) {
+        if self.is_lowres() { lores.scale() } else { (1, 1) }
+    }
+
+    /// The rows of a plane at the resolution of the current mode, one `0` or `1` per pixel
+    pub fn bit_rows(&self, plane: usize, lores: LoresSize) -> Vec<String> {
+        let (width, height) = self.size(lores);
+        let (scale_x, scale_y) = self.scale(lores);
+        let rows = &self.planes[plane].rows;
+        (0..height).map(|y| {
+            (0..width).map(|x| {
+                let bit = rows[y * scale_y] & (1 << (WIDTH - 1 - x * scale_x)) != 0;
+                if bit { '1' } else { '0' }
+            }).collect()
+        }).collect()
+    }
+
+    /// Scrolls the selected planes down by `rows` physical pixels
+    pub fn scroll_down(&mut self, rows: usize) {
+        for (plane, sel) in self.planes.iter_mut().zip(self.plane_selected) {
+            if sel {
+                plane.scroll_down(rows);
+            }
+        }
+    }
+    /// Scrolls the selected planes right by `columns` physical pixels
+    pub fn scroll_right(&mut self, columns: usize) {
+        for (plane, sel) in self.planes.iter_mut().zip(self.plane_selected) {
+            if sel {
+                plane.scroll_right(columns);
+            }
+        }
+    }
+    /// Scrolls the selected planes left by `columns` physical pixels
+    pub fn scroll_left(&mut self, columns: usize) {
+        for (plane, sel) in self.planes.iter_mut().zip(self.plane_selected) {
+            if sel {
+                plane.scroll_left(columns);
+            }
+        }
+    }
 
     pub fn write<O: Write>(&self, mut out: O) -> io::Result<()> {
         for row in 0..64 {
@@ -58,18 +117,106 @@ impl Screen {
 
         Ok(())
     }
-    pub fn render_to_pixel_buffer(&self, buffer: &mut [u8]) {
+    /// Writes the screen as text in `style`, counting a pixel as set if it is set on any plane
+    /// for the compact styles
+    pub fn write_text<O: Write>(&self, mut out: O, style: TextStyle) -> io::Result<()> {
+        match style {
+            TextStyle::Ascii => return self.write(out),
+            TextStyle::HalfBlock => {
+                for row in (0..HEIGHT).step_by(2) {
+                    let line: String = (0..WIDTH).map(|x| {
+                        match (self.get_pixel(x, row) != 0, self.get_pixel(x, row + 1) != 0) {
+                            (false, false) => ' ',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (true, true) => '█',
+                        }
+                    }).collect();
+                    writeln!(out, "{}", line)?;
+                }
+            }
+            TextStyle::Braille => {
+                // Dot bits of a braille cell, indexed by row and then column
+                const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+                for row in (0..HEIGHT).step_by(4) {
+                    let line: String = (0..WIDTH).step_by(2).map(|column| {
+                        let mut bits = 0;
+                        for (y, dots) in DOTS.iter().enumerate() {
+                            for (x, dot) in dots.iter().enumerate() {
+                                if self.get_pixel(column + x, row + y) != 0 {
+                                    bits |= dot;
+                                }
+                            }
+                        }
+                        char::from_u32(0x2800 + bits).unwrap()
+                    }).collect();
+                    writeln!(out, "{}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Writes the screen as binary PBM, with set pixels on any plane in black
+    pub fn write_pbm<O: Write>(&self, mut out: O) -> io::Result<()> {
+        write!(out, "P4\n{} {}\n", WIDTH, HEIGHT)?;
+        let mut packed = [0; PACKED_SIZE];
+        self.pack_rows(&mut packed);
+        out.write_all(&packed)
+    }
+    /// Writes the screen as binary PPM in the colours of `palette`
+    pub fn write_ppm<O: Write>(&self, mut out: O, palette: &Palette) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", WIDTH, HEIGHT)?;
+        let mut data = Vec::with_capacity(WIDTH * HEIGHT * 3);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                data.extend_from_slice(&palette.color(self.get_pixel(x, y)));
+            }
+        }
+        out.write_all(&data)
+    }
+    pub fn write_image<O: Write>(&self, out: O, format: ImageFormat, palette: &Palette) -> io::Result<()> {
+        match format {
+            ImageFormat::Pbm => self.write_pbm(out),
+            ImageFormat::Ppm => self.write_ppm(out, palette),
+        }
+    }
+
+    /// Packs the screen into one bit per pixel, row by row with the leftmost pixel in the
+    /// highest bit, like most monochrome LCDs and PBM expect. Pixels count as set if they
+    /// are set on any plane, and the buffer always holds the full 128x64 resolution.
+    pub fn pack_rows(&self, buffer: &mut [u8; PACKED_SIZE]) {
+        for (y, row) in buffer.chunks_exact_mut(WIDTH / 8).enumerate() {
+            row.copy_from_slice(&self.lit_row(y).to_be_bytes());
+        }
+    }
+    /// Packs the screen in the page layout of SSD1306 and similar OLED controllers:
+    /// eight pages of eight rows, each byte one column of a page with the top pixel in the lowest bit.
+    /// Like [`Screen::pack_rows`], any plane sets a pixel.
+    pub fn pack_pages(&self, buffer: &mut [u8; PACKED_SIZE]) {
+        for (page, columns) in buffer.chunks_exact_mut(WIDTH).enumerate() {
+            let rows: [u128; 8] = std::array::from_fn(|bit| self.lit_row(page * 8 + bit));
+            for (x, column) in columns.iter_mut().enumerate() {
+                let mask = 1 << (WIDTH - 1 - x);
+                *column = rows.iter().enumerate().fold(0, |byte, (bit, row)| byte | (((row & mask) != 0) as u8) << bit);
+            }
+        }
+    }
+    /// The pixels of row `y` set on any plane, the leftmost in the highest bit
+    fn lit_row(&self, y: usize) -> u128 {
+        self.planes.iter().fold(0, |row, plane| row | plane.rows[y])
+    }
+
+    pub fn render_to_pixel_buffer(&self, buffer: &mut [u8], palette: &Palette) {
+        self.render_planes_to_pixel_buffer(buffer, palette, [true; PLANES]);
+    }
+    /// Renders only the planes marked in `visible`, as if the others were blank
+    pub fn render_planes_to_pixel_buffer(&self, buffer: &mut [u8], palette: &Palette, visible: [bool; PLANES]) {
+        let mask = visible.iter().enumerate().fold(0, |mask, (i, &v)| mask | (v as u8) << i);
         for (i, pixel) in buffer.chunks_exact_mut(4).enumerate() {
             let y = i / WIDTH;
             let x = i % WIDTH;
-            let value = self.get_pixel(x, y);
-            let color = match value {
-                0 => [0, 0, 0],
-                1 => [255, 255, 255],
-                2 => [0, 255, 0],
-                3 => [128, 240, 128],
-                _ => unreachable!(),
-            };
+            let value = self.get_pixel(x, y) & mask;
+            let color = palette.color(value);
 
             pixel[0] = color[0];
             pixel[1] = color[1];
@@ -91,73 +238,95 @@ impl Screen {
         value
     }
 
-    pub fn draw_sprite(&mut self, sprite: &[u8], x: usize, y: usize, height: usize) -> usize {
-        let mut collisions = 0;
+    /// The bytes per row and the number of rows of a sprite drawn with `height`
+    fn sprite_shape(&self, height: usize, large_sprites: LargeSpriteMode) -> (usize, usize) {
+        if height != 0 {
+            return (1, height);
+        }
+        match large_sprites {
+            LargeSpriteMode::SuperChip if self.is_lowres() => (1, 16),
+            _ => (2, 16),
+        }
+    }
 
-        let sprite_size = if height == 0 {
-            if self.is_lowres() {
-                16
-            }
-            else {
-                32
-            }
+    /// The area covered by a sprite drawn at (x, y), in physical screen pixels
+    pub fn sprite_draw(&self, x: usize, y: usize, height: usize, large_sprites: LargeSpriteMode, lores: LoresSize) -> SpriteDraw {
+        let (scale_x, scale_y) = self.scale(lores);
+        let (bytes_per_row, height) = self.sprite_shape(height, large_sprites);
+
+        SpriteDraw {
+            x: x * scale_x,
+            y: y * scale_y,
+            width: bytes_per_row * 8 * scale_x,
+            height: height * scale_y,
+            planes: self.plane_selected,
         }
-        else {
-            height
-        };
+    }
+
+    /// Draws a sprite at (x, y), clipping or wrapping it at the edges of the screen as `edges`,
+    /// horizontal and vertical, say
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: usize, y: usize, height: usize, large_sprites: LargeSpriteMode, edges: [SpriteEdge; 2], lores_size: LoresSize) -> DrawResult {
+        let (bytes_per_row, rows) = self.sprite_shape(height, large_sprites);
+        let lores = self.is_lowres();
+        let clip = edges.map(|edge| edge.clips(lores));
+        let sprite_size = bytes_per_row * rows;
+        let scale = self.scale(lores_size);
+        let area = Area { scale, height: self.size(lores_size).1 * scale.1, clip };
 
+        let mut collided = 0;
         let mut offset = 0;
         for i in 0..PLANES {
             if self.plane_selected[i] {
-                let start = offset * sprite_size;
+                let start = (offset * sprite_size).min(sprite.len());
                 offset += 1;
-                collisions += self.draw_to_plane(i, &sprite[start..], x, y, height);
+                collided |= self.draw_to_plane(i, &sprite[start..], (x, y), bytes_per_row, rows, area);
             }
         }
 
-        collisions
-    }
-    fn draw_to_plane(&mut self, plane: usize, sprite: &[u8], x: usize, y: usize, height: usize) -> usize {
-        let mut collisions = 0;
-
-        let bytes_per_row = if height == 0 {
-            if self.is_lowres() {
-                1
-            }
-            else {
-                2
-            }
+        let clipped_rows = if lores || !clip[1] { 0 } else { (y + rows).saturating_sub(HEIGHT).min(rows) };
+        DrawResult {
+            collided_rows: collided.count_ones() as usize,
+            clipped_rows,
         }
-        else {
-            1
-        };
-        let height = if height == 0 { 16 } else { height };
-
+    }
+    /// Draws the sprite to one plane, returning a mask of the sprite rows that collided
+    fn draw_to_plane(&mut self, plane: usize, sprite: &[u8], (x, y): (usize, usize), bytes_per_row: usize, height: usize, area: Area) -> u16 {
+        let mut collided = 0;
 
         for (row, sprite_bytes) in sprite.chunks_exact(bytes_per_row).take(height).enumerate() {
             let y = y + row;
 
-            for (column_offset, &sprite_byte) in sprite_bytes.into_iter().enumerate() {
+            for (column_offset, &sprite_byte) in sprite_bytes.iter().enumerate() {
                 for column in 0..8 {
                     let x = x + column + column_offset * 8;
                     let mask = 1 << (7 - column);
                     let bit = (sprite_byte & mask) != 0;
-                    if self.planes[plane].draw_pixel(x, y, bit, self.is_lowres()) {
-                        collisions += 1;
+                    if self.planes[plane].draw_pixel(x, y, bit, area) {
+                        collided |= 1 << row;
                     }
                 }
             }
         }
 
-        collisions
+        collided
+    }
+}
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-const WIDTH: usize = 128;
-const HEIGHT: usize = 64;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+/// Bytes of a screen packed to one bit per pixel
+pub const PACKED_SIZE: usize = WIDTH * HEIGHT / 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BitPlane {
+    #[serde(with = "big_array")]
     rows: [u128; HEIGHT],
 }
 impl BitPlane {
@@ -171,30 +340,43 @@ impl BitPlane {
         self.rows = [0; HEIGHT];
     }
 
-    fn draw_pixel(&mut self, mut x: usize, mut y: usize, pixel: bool, lores: bool) -> bool {
-        if lores {
-            x *= 2;
-            y *= 2;
+    fn scroll_down(&mut self, rows: usize) {
+        let rows = rows.min(HEIGHT);
+        self.rows.copy_within(..HEIGHT - rows, rows);
+        self.rows[..rows].fill(0);
+    }
+    fn scroll_right(&mut self, columns: usize) {
+        for row in &mut self.rows {
+            *row = row.checked_shr(columns as u32).unwrap_or(0);
+        }
+    }
+    fn scroll_left(&mut self, columns: usize) {
+        for row in &mut self.rows {
+            *row = row.checked_shl(columns as u32).unwrap_or(0);
         }
+    }
+
+    /// Flips the pixel if `pixel` is set, pixels beyond an edge of `area` are dropped if it clips
+    /// that axis and wrapped around otherwise
+    fn draw_pixel(&mut self, x: usize, y: usize, pixel: bool, area: Area) -> bool {
+        let Area { scale: (scale_x, scale_y), height, clip } = area;
+        let x = x * scale_x;
+        let y = y * scale_y;
         
         let mut collision = false;
         let pixel_mask = if pixel { u128::MAX } else { 0 };
 
-        let limit = if lores { 2 } else { 1 };
-        for y_off in 0..limit {
+        for y_off in 0..scale_y {
             let y = y + y_off;
             
-            for x_off in 0..limit {
+            for x_off in 0..scale_x {
                 let x = x + x_off;
 
-                if x >= WIDTH || y >= HEIGHT {
-                    if !lores {
-                        collision = true;
-                        break;
-                    }
+                if (x >= WIDTH && clip[0]) || (y >= height && clip[1]) {
+                    continue;
                 }
                 let x = x % WIDTH;
-                let y = y % HEIGHT;
+                let y = y % height;
 
                 let row = &mut self.rows[y];
                 let mask = pixel_mask & 1 << (WIDTH - 1 - x);
@@ -208,10 +390,154 @@ impl BitPlane {
         collision
     }
 }
+impl Default for BitPlane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 
+/// Where and how large the pixels of a sprite are drawn
+#[derive(Copy, Clone, Debug)]
+struct Area {
+    /// Physical pixels per pixel of the current mode, horizontally and vertically
+    scale: (usize, usize),
+    /// Physical rows covered by the current mode
+    height: usize,
+    /// Whether the horizontal and vertical edges clip
+    clip: [bool; 2],
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpriteDraw {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// The planes that were selected for drawing
+    pub planes: [bool; PLANES],
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextStyle {
+    /// One character per pixel, distinguishing the planes
+    Ascii,
+    /// Unicode half blocks, one character for two pixels stacked on each other
+    HalfBlock,
+    /// Unicode braille patterns, one character for a block of 2x4 pixels
+    Braille,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    /// Black and white portable bitmap, ignoring the palette
+    Pbm,
+    /// Full colour portable pixmap
+    Ppm,
+}
+
+
+/// What happened while drawing a sprite
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DrawResult {
+    /// Sprite rows in which a set pixel was turned off, on any plane
+    pub collided_rows: usize,
+    /// Sprite rows that were cut off by the bottom of the hires screen
+    pub clipped_rows: usize,
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ScreenMode {
     HighRes,
     LowRes,
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDGES: [SpriteEdge; 2] = [SpriteEdge::Auto, SpriteEdge::Auto];
+    /// Enough set bytes for a 16x16 sprite
+    const SPRITE: [u8; 32] = [0xFF; 32];
+
+    fn screen(hires: bool) -> Screen {
+        let mut screen = Screen::new();
+        if hires {
+            screen.enable_hires();
+        }
+        screen
+    }
+
+    /// Width and height in physical pixels that a DXY0 sprite covers
+    fn large_sprite_size(hires: bool, mode: LargeSpriteMode) -> (usize, usize) {
+        let area = screen(hires).sprite_draw(0, 0, 0, mode, LoresSize::Standard);
+        (area.width, area.height)
+    }
+
+    #[test]
+    fn large_sprites_in_lores() {
+        assert_eq!(large_sprite_size(false, LargeSpriteMode::SuperChip), (16, 32));
+        assert_eq!(large_sprite_size(false, LargeSpriteMode::XOChip), (32, 32));
+    }
+
+    #[test]
+    fn large_sprites_in_hires() {
+        assert_eq!(large_sprite_size(true, LargeSpriteMode::SuperChip), (16, 16));
+        assert_eq!(large_sprite_size(true, LargeSpriteMode::XOChip), (16, 16));
+    }
+
+    #[test]
+    fn large_sprites_draw_their_size() {
+        for (hires, mode, lit) in [
+            (false, LargeSpriteMode::SuperChip, 16 * 32),
+            (false, LargeSpriteMode::XOChip, 32 * 32),
+            (true, LargeSpriteMode::SuperChip, 16 * 16),
+            (true, LargeSpriteMode::XOChip, 16 * 16),
+        ] {
+            let mut screen = screen(hires);
+            screen.draw_sprite(&SPRITE, 0, 0, 0, mode, EDGES, LoresSize::Standard);
+            let lit_pixels: u32 = (0..HEIGHT).map(|y| screen.lit_row(y).count_ones()).sum();
+            assert_eq!(lit_pixels, lit, "{:?} with hires {}", mode, hires);
+        }
+    }
+
+    #[test]
+    fn large_sprites_count_collided_rows() {
+        for hires in [false, true] {
+            for mode in [LargeSpriteMode::SuperChip, LargeSpriteMode::XOChip] {
+                let mut screen = screen(hires);
+                let first = screen.draw_sprite(&SPRITE, 0, 0, 0, mode, EDGES, LoresSize::Standard);
+                assert_eq!(first.collided_rows, 0);
+                let second = screen.draw_sprite(&SPRITE, 0, 0, 0, mode, EDGES, LoresSize::Standard);
+                assert_eq!(second.collided_rows, 16, "{:?} with hires {}", mode, hires);
+                assert_eq!(second.clipped_rows, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn large_sprites_clip_at_the_bottom_in_hires() {
+        for mode in [LargeSpriteMode::SuperChip, LargeSpriteMode::XOChip] {
+            let mut screen = screen(true);
+            let result = screen.draw_sprite(&SPRITE, 0, HEIGHT - 4, 0, mode, EDGES, LoresSize::Standard);
+            assert_eq!(result.clipped_rows, 12, "{:?}", mode);
+            assert_eq!(result.collided_rows, 0);
+        }
+    }
+
+    #[test]
+    fn large_sprites_wrap_at_the_bottom_in_lores() {
+        for mode in [LargeSpriteMode::SuperChip, LargeSpriteMode::XOChip] {
+            let mut screen = screen(false);
+            let result = screen.draw_sprite(&SPRITE, 0, 28, 0, mode, EDGES, LoresSize::Standard);
+            assert_eq!(result.clipped_rows, 0, "{:?}", mode);
+            // The wrapped rows land at the top of the screen
+            assert_ne!(screen.lit_row(0), 0);
+        }
+    }
+}
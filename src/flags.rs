@@ -0,0 +1,72 @@
+//! User flags saved to disk, so games that keep their save data in them with FX75 remember it.
+//!
+//! A flags file is a JSON array of the flag values, like `[3, 0, 17, 0, 0, 0, 0, 0]`, the way Octo
+//! keeps its persistent flags, so save data can be copied between the two. Files with fewer
+//! than 16 values set only the first flags.
+
+use std::{fs, io, path::{Path, PathBuf}};
+use tracing::{error, warn};
+use chippy::emulator::machine::Machine;
+use crate::rom;
+
+/// Directory holding flags files named after the hash of the ROM they belong to
+pub const FLAGS_DIR: &str = "./flags";
+
+
+/// The flags file of a ROM, written whenever the program changes its flags
+pub struct FlagsFile {
+    path: PathBuf,
+    /// The flags as last read or written, to notice changes
+    saved: Vec<u8>,
+}
+impl FlagsFile {
+    /// The file in [`FLAGS_DIR`] for `program`
+    pub fn for_rom(program: &[u8]) -> Self {
+        Self::at(&Path::new(FLAGS_DIR).join(format!("{}.json", rom::hash_string(program))))
+    }
+    pub fn at(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            saved: Vec::new(),
+        }
+    }
+
+    /// Sets the machine's flags from the file, if it exists
+    pub fn load(&mut self, machine: &mut Machine) {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("Could not read flags {}: {}", self.path.display(), e);
+                return;
+            }
+        };
+        match serde_json::from_str::<Vec<u8>>(&text) {
+            Ok(flags) => {
+                machine.set_user_flags(&flags);
+                self.saved = machine.user_flags().to_vec();
+            }
+            Err(e) => warn!("Could not parse flags {}: {}", self.path.display(), e),
+        }
+    }
+
+    /// Writes the machine's flags if they changed since they were last loaded or saved.
+    /// Flags that were never set aren't written, so ROMs that don't use them leave no file.
+    pub fn save_changes(&mut self, machine: &Machine) {
+        let flags = machine.user_flags();
+        let unchanged = if self.saved.is_empty() { flags.iter().all(|&f| f == 0) } else { self.saved == flags };
+        if unchanged {
+            return;
+        }
+        self.saved = flags.to_vec();
+        if let Err(e) = self.write() {
+            error!("Could not save flags to {}: {}", self.path.display(), e);
+        }
+    }
+    fn write(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string(&self.saved)?)
+    }
+}
@@ -0,0 +1,246 @@
+//! Control-flow graphs of ROMs as Graphviz files, for finding one's way around classic programs.
+//!
+//! Code is followed from the start of the program through jumps, calls and skips. Computed jumps
+//! (BNNN) depend on a register at runtime, so their targets aren't followed and they are marked
+//! in the graph instead, like code that can't be decoded.
+
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Write as _, fs, io::{self, Write}, path::{Path, PathBuf}, process::ExitCode};
+use tracing::{error, warn};
+use crate::{config::{Config, CONFIG_PATH}, emulator::instruction::{Address, Instruction}};
+
+
+/// Builds the graph of the program at `path` and writes it to `output`, or stdout without one
+pub fn run(path: &Path, patches: &[PathBuf], output: Option<&Path>) -> ExitCode {
+    let program = match crate::read_program(path, patches) {
+        Ok(program) => program,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let comp = crate::load_rom_settings(&Config::load(CONFIG_PATH), path, &program).quirks.build();
+    let machine = match crate::new_machine(&program, &comp, None) {
+        Ok(machine) => machine,
+        Err(e) => {
+            error!("Could not load {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let end = machine.program_start() + program.len();
+    let graph = FlowGraph::build(&machine.memory()[..end], comp.program_start);
+    for problem in &graph.problems {
+        warn!("{}", problem.describe());
+    }
+
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let dot = graph.to_dot(&name);
+    let result = match output {
+        Some(output) => fs::write(output, dot),
+        None => io::stdout().write_all(dot.as_bytes()),
+    };
+    if let Err(e) = result {
+        error!("Could not write control-flow graph: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum EdgeKind {
+    /// Execution continues with the next instruction
+    Next,
+    Jump,
+    /// A skip instruction whose condition held
+    Skip,
+    Call,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Problem {
+    /// A BNNN jump, whose target depends on V0 or VX
+    ComputedJump(u16),
+    /// Execution reaches bytes that aren't an instruction, or the end of memory
+    Undecodable(u16),
+}
+impl Problem {
+    fn address(&self) -> u16 {
+        match *self {
+            Problem::ComputedJump(address) | Problem::Undecodable(address) => address,
+        }
+    }
+    fn describe(&self) -> String {
+        match self {
+            Problem::ComputedJump(address) => format!("Computed jump at {:03X}, its targets are not followed", address),
+            Problem::Undecodable(address) => format!("Execution reaches {:03X}, which holds no valid instruction", address),
+        }
+    }
+}
+
+
+pub struct FlowGraph {
+    /// Where the program starts
+    start: u16,
+    /// Every reachable instruction by address
+    instructions: BTreeMap<u16, Instruction>,
+    /// Addresses that start a basic block
+    leaders: BTreeSet<u16>,
+    /// Entry points of subroutines, as found by calls
+    subroutines: BTreeSet<u16>,
+    /// From the last instruction of a block to the start of another
+    edges: BTreeSet<(u16, u16, EdgeKind)>,
+    problems: BTreeSet<Problem>,
+}
+impl FlowGraph {
+    /// Follows the code in `memory` from `start`
+    pub fn build(memory: &[u8], start: u16) -> Self {
+        let mut graph = Self {
+            start,
+            instructions: BTreeMap::new(),
+            leaders: BTreeSet::from([start]),
+            subroutines: BTreeSet::new(),
+            edges: BTreeSet::new(),
+            problems: BTreeSet::new(),
+        };
+
+        let decode = |address: u16| memory.get(address as usize..).and_then(Instruction::decode);
+        let mut pending = vec![start];
+        while let Some(address) = pending.pop() {
+            if graph.instructions.contains_key(&address) {
+                continue;
+            }
+            let Some(instruction) = decode(address) else {
+                graph.problems.insert(Problem::Undecodable(address));
+                continue;
+            };
+            graph.instructions.insert(address, instruction);
+
+            let next = address.wrapping_add(instruction.length());
+            let successors = match instruction {
+                Instruction::Jump(Address(target)) => vec![(target, EdgeKind::Jump)],
+                Instruction::Call(Address(target)) => {
+                    graph.subroutines.insert(target);
+                    vec![(target, EdgeKind::Call), (next, EdgeKind::Next)]
+                }
+                Instruction::Return | Instruction::Exit => vec![],
+                Instruction::JumpRelative(_) => {
+                    graph.problems.insert(Problem::ComputedJump(address));
+                    vec![]
+                }
+                Instruction::SkipEqualConstant(..) | Instruction::SkipNotEqualConstant(..)
+                | Instruction::SkipEqual(..) | Instruction::SkipNotEqual(..)
+                | Instruction::SkipPressed(_) | Instruction::SkipNotPressed(_) => {
+                    let skipped = decode(next).map_or(2, |i| i.length());
+                    vec![(next, EdgeKind::Next), (next.wrapping_add(skipped), EdgeKind::Skip)]
+                }
+                _ => vec![(next, EdgeKind::Next)],
+            };
+
+            let branches = successors.len() != 1 || successors[0].1 != EdgeKind::Next;
+            for (target, kind) in successors {
+                if branches {
+                    graph.leaders.insert(target);
+                }
+                graph.edges.insert((address, target, kind));
+                pending.push(target);
+            }
+        }
+
+        graph.split_blocks();
+        graph
+    }
+
+    /// Every instruction reachable from the start, by address
+    pub fn instructions(&self) -> &BTreeMap<u16, Instruction> {
+        &self.instructions
+    }
+    /// Entry points of subroutines
+    pub fn subroutines(&self) -> &BTreeSet<u16> {
+        &self.subroutines
+    }
+
+    /// Drops the edges within blocks, so edges only leave the last instruction of a block.
+    /// Blocks end at the instruction before a leader, or before code that can't be decoded.
+    fn split_blocks(&mut self) {
+        let (leaders, instructions) = (&self.leaders, &self.instructions);
+        self.edges.retain(|&(_, target, kind)| {
+            kind != EdgeKind::Next || leaders.contains(&target) || !instructions.contains_key(&target)
+        });
+    }
+
+    /// The addresses of the instructions in the block starting at `leader`
+    fn block(&self, leader: u16) -> Vec<u16> {
+        let mut addresses = vec![leader];
+        let mut address = leader;
+        loop {
+            let next = self.edges.range((address, 0, EdgeKind::Next)..)
+                .take_while(|&&(from, _, _)| from == address)
+                .next();
+            let Some(instruction) = self.instructions.get(&address) else { break };
+            let following = address.wrapping_add(instruction.length());
+            // The block goes on as long as nothing leaves it and the next instruction starts no block
+            if next.is_some() || self.leaders.contains(&following) || !self.instructions.contains_key(&following) || ends_block(instruction) {
+                break;
+            }
+            addresses.push(following);
+            address = following;
+        }
+        addresses
+    }
+
+    fn to_dot(&self, name: &str) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape(name)).unwrap();
+        writeln!(dot, "    node [shape=box, fontname=monospace];").unwrap();
+
+        for &leader in &self.leaders {
+            if !self.instructions.contains_key(&leader) {
+                continue;
+            }
+            let block = self.block(leader);
+            let mut label = String::new();
+            if self.subroutines.contains(&leader) {
+                write!(label, "sub_{:03X}:\\l", leader).unwrap();
+            }
+            for address in &block {
+                write!(label, "{:03X}  {}\\l", address, escape(&self.instructions[address].to_string())).unwrap();
+            }
+            let last = *block.last().unwrap();
+            let problem = self.problems.iter().find(|p| p.address() == last);
+            if let Some(Problem::ComputedJump(_)) = problem {
+                label.push_str("(computed jump)\\l");
+            }
+            let style = if problem.is_some() { ", color=red" } else if leader == self.start { ", penwidth=2" } else { "" };
+            writeln!(dot, "    b{:03X} [label=\"{}\"{}];", leader, label, style).unwrap();
+
+            for &(_, target, kind) in self.edges.range((last, 0, EdgeKind::Next)..).take_while(|&&(from, _, _)| from == last) {
+                let style = match kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Call => " [label=\"call\", style=dashed]",
+                };
+                let target = if self.instructions.contains_key(&target) { format!("b{:03X}", target) } else { format!("bad{:03X}", target) };
+                writeln!(dot, "    b{:03X} -> {}{};", leader, target, style).unwrap();
+            }
+        }
+
+        for problem in &self.problems {
+            if let Problem::Undecodable(address) = problem {
+                writeln!(dot, "    bad{:03X} [label=\"{:03X}  invalid\", color=red, fontcolor=red];", address, address).unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+/// Whether nothing executes after `instruction` without a jump
+fn ends_block(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::Jump(_) | Instruction::JumpRelative(_) | Instruction::Return | Instruction::Exit)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,113 @@
+use egui::{ClippedPrimitive, Context, TexturesDelta};
+use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
+use pixels::{wgpu, Pixels, PixelsContext};
+use winit::{event::WindowEvent, event_loop::EventLoopWindowTarget, window::Window};
+
+pub mod archive_picker;
+pub mod cheat_view;
+pub mod crash_view;
+pub mod debug_view;
+pub mod debug_window;
+pub mod help;
+pub mod magnifier;
+pub mod pause_menu;
+pub mod perf_graph;
+pub mod profiler;
+pub mod run_timer;
+pub mod settings_view;
+pub mod toasts;
+
+
+/// Draws egui user interfaces on top of the emulated display
+pub struct Gui {
+    ctx: Context,
+    state: egui_winit::State,
+    screen_descriptor: ScreenDescriptor,
+    renderer: Renderer,
+    paint_jobs: Vec<ClippedPrimitive>,
+    textures: TexturesDelta,
+}
+impl Gui {
+    pub fn new<T>(event_loop: &EventLoopWindowTarget<T>, window: &Window, pixels: &Pixels) -> Self {
+        let size = window.inner_size();
+        let scale_factor = window.scale_factor() as f32;
+        let max_texture_size = pixels.device().limits().max_texture_dimension_2d as usize;
+
+        let mut state = egui_winit::State::new(event_loop);
+        state.set_max_texture_side(max_texture_size);
+        state.set_pixels_per_point(scale_factor);
+        let screen_descriptor = ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: scale_factor,
+        };
+        let renderer = Renderer::new(pixels.device(), pixels.render_texture_format(), None, 1);
+
+        Self {
+            ctx: Context::default(),
+            state,
+            screen_descriptor,
+            renderer,
+            paint_jobs: Vec::new(),
+            textures: TexturesDelta::default(),
+        }
+    }
+
+    /// Feeds a window event to egui, returning whether egui consumed it
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event {
+            self.screen_descriptor.pixels_per_point = *scale_factor as f32;
+        }
+        self.state.on_event(&self.ctx, event).consumed
+    }
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.screen_descriptor.size_in_pixels = [width, height];
+        }
+    }
+
+    /// Runs the user interface code for this frame
+    pub fn prepare(&mut self, window: &Window, ui: impl FnOnce(&Context)) {
+        let raw_input = self.state.take_egui_input(window);
+        let output = self.ctx.run(raw_input, ui);
+
+        self.textures.append(output.textures_delta);
+        self.state.handle_platform_output(window, &self.ctx, output.platform_output);
+        self.paint_jobs = self.ctx.tessellate(output.shapes);
+    }
+
+    pub fn render(&mut self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView, context: &PixelsContext) {
+        for (id, image_delta) in &self.textures.set {
+            self.renderer.update_texture(&context.device, &context.queue, *id, image_delta);
+        }
+        self.renderer.update_buffers(&context.device, &context.queue, encoder, &self.paint_jobs, &self.screen_descriptor);
+
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("chippy_gui_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: render_target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer.render(&mut rpass, &self.paint_jobs, &self.screen_descriptor);
+        }
+
+        let textures = std::mem::take(&mut self.textures);
+        for id in &textures.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+
+/// Parses a hexadecimal address, with or without `0x` prefix
+fn parse_address(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
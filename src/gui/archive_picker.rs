@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+use egui::{Align2, Context, ScrollArea, Vec2};
+
+
+/// Asks which ROM to load from an archive that contains several
+pub struct ArchivePicker {
+    pending: Option<PendingArchive>,
+}
+struct PendingArchive {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    names: Vec<String>,
+}
+impl ArchivePicker {
+    pub fn new() -> Self {
+        Self {
+            pending: None,
+        }
+    }
+
+    pub fn open(&mut self, path: &Path, bytes: Vec<u8>, names: Vec<String>) {
+        self.pending = Some(PendingArchive { path: path.to_path_buf(), bytes, names });
+    }
+
+    /// Returns the archive's path, bytes and the name of the chosen ROM once the user picked one
+    pub fn show(&mut self, ctx: &Context) -> Option<(PathBuf, Vec<u8>, String)> {
+        let pending = self.pending.as_ref()?;
+
+        let mut chosen = None;
+        let mut open = true;
+        let title = format!("Choose a ROM from {}", pending.path.file_name().unwrap_or_default().to_string_lossy());
+        egui::Window::new(title)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for name in &pending.names {
+                        if ui.selectable_label(false, name).clicked() {
+                            chosen = Some(name.clone());
+                        }
+                    }
+                });
+            });
+
+        if !open {
+            self.pending = None;
+        }
+        let name = chosen?;
+        let pending = self.pending.take()?;
+        Some((pending.path, pending.bytes, name))
+    }
+}
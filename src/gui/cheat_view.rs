@@ -0,0 +1,129 @@
+use egui::{Context, Ui};
+use super::parse_address;
+use crate::{cheats::{Cheats, Filter}, debugger::{Debugger, expression::parse_number}, emulator::machine::Machine};
+
+/// How many search candidates are listed at most
+const MAX_RESULTS: usize = 64;
+
+
+pub struct CheatView {
+    pub visible: bool,
+    search_value: String,
+    poke_address: String,
+    poke_value: String,
+}
+impl CheatView {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            search_value: String::new(),
+            poke_address: String::new(),
+            poke_value: String::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, cheats: &mut Cheats, machine: &mut Machine, debugger: &mut Debugger) {
+        if !self.visible {
+            return;
+        }
+
+        egui::Window::new("Cheats").show(ctx, |ui| {
+            self.search(ui, cheats, machine);
+            ui.separator();
+            self.poke(ui, cheats, machine, debugger);
+            ui.separator();
+            frozen(ui, cheats);
+        });
+    }
+
+    fn search(&mut self, ui: &mut Ui, cheats: &mut Cheats, machine: &Machine) {
+        ui.horizontal(|ui| {
+            ui.heading("Search");
+            if ui.button("New search").clicked() {
+                cheats.start_search(machine);
+            }
+        });
+
+        let Some(search) = &mut cheats.search else {
+            return;
+        };
+
+        let value = parse_byte(&self.search_value);
+        ui.horizontal(|ui| {
+            if ui.add_enabled(value.is_some(), egui::Button::new("Equal to")).clicked() {
+                search.filter(machine, Filter::Equal(value.unwrap()));
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.search_value).desired_width(40.0).hint_text("value"));
+        });
+        ui.horizontal(|ui| {
+            for (label, filter) in [("Changed", Filter::Changed), ("Unchanged", Filter::Unchanged), ("Increased", Filter::Increased), ("Decreased", Filter::Decreased)] {
+                if ui.button(label).clicked() {
+                    search.filter(machine, filter);
+                }
+            }
+        });
+
+        let candidates = search.candidates();
+        ui.label(format!("{} candidates", candidates.len()));
+        let mut frozen = None;
+        egui::Grid::new("search_results").striped(true).show(ui, |ui| {
+            for &address in candidates.iter().take(MAX_RESULTS) {
+                let value = machine.memory()[address as usize];
+                ui.monospace(format!("{:04X}", address));
+                ui.monospace(format!("{:#04X} ({})", value, value));
+                if ui.small_button("Freeze").clicked() {
+                    frozen = Some((address, value));
+                }
+                ui.end_row();
+            }
+        });
+        if let Some((address, value)) = frozen {
+            cheats.freeze(address, value);
+        }
+    }
+
+    fn poke(&mut self, ui: &mut Ui, cheats: &mut Cheats, machine: &mut Machine, debugger: &mut Debugger) {
+        ui.heading("Poke");
+        let address = parse_address(&self.poke_address);
+        let value = parse_byte(&self.poke_value);
+        let valid = address.is_some() && value.is_some();
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.poke_address).desired_width(40.0).hint_text("address"));
+            ui.add(egui::TextEdit::singleline(&mut self.poke_value).desired_width(40.0).hint_text("value"));
+            if ui.add_enabled(valid, egui::Button::new("Poke")).clicked() {
+                debugger.poke(machine, address.unwrap(), value.unwrap());
+            }
+            if ui.add_enabled(valid, egui::Button::new("Freeze")).clicked() {
+                cheats.freeze(address.unwrap(), value.unwrap());
+            }
+        });
+    }
+}
+
+
+fn frozen(ui: &mut Ui, cheats: &mut Cheats) {
+    ui.heading("Frozen");
+    if cheats.frozen().is_empty() {
+        ui.label("No frozen addresses");
+    }
+
+    let mut removed = None;
+    egui::Grid::new("frozen").striped(true).show(ui, |ui| {
+        for freeze in cheats.frozen() {
+            ui.monospace(format!("{:04X}", freeze.address));
+            ui.monospace(format!("{:#04X} ({})", freeze.value, freeze.value));
+            if ui.small_button("x").clicked() {
+                removed = Some(freeze.address);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(address) = removed {
+        cheats.unfreeze(address);
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal byte
+fn parse_byte(text: &str) -> Option<u8> {
+    parse_number(text.trim()).ok().and_then(|n| u8::try_from(n).ok())
+}
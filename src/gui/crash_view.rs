@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+use egui::{Align2, Color32, Context, RichText, Vec2};
+use crate::{crash::{self, Crash}, emulator::machine::Machine};
+
+
+/// What the user wants to do about a crash
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CrashChoice {
+    Reset,
+    /// Close the error screen and look at the halted machine in the debugger
+    Debug,
+    Quit,
+}
+
+
+/// The machine state at the time of a crash, captured so the screen stays the same while it is shown
+struct CrashScreen {
+    message: String,
+    report: Option<PathBuf>,
+    pc: u16,
+    backtrace: Vec<String>,
+    disassembly: Vec<(usize, String)>,
+}
+
+
+/// An error screen shown over the display when the emulator core fails
+pub struct CrashView {
+    crash: Option<CrashScreen>,
+}
+impl CrashView {
+    pub fn new() -> Self {
+        Self {
+            crash: None,
+        }
+    }
+
+    pub fn set(&mut self, crash: &Crash, machine: &Machine) {
+        self.crash = Some(CrashScreen {
+            message: crash.message.clone(),
+            report: crash.report.clone(),
+            pc: machine.pc(),
+            backtrace: crash.backtrace.clone(),
+            disassembly: crash::disassembly_around_pc(machine),
+        });
+    }
+    pub fn clear(&mut self) {
+        self.crash = None;
+    }
+
+    pub fn show(&self, ctx: &Context) -> Option<CrashChoice> {
+        let crash = self.crash.as_ref()?;
+
+        let mut choice = None;
+        egui::Window::new("Emulation error")
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(RichText::new(&crash.message).strong().color(Color32::LIGHT_RED));
+                for frame in &crash.backtrace {
+                    ui.label(RichText::new(frame).monospace());
+                }
+                if let Some(path) = &crash.report {
+                    ui.label(format!("Crash report written to {}", path.display()));
+                }
+                ui.separator();
+
+                for (address, text) in &crash.disassembly {
+                    let marker = if *address == crash.pc as usize { ">" } else { " " };
+                    ui.label(RichText::new(format!("{} {:03X}  {}", marker, address, text)).monospace());
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        choice = Some(CrashChoice::Reset);
+                    }
+                    if ui.button("Debug").clicked() {
+                        choice = Some(CrashChoice::Debug);
+                    }
+                    if ui.button("Quit").clicked() {
+                        choice = Some(CrashChoice::Quit);
+                    }
+                });
+            });
+        choice
+    }
+}
@@ -0,0 +1,420 @@
+use egui::{Color32, Context, RichText, Ui};
+use tracing::error;
+use super::parse_address;
+use crate::{emulator::{machine::Machine, comp_mode::CompatibilityMode, instruction::disassemble, screen::PLANES}, debugger::{Debugger, InstructionClass, expression::{Expr, ExprError}, heatmap::heat_color, search::Pattern}, quirk_toggle::Quirk};
+
+const DISASSEMBLY_LINES: usize = 24;
+/// How many bytes before the target address the disassembly starts
+const DISASSEMBLY_CONTEXT: u16 = 8;
+const HEATMAP_PATH: &str = "./heatmap.ppm";
+const WRITE_HEAT_PATH: &str = "./write-heat.ppm";
+/// Rows of 16 bytes shown in the memory window
+const MEMORY_ROWS: usize = 16;
+/// How many search results are listed at most
+const MAX_MATCHES: usize = 64;
+
+
+pub struct DebugView {
+    pub visible: bool,
+    /// The address the disassembly is centered on. Follows the PC when `None`.
+    disassembly_address: Option<u16>,
+    /// Show how often every instruction in the disassembly was executed
+    heatmap: bool,
+    /// Planes shown on the display, independent of the planes the program selected
+    pub visible_planes: [bool; PLANES],
+    watches: Vec<Watch>,
+    new_watch: String,
+    range_start: String,
+    range_end: String,
+    /// Condition to run until, an expression like in the watch window
+    break_condition: String,
+    /// First address shown in the memory window, a multiple of 16
+    memory_start: u16,
+    memory_goto: String,
+    find_text: String,
+    /// Addresses of the last search's matches and the length of the pattern, or why it failed
+    found: Result<(Vec<u16>, usize), String>,
+    /// Colour the memory window by how often every byte was written
+    write_heat: bool,
+    /// Count only the writes of the last frame instead of the whole session
+    frame_heat: bool,
+}
+impl DebugView {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            disassembly_address: None,
+            heatmap: false,
+            visible_planes: [true; PLANES],
+            watches: Vec::new(),
+            new_watch: String::new(),
+            range_start: String::new(),
+            range_end: String::new(),
+            break_condition: String::new(),
+            memory_start: 0,
+            memory_goto: String::new(),
+            find_text: String::new(),
+            found: Ok((Vec::new(), 0)),
+            write_heat: false,
+            frame_heat: false,
+        }
+    }
+
+    /// Shows the debugger windows, returning the quirk the user flipped, if any
+    pub fn show(&mut self, ctx: &Context, machine: &mut Machine, comp: &CompatibilityMode, debugger: &mut Debugger) -> Option<Quirk> {
+        if !self.visible {
+            return None;
+        }
+
+        egui::Window::new("Controls").show(ctx, |ui| self.controls(ui, machine, comp, debugger));
+        egui::Window::new("Call stack").show(ctx, |ui| self.call_stack(ui, machine));
+        egui::Window::new("Disassembly").show(ctx, |ui| self.disassembly(ui, machine, debugger));
+        egui::Window::new("Watch").show(ctx, |ui| self.watch(ui, machine));
+        egui::Window::new("Memory").show(ctx, |ui| self.memory(ui, machine, debugger));
+        egui::Window::new("Quirks").show(ctx, |ui| quirks(ui, comp)).and_then(|r| r.inner).flatten()
+    }
+
+    fn controls(&mut self, ui: &mut Ui, machine: &mut Machine, comp: &CompatibilityMode, debugger: &mut Debugger) {
+        ui.horizontal(|ui| {
+            if debugger.is_paused() {
+                if ui.button("Continue").clicked() {
+                    debugger.resume();
+                }
+            }
+            else if ui.button("Pause").clicked() {
+                debugger.pause();
+            }
+
+            ui.add_enabled_ui(debugger.is_paused(), |ui| {
+                if ui.button("Step into").clicked() {
+                    debugger.step_into();
+                }
+                if ui.button("Step over").clicked() {
+                    debugger.step_over(machine);
+                }
+                if ui.button("Step out").clicked() {
+                    debugger.step_out(machine);
+                }
+            });
+        });
+
+        ui.add_enabled_ui(debugger.is_paused(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Step back").clicked() {
+                    debugger.step_back(machine, comp);
+                }
+                if ui.button("Frame back").clicked() {
+                    debugger.frame_back(machine, comp);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Run to next");
+                for class in InstructionClass::ALL {
+                    if ui.button(class.name()).clicked() {
+                        debugger.run_to_next(class);
+                    }
+                }
+                if ui.button("frame").clicked() {
+                    debugger.run_to_frame_end();
+                }
+            });
+            ui.horizontal(|ui| {
+                let start = parse_address(&self.range_start);
+                let end = parse_address(&self.range_end);
+                if ui.add_enabled(start.is_some() && end.is_some(), egui::Button::new("Run while PC in")).clicked() {
+                    debugger.run_while_pc_in(start.unwrap()..=end.unwrap());
+                }
+                ui.add(egui::TextEdit::singleline(&mut self.range_start).desired_width(40.0).hint_text("start"));
+                ui.label("-");
+                ui.add(egui::TextEdit::singleline(&mut self.range_end).desired_width(40.0).hint_text("end"));
+            });
+            ui.horizontal(|ui| {
+                let condition = Expr::parse(&self.break_condition);
+                let button = ui.add_enabled(condition.is_ok(), egui::Button::new("Run until"));
+                match condition {
+                    Ok(condition) => if button.clicked() {
+                        debugger.run_until(Box::new(move |m| condition.holds(m)));
+                    },
+                    Err(e) if !self.break_condition.trim().is_empty() => {
+                        button.on_disabled_hover_text(e.to_string());
+                    }
+                    Err(_) => {}
+                }
+                ui.add(egui::TextEdit::singleline(&mut self.break_condition).hint_text("V[3] == 2 && stack.len() > 1"));
+            });
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Show planes");
+            for (i, visible) in self.visible_planes.iter_mut().enumerate() {
+                ui.checkbox(visible, (i + 1).to_string());
+            }
+        });
+        let selected: Vec<_> = machine.screen().selected_planes().iter().enumerate()
+            .filter(|(_, &selected)| selected)
+            .map(|(i, _)| (i + 1).to_string())
+            .collect();
+        ui.label(format!("Selected by the program: {}", if selected.is_empty() { "none".to_string() } else { selected.join(", ") }));
+    }
+
+    /// Cycles the display through all planes, then each plane on its own
+    pub fn cycle_visible_planes(&mut self) -> String {
+        let shown: Vec<_> = (0..PLANES).filter(|&i| self.visible_planes[i]).collect();
+        let solo = match shown[..] {
+            [plane] if plane + 1 < PLANES => Some(plane + 1),
+            [_] => None,
+            _ => Some(0),
+        };
+
+        self.visible_planes = match solo {
+            Some(plane) => std::array::from_fn(|i| i == plane),
+            None => [true; PLANES],
+        };
+        match solo {
+            Some(plane) => format!("Showing plane {} only", plane + 1),
+            None => "Showing all planes".to_string(),
+        }
+    }
+
+    fn call_stack(&mut self, ui: &mut Ui, machine: &Machine) {
+        let stack = machine.stack();
+        if stack.is_empty() {
+            ui.label("No active calls");
+        }
+
+        for (depth, &return_address) in stack.iter().enumerate().rev() {
+            let call_site = return_address.wrapping_sub(2);
+            ui.horizontal(|ui| {
+                ui.monospace(format!("#{:<2}", depth));
+                if ui.link(RichText::new(format!("{:03X}", call_site)).monospace()).clicked() {
+                    self.disassembly_address = Some(call_site);
+                }
+                ui.monospace(format!("returns to {:03X}", return_address));
+            });
+        }
+    }
+
+    fn disassembly(&mut self, ui: &mut Ui, machine: &Machine, debugger: &mut Debugger) {
+        let pc = machine.pc();
+        if let Some(target) = self.disassembly_address {
+            ui.horizontal(|ui| {
+                ui.label(format!("Showing {:03X}", target));
+                if ui.button("Follow PC").clicked() {
+                    self.disassembly_address = None;
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.heatmap, "Heatmap");
+            if self.heatmap {
+                if ui.button("Clear").clicked() {
+                    debugger.clear_heatmap();
+                }
+                if ui.button("Save image").clicked() {
+                    if let Err(e) = debugger.heatmap().write_image(HEATMAP_PATH) {
+                        error!("Could not write heatmap to {}: {}", HEATMAP_PATH, e);
+                    }
+                }
+            }
+        });
+
+        let target = self.disassembly_address.unwrap_or(pc);
+        let memory = machine.memory();
+        let mut address = target.saturating_sub(DISASSEMBLY_CONTEXT) as usize;
+        for _ in 0..DISASSEMBLY_LINES {
+            if address >= memory.len() {
+                break;
+            }
+
+            let (text, length) = disassemble(&memory[address..]);
+            let marker = if address == pc as usize { ">" } else { " " };
+            let mut line = RichText::new(format!("{} {:03X}  {}", marker, address, text)).monospace();
+            if address == pc as usize {
+                line = line.color(Color32::YELLOW);
+            }
+            else if address == target as usize {
+                line = line.color(Color32::LIGHT_BLUE);
+            }
+            if self.heatmap {
+                let heatmap = debugger.heatmap();
+                let [r, g, b] = heatmap.color(address as u16);
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("█").monospace().color(Color32::from_rgb(r, g, b)));
+                    ui.monospace(format!("{:>8}", heatmap.count(address as u16)));
+                    ui.label(line);
+                });
+            }
+            else {
+                ui.label(line);
+            }
+
+            address += length as usize;
+        }
+    }
+
+    fn watch(&mut self, ui: &mut Ui, machine: &Machine) {
+        egui::Grid::new("watch_grid").striped(true).show(ui, |ui| {
+            watch_row(ui, "DT", Ok(machine.delay_timer() as i64));
+            ui.end_row();
+            watch_row(ui, "ST", Ok(machine.sound_timer() as i64));
+            ui.end_row();
+            watch_row(ui, "I", Ok(machine.i() as i64));
+            ui.end_row();
+
+            let mut removed = None;
+            for (index, watch) in self.watches.iter().enumerate() {
+                let value = watch.expr.as_ref().map_err(Clone::clone).and_then(|e| e.eval(machine));
+                watch_row(ui, &watch.text, value);
+                if ui.small_button("x").clicked() {
+                    removed = Some(index);
+                }
+                ui.end_row();
+            }
+            if let Some(index) = removed {
+                self.watches.remove(index);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let response = ui.text_edit_singleline(&mut self.new_watch);
+            let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            if (ui.button("Add").clicked() || submitted) && !self.new_watch.trim().is_empty() {
+                let text = std::mem::take(&mut self.new_watch);
+                self.watches.push(Watch { expr: Expr::parse(&text), text });
+            }
+        });
+    }
+
+    fn memory(&mut self, ui: &mut Ui, machine: &Machine, debugger: &mut Debugger) {
+        ui.horizontal(|ui| {
+            let response = ui.add(egui::TextEdit::singleline(&mut self.memory_goto).desired_width(40.0).hint_text("address"));
+            let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            let address = parse_address(&self.memory_goto);
+            let clicked = ui.add_enabled(address.is_some(), egui::Button::new("Go to")).clicked();
+            if let Some(address) = address.filter(|_| clicked || submitted) {
+                self.memory_start = address & !0xF;
+            }
+            let page = (MEMORY_ROWS * 16) as u16;
+            if ui.button("Up").clicked() {
+                self.memory_start = self.memory_start.saturating_sub(page);
+            }
+            if ui.button("Down").clicked() {
+                let last = (machine.memory().len() - MEMORY_ROWS * 16) as u16;
+                self.memory_start = self.memory_start.saturating_add(page).min(last);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.find_text).hint_text("F0 90 ?? 90, or a value"));
+            let memory = machine.memory();
+            if ui.button("Find bytes").clicked() {
+                self.found = Pattern::parse(&self.find_text).map(|p| (p.find(memory), p.len()));
+            }
+            if ui.button("Find value").clicked() {
+                self.found = Pattern::value(&self.find_text).map(|p| (p.find(memory), p.len()));
+            }
+        });
+        match &self.found {
+            Err(e) => {
+                ui.colored_label(Color32::RED, e);
+            }
+            Ok((matches, _)) if !self.find_text.trim().is_empty() => {
+                ui.label(format!("{} matches", matches.len()));
+                ui.horizontal_wrapped(|ui| {
+                    for &address in matches.iter().take(MAX_MATCHES) {
+                        if ui.link(RichText::new(format!("{:04X}", address)).monospace()).clicked() {
+                            self.memory_start = address & !0xF;
+                        }
+                    }
+                });
+            }
+            Ok(_) => {}
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.write_heat, "Write heat");
+            if self.write_heat {
+                ui.radio_value(&mut self.frame_heat, false, "Session");
+                ui.radio_value(&mut self.frame_heat, true, "Last frame");
+                if ui.button("Clear").clicked() {
+                    debugger.clear_write_heat();
+                }
+                if ui.button("Save image").clicked() {
+                    if let Err(e) = debugger.write_heat().write_image(WRITE_HEAT_PATH) {
+                        error!("Could not write write heat to {}: {}", WRITE_HEAT_PATH, e);
+                    }
+                }
+            }
+        });
+        ui.separator();
+
+        let (matches, length) = self.found.as_ref().map_or((&[][..], 0), |(m, l)| (&m[..], *l));
+        let highlighted = |address: usize| {
+            // The last match starting at or before the address is the only one that can cover it
+            let before = matches.partition_point(|&m| m as usize <= address);
+            before > 0 && address < matches[before - 1] as usize + length
+        };
+        let frame_writes = debugger.frame_writes();
+        let frame_max = frame_writes.values().copied().max().unwrap_or(0);
+        let heat = |address: u16| if self.frame_heat {
+            let count = frame_writes.get(&address).copied().unwrap_or(0);
+            (count, heat_color(count, frame_max))
+        }
+        else {
+            (debugger.write_heat().count(address), debugger.write_heat().color(address))
+        };
+        let memory = machine.memory();
+        for row in 0..MEMORY_ROWS {
+            let start = self.memory_start as usize + row * 16;
+            if start >= memory.len() {
+                break;
+            }
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 4.0;
+                ui.monospace(format!("{:04X} ", start));
+                for (address, byte) in memory.iter().enumerate().skip(start).take(16) {
+                    let mut text = RichText::new(format!("{:02X}", byte)).monospace();
+                    if highlighted(address) {
+                        text = text.color(Color32::YELLOW);
+                    }
+                    if !self.write_heat {
+                        ui.label(text);
+                        continue;
+                    }
+                    let (count, [r, g, b]) = heat(address as u16);
+                    ui.label(text.background_color(Color32::from_rgb(r, g, b))).on_hover_text(format!("{} writes", count));
+                }
+            });
+        }
+    }
+}
+
+
+struct Watch {
+    text: String,
+    expr: Result<Expr, ExprError>,
+}
+
+fn watch_row(ui: &mut Ui, label: &str, value: Result<i64, ExprError>) {
+    ui.monospace(label);
+    match value {
+        Ok(value) => ui.monospace(format!("{:#X} ({})", value, value)),
+        Err(e) => ui.colored_label(Color32::RED, e.to_string()),
+    };
+}
+
+
+/// Buttons flipping each quirk for the running session
+fn quirks(ui: &mut Ui, comp: &CompatibilityMode) -> Option<Quirk> {
+    let mut flipped = None;
+    egui::Grid::new("quirks").num_columns(2).show(ui, |ui| {
+        for quirk in Quirk::ALL {
+            ui.label(quirk.name());
+            if ui.button(quirk.value(comp)).clicked() {
+                flipped = Some(quirk);
+            }
+            ui.end_row();
+        }
+    });
+    flipped
+}
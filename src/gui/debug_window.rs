@@ -0,0 +1,67 @@
+use egui::Context;
+use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
+use winit::{dpi::LogicalSize, event::WindowEvent, event_loop::EventLoopWindowTarget, window::{Window, WindowBuilder, WindowId}};
+use super::Gui;
+
+const TITLE: &str = "chippy debugger";
+
+
+/// A second window hosting the debug UI, so it doesn't cover the emulated display
+pub struct DebugWindow {
+    window: Window,
+    pixels: Pixels,
+    gui: Gui,
+}
+impl DebugWindow {
+    pub fn new<T>(event_loop: &EventLoopWindowTarget<T>) -> Self {
+        let window = WindowBuilder::new()
+            .with_title(TITLE)
+            .with_inner_size(LogicalSize::new(900.0, 700.0))
+            .build(event_loop)
+            .unwrap();
+
+        // The pixel buffer is never shown, the window only needs a surface to draw egui on.
+        // Without vsync, so only the main window paces the event loop.
+        let size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+        let pixels = PixelsBuilder::new(1, 1, surface_texture)
+            .enable_vsync(false)
+            .build().unwrap();
+        let gui = Gui::new(event_loop, &window, &pixels);
+
+        Self {
+            window,
+            pixels,
+            gui,
+        }
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    /// Feeds a window event to egui, returning whether egui consumed it
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        self.gui.handle_event(event)
+    }
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.pixels.resize_surface(width, height).unwrap();
+        }
+        self.gui.resize(width, height);
+    }
+
+    pub fn render(&mut self, ui: impl FnOnce(&Context)) {
+        self.gui.prepare(&self.window, |ctx| {
+            // Covers the whole window, nothing else clears it
+            egui::CentralPanel::default().show(ctx, |_| ());
+            ui(ctx);
+        });
+
+        let gui = &mut self.gui;
+        self.pixels.render_with(|encoder, render_target, context| {
+            gui.render(encoder, render_target, context);
+            Ok(())
+        }).unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+//! A list of every key chippy reacts to, read from the configuration in use, so it shows
+//! rebound keys and the keymap of the running ROM as they are.
+
+use egui::{Align2, Context, RichText, Vec2};
+use crate::{hotkeys::Hotkeys, keymap::Keymap};
+use super::settings_view::{KEYPAD, KEYPAD_LAYOUT};
+
+
+pub struct Help {
+    pub visible: bool,
+}
+impl Help {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+        }
+    }
+
+    pub fn show(&self, ctx: &Context, keymap: &Keymap, hotkeys: &Hotkeys) {
+        if !self.visible {
+            return;
+        }
+
+        egui::Window::new("Help")
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(RichText::new("Keypad").strong());
+                egui::Grid::new("help_keypad").striped(true).show(ui, |ui| {
+                    for row in KEYPAD_LAYOUT {
+                        for key in row {
+                            ui.label(format!("{}: {:?}", KEYPAD[key], keymap.0[key]));
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+                ui.label(RichText::new("Hotkeys").strong());
+                egui::Grid::new("help_hotkeys").striped(true).show(ui, |ui| {
+                    for (action, key) in hotkeys.bindings() {
+                        ui.label(format!("{:?}", key));
+                        ui.label(action.describe());
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
@@ -0,0 +1,77 @@
+use egui::{Color32, Context, Rect, Sense, Stroke, Vec2};
+use crate::{emulator::{comp_mode::LoresSize, screen::{WIDTH, HEIGHT}}, renderer::ScalingRenderer};
+
+/// How many pixels are shown on each side of the one under the cursor
+const RADIUS: isize = 8;
+/// Size of one magnified pixel in points
+const ZOOM: f32 = 10.0;
+/// Distance of the lens from the cursor, so it does not cover what it magnifies
+const OFFSET: Vec2 = Vec2::new(24.0, 24.0);
+
+
+/// A lens following the mouse that shows the pixels around the cursor enlarged
+pub struct Magnifier {
+    pub visible: bool,
+}
+impl Magnifier {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+        }
+    }
+
+    /// Shows the lens over the rendered `frame`, if the cursor is on the emulated display.
+    /// `lores` is the resolution of lores mode if the screen is in it.
+    pub fn show(&self, ctx: &Context, frame: &[u8], renderer: &ScalingRenderer, lores: Option<LoresSize>) {
+        if !self.visible {
+            return;
+        }
+        let Some(cursor) = ctx.input().pointer.hover_pos() else {
+            return;
+        };
+        let pixels_per_point = ctx.pixels_per_point();
+        let Some((x, y)) = renderer.texel_at(cursor.x * pixels_per_point, cursor.y * pixels_per_point) else {
+            return;
+        };
+
+        egui::Area::new("magnifier")
+            .order(egui::Order::Tooltip)
+            .fixed_pos(cursor + OFFSET)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let side = (2 * RADIUS + 1) as f32 * ZOOM;
+                    let (response, painter) = ui.allocate_painter(Vec2::splat(side), Sense::hover());
+                    let origin = response.rect.min;
+
+                    for dy in -RADIUS..=RADIUS {
+                        for dx in -RADIUS..=RADIUS {
+                            let column = x as isize + dx;
+                            let row = y as isize + dy;
+                            let color = pixel_color(frame, column, row);
+                            let min = origin + Vec2::new((dx + RADIUS) as f32, (dy + RADIUS) as f32) * ZOOM;
+                            painter.rect_filled(Rect::from_min_size(min, Vec2::splat(ZOOM)), 0.0, color);
+                        }
+                    }
+
+                    let center = Rect::from_min_size(origin + Vec2::splat(RADIUS as f32 * ZOOM), Vec2::splat(ZOOM));
+                    painter.rect_stroke(center, 0.0, Stroke::new(2.0, Color32::RED));
+
+                    match lores.map(LoresSize::scale) {
+                        Some((scale_x, scale_y)) => ui.label(format!("{}, {} (lores {}, {})", x, y, x / scale_x, y / scale_y)),
+                        None => ui.label(format!("{}, {}", x, y)),
+                    };
+                });
+            });
+    }
+}
+
+
+/// The colour of a pixel in the RGBA frame, with everything off the screen drawn dark grey
+fn pixel_color(frame: &[u8], x: isize, y: isize) -> Color32 {
+    if x < 0 || y < 0 || x >= WIDTH as isize || y >= HEIGHT as isize {
+        return Color32::from_gray(32);
+    }
+    let index = (y as usize * WIDTH + x as usize) * 4;
+    Color32::from_rgb(frame[index], frame[index + 1], frame[index + 2])
+}
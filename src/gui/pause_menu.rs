@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+use egui::{Align2, Context, Vec2};
+
+/// Width of the menu's buttons in points
+const BUTTON_WIDTH: f32 = 160.0;
+
+
+/// What the user picked in the pause menu
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MenuChoice {
+    Resume,
+    Reset,
+    /// Load the ROM, archive or save state at the path
+    Open(PathBuf),
+    Settings,
+    SaveState,
+    LoadState,
+    Quit,
+}
+
+
+/// A menu over the paused game, opened with Escape
+pub struct PauseMenu {
+    pub visible: bool,
+    rom_path: String,
+}
+impl PauseMenu {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            rom_path: String::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) -> Option<MenuChoice> {
+        if !self.visible {
+            return None;
+        }
+
+        let mut choice = None;
+        egui::Window::new("Paused")
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let button = |ui: &mut egui::Ui, text: &str| ui.add_sized([BUTTON_WIDTH, 0.0], egui::Button::new(text)).clicked();
+                ui.vertical_centered(|ui| {
+                    if button(ui, "Resume") {
+                        choice = Some(MenuChoice::Resume);
+                    }
+                    if button(ui, "Reset") {
+                        choice = Some(MenuChoice::Reset);
+                    }
+                    if button(ui, "Save state") {
+                        choice = Some(MenuChoice::SaveState);
+                    }
+                    if button(ui, "Load state") {
+                        choice = Some(MenuChoice::LoadState);
+                    }
+                    if button(ui, "Settings") {
+                        choice = Some(MenuChoice::Settings);
+                    }
+                    if button(ui, "Quit") {
+                        choice = Some(MenuChoice::Quit);
+                    }
+                });
+
+                ui.separator();
+                ui.label("Open a ROM, or drop it on the window");
+                ui.horizontal(|ui| {
+                    let response = ui.add(egui::TextEdit::singleline(&mut self.rom_path).hint_text("path"));
+                    let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+                    let path = self.rom_path.trim();
+                    if (ui.add_enabled(!path.is_empty(), egui::Button::new("Open")).clicked() || submitted) && !path.is_empty() {
+                        choice = Some(MenuChoice::Open(PathBuf::from(path)));
+                    }
+                });
+            });
+        choice
+    }
+}
@@ -0,0 +1,104 @@
+//! A rolling graph of the emulation speed and the host's frame times over the last seconds,
+//! to spot stutters and relate them to what the game or chippy was doing at the time.
+
+use std::{collections::VecDeque, time::{Duration, Instant}};
+use egui::{Align2, Color32, Context, Pos2, RichText, Sense, Shape, Stroke, Vec2};
+
+/// How far back the graph reaches
+const WINDOW: Duration = Duration::from_secs(5);
+const GRAPH_WIDTH: f32 = 240.0;
+/// Height of each of the two graphs in points
+const GRAPH_HEIGHT: f32 = 40.0;
+/// The frame time graph reaches at least up to twice the 60 Hz frame budget
+const FRAME_BUDGET: Duration = Duration::from_micros(1_000_000 / 60);
+
+const IPS_COLOR: Color32 = Color32::from_rgb(230, 160, 40);
+const FRAME_COLOR: Color32 = Color32::from_rgb(60, 160, 230);
+
+
+struct Sample {
+    at: Instant,
+    /// Instructions per second emulated during the frame
+    ips: f64,
+    /// Time since the previous frame was rendered
+    frame_time: Duration,
+}
+
+
+pub struct PerfGraph {
+    pub visible: bool,
+    samples: VecDeque<Sample>,
+    /// Instructions executed since the last rendered frame
+    instructions: u64,
+    last_frame: Option<Instant>,
+}
+impl PerfGraph {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            samples: VecDeque::new(),
+            instructions: 0,
+            last_frame: None,
+        }
+    }
+
+    pub fn count_instructions(&mut self, instructions: u64) {
+        self.instructions += instructions;
+    }
+    /// Records a frame rendered at `now`, with the instructions counted since the previous one
+    pub fn record_frame(&mut self, now: Instant) {
+        if let Some(last) = self.last_frame {
+            let frame_time = now - last;
+            let ips = self.instructions as f64 / frame_time.as_secs_f64().max(f64::EPSILON);
+            self.samples.push_back(Sample { at: now, ips, frame_time });
+        }
+        self.last_frame = Some(now);
+        self.instructions = 0;
+        while self.samples.front().is_some_and(|s| now - s.at > WINDOW) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn show(&self, ctx: &Context, now: Instant) {
+        if !self.visible {
+            return;
+        }
+
+        let max_ips = self.samples.iter().map(|s| s.ips).fold(1.0, f64::max);
+        let max_frame_time = self.samples.iter().map(|s| s.frame_time).max().unwrap_or_default().max(2 * FRAME_BUDGET);
+        let current = self.samples.back();
+
+        egui::Area::new("perf_graph")
+            .anchor(Align2::LEFT_TOP, Vec2::new(8.0, 8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let ips = current.map_or(0.0, |s| s.ips);
+                    let text = format!("IPS   {:>9.0}  max {:.0}", ips, max_ips);
+                    ui.label(RichText::new(text).monospace().color(IPS_COLOR));
+                    self.graph(ui, now, |s| (s.ips / max_ips) as f32, IPS_COLOR, None);
+
+                    let frame_time = current.map_or(0.0, |s| s.frame_time.as_secs_f64() * 1000.0);
+                    let text = format!("Frame {:>6.2} ms  max {:.2} ms", frame_time, max_frame_time.as_secs_f64() * 1000.0);
+                    ui.label(RichText::new(text).monospace().color(FRAME_COLOR));
+                    let scale = max_frame_time.as_secs_f32();
+                    let budget = FRAME_BUDGET.as_secs_f32() / scale;
+                    self.graph(ui, now, |s| s.frame_time.as_secs_f32() / scale, FRAME_COLOR, Some(budget));
+                });
+            });
+    }
+
+    /// Plots `value`, from 0 to 1, over time, with an optional reference line at `mark`
+    fn graph(&self, ui: &mut egui::Ui, now: Instant, value: impl Fn(&Sample) -> f32, color: Color32, mark: Option<f32>) {
+        let (response, painter) = ui.allocate_painter(Vec2::new(GRAPH_WIDTH, GRAPH_HEIGHT), Sense::hover());
+        let area = response.rect;
+        if let Some(mark) = mark {
+            painter.hline(area.x_range(), area.bottom() - mark * GRAPH_HEIGHT, (1.0, Color32::GRAY));
+        }
+        let points = self.samples.iter().map(|s| {
+            let age = (now.saturating_duration_since(s.at).as_secs_f32() / WINDOW.as_secs_f32()).min(1.0);
+            Pos2::new(area.right() - age * GRAPH_WIDTH, area.bottom() - value(s).clamp(0.0, 1.0) * GRAPH_HEIGHT)
+        });
+        painter.add(Shape::line(points.collect(), Stroke::new(1.0, color)));
+    }
+}
@@ -0,0 +1,95 @@
+use std::{collections::VecDeque, time::Duration};
+use egui::{Align2, Color32, Context, Pos2, Rect, RichText, Sense, Vec2};
+
+/// How many frames the graph shows
+const HISTORY: usize = 120;
+/// Height of the graph in points, the top is at twice the 60 Hz frame budget
+const GRAPH_HEIGHT: f32 = 80.0;
+const GRAPH_SCALE: Duration = Duration::from_micros(2 * 1_000_000 / 60);
+const BAR_WIDTH: f32 = 2.0;
+
+const EMULATION_COLOR: Color32 = Color32::from_rgb(230, 160, 40);
+const RENDER_COLOR: Color32 = Color32::from_rgb(60, 160, 230);
+const PRESENT_COLOR: Color32 = Color32::from_rgb(120, 200, 90);
+
+
+/// Time spent on each part of one frame
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FrameTimes {
+    /// Running the machine and everything that reacts to it
+    pub emulation: Duration,
+    /// Drawing the screen and building the user interface
+    pub render: Duration,
+    /// Submitting to the GPU and presenting the frame
+    pub present: Duration,
+}
+impl FrameTimes {
+    fn parts(&self) -> [(Duration, Color32); 3] {
+        [(self.emulation, EMULATION_COLOR), (self.render, RENDER_COLOR), (self.present, PRESENT_COLOR)]
+    }
+}
+
+
+/// A graph of where the time of the recent frames went, to tell a slow interpreter from a slow GPU
+pub struct Profiler {
+    pub visible: bool,
+    frames: VecDeque<FrameTimes>,
+}
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            frames: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    pub fn record(&mut self, times: FrameTimes) {
+        if self.frames.len() == HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(times);
+    }
+
+    pub fn show(&self, ctx: &Context) {
+        if !self.visible {
+            return;
+        }
+
+        let count = self.frames.len().max(1) as u32;
+        let average = |part: fn(&FrameTimes) -> Duration| self.frames.iter().map(part).sum::<Duration>() / count;
+        let legend = [
+            ("Emulation", average(|t| t.emulation), EMULATION_COLOR),
+            ("Render", average(|t| t.render), RENDER_COLOR),
+            ("Present", average(|t| t.present), PRESENT_COLOR),
+        ];
+
+        egui::Area::new("profiler")
+            .anchor(Align2::LEFT_BOTTOM, Vec2::new(8.0, -8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let size = Vec2::new(HISTORY as f32 * BAR_WIDTH, GRAPH_HEIGHT);
+                    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+                    let area = response.rect;
+                    let scale = GRAPH_HEIGHT / GRAPH_SCALE.as_secs_f32();
+                    for (i, times) in self.frames.iter().enumerate() {
+                        let x = area.left() + i as f32 * BAR_WIDTH;
+                        let mut bottom = area.bottom();
+                        for (duration, color) in times.parts() {
+                            let top = (bottom - duration.as_secs_f32() * scale).max(area.top());
+                            painter.rect_filled(Rect::from_min_max(Pos2::new(x, top), Pos2::new(x + BAR_WIDTH, bottom)), 0.0, color);
+                            bottom = top;
+                        }
+                    }
+                    // The 60 Hz frame budget
+                    let budget = area.bottom() - GRAPH_HEIGHT / 2.0;
+                    painter.hline(area.x_range(), budget, (1.0, Color32::GRAY));
+
+                    for (name, duration, color) in legend {
+                        let text = format!("{:<9} {:>6.2} ms", name, duration.as_secs_f64() * 1000.0);
+                        ui.label(RichText::new(text).monospace().color(color));
+                    }
+                });
+            });
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+use egui::{Align2, Context, RichText, Vec2};
+
+
+/// A real time attack timer for speedruns, counting emulated frames alongside the wall time
+pub struct RunTimer {
+    pub visible: bool,
+    /// When the timer was last started, if it is running
+    started: Option<Instant>,
+    /// Time accumulated before the last start
+    elapsed: Duration,
+    frames: u64,
+}
+impl RunTimer {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            started: None,
+            elapsed: Duration::ZERO,
+            frames: 0,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+    /// Starts a stopped timer, or stops a running one. Starting also shows the timer.
+    pub fn start_stop(&mut self) {
+        match self.started.take() {
+            Some(started) => self.elapsed += started.elapsed(),
+            None => {
+                self.started = Some(Instant::now());
+                self.visible = true;
+            }
+        }
+    }
+    /// Stops the timer and sets it back to zero
+    pub fn reset(&mut self) {
+        self.started = None;
+        self.elapsed = Duration::ZERO;
+        self.frames = 0;
+    }
+
+    /// Counts an emulated frame, if the timer is running
+    pub fn count_frame(&mut self) {
+        if self.is_running() {
+            self.frames += 1;
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed + self.started.map_or(Duration::ZERO, |started| started.elapsed())
+    }
+
+    pub fn show(&self, ctx: &Context) {
+        if !self.visible {
+            return;
+        }
+
+        let elapsed = self.elapsed();
+        let centis = elapsed.as_millis() / 10;
+        let text = format!(
+            "{}:{:02}.{:02}\n{} frames",
+            centis / 6000, centis / 100 % 60, centis % 100, self.frames,
+        );
+        egui::Area::new("run_timer")
+            .anchor(Align2::RIGHT_TOP, Vec2::new(-8.0, 8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(RichText::new(text).monospace().size(18.0));
+                });
+            });
+    }
+}
@@ -0,0 +1,217 @@
+use egui::{Context, Ui};
+use crate::{config::{Config, QuirkPreset, Quirks, CUSTOM_PALETTE}, emulator::{comp_mode::*, palette}, keymap::PRESETS, preferences::RomPreferences};
+
+/// Names of the keypad keys in the order of the keymap
+pub const KEYPAD: [&str; 16] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "A", "B", "C", "D", "E", "F"];
+/// Keypad keys in the layout of the COSMAC VIP's hex keypad
+pub const KEYPAD_LAYOUT: [[usize; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+
+/// What the settings window changed in the last frame
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Changes {
+    pub config: bool,
+    /// The preferences of the running ROM
+    pub rom: bool,
+}
+
+
+/// Edits the configuration while the emulator runs
+pub struct SettingsView {
+    pub visible: bool,
+    /// The keypad key waiting for a keyboard key to be bound to it
+    pub rebinding: Option<usize>,
+}
+impl SettingsView {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            rebinding: None,
+        }
+    }
+
+    /// Shows the settings window for the config and the preferences of the running ROM
+    pub fn show(&mut self, ctx: &Context, config: &mut Config, preferences: &mut RomPreferences) -> Changes {
+        let mut changes = Changes::default();
+        if !self.visible {
+            return changes;
+        }
+
+        let changed = &mut changes.config;
+        let mut visible = self.visible;
+        egui::Window::new("Settings").open(&mut visible).show(ctx, |ui| {
+            egui::CollapsingHeader::new("Display").default_open(true).show(ui, |ui| {
+                *changed |= display(ui, config);
+            });
+            egui::CollapsingHeader::new("Speed").default_open(true).show(ui, |ui| {
+                *changed |= speed(ui, config);
+            });
+            egui::CollapsingHeader::new("Audio").default_open(true).show(ui, |ui| {
+                *changed |= audio(ui, config);
+            });
+            egui::CollapsingHeader::new("Quirks").show(ui, |ui| {
+                *changed |= quirks(ui, &mut config.quirks);
+            });
+            egui::CollapsingHeader::new("Keymap").show(ui, |ui| {
+                *changed |= self.keymap(ui, config);
+            });
+            egui::CollapsingHeader::new("This ROM").show(ui, |ui| {
+                changes.rom |= rom(ui, config, preferences);
+            });
+        });
+        self.visible = visible;
+        if !self.visible {
+            self.rebinding = None;
+        }
+
+        changes
+    }
+
+    fn keymap(&mut self, ui: &mut Ui, config: &mut Config) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Preset");
+            for (name, keymap) in PRESETS {
+                changed |= ui.selectable_value(&mut config.keymap, keymap, name).changed();
+            }
+        });
+        egui::Grid::new("keymap_grid").show(ui, |ui| {
+            for row in KEYPAD_LAYOUT {
+                for key in row {
+                    let text = if self.rebinding == Some(key) {
+                        format!("{}: ...", KEYPAD[key])
+                    }
+                    else {
+                        format!("{}: {:?}", KEYPAD[key], config.keymap.0[key])
+                    };
+                    if ui.button(text).clicked() {
+                        self.rebinding = Some(key);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if self.rebinding.is_some() {
+            ui.label("Press a key to bind it");
+        }
+        changed
+    }
+}
+
+
+fn display(ui: &mut Ui, config: &mut Config) -> bool {
+    let mut changed = false;
+    let (name, _) = config.palette();
+    let name = name.to_string();
+    let keys: Vec<String> = config.palette_keys().into_iter().map(String::from).collect();
+    egui::ComboBox::from_label("Palette").selected_text(name).show_ui(ui, |ui| {
+        for key in keys {
+            let name = palette_name(&key);
+            changed |= ui.selectable_value(&mut config.palette, key.clone(), name).changed();
+        }
+    });
+    changed |= ui.checkbox(&mut config.separate_debugger_window, "Debugger in a separate window").changed();
+    changed
+}
+
+fn speed(ui: &mut Ui, config: &mut Config) -> bool {
+    let mut changed = false;
+    let slider = egui::Slider::new(&mut config.instructions_per_frame, 1..=1000).logarithmic(true).text("Instructions per frame");
+    changed |= ui.add(slider).changed();
+    changed |= ui.add(egui::Slider::new(&mut config.frame_skip, 0..=10).text("Frame skip")).changed();
+    changed |= ui.checkbox(&mut config.pause_on_focus_loss, "Pause when unfocused").changed();
+    changed
+}
+
+fn audio(ui: &mut Ui, config: &mut Config) -> bool {
+    let mut changed = false;
+    changed |= ui.add(egui::Slider::new(&mut config.volume, 0.0..=1.0).text("Volume")).changed();
+    changed |= ui.checkbox(&mut config.muted, "Muted").changed();
+    changed
+}
+
+/// Choices that are remembered for the running ROM only, taking precedence over the config
+fn rom(ui: &mut Ui, config: &Config, preferences: &mut RomPreferences) -> bool {
+    let mut changed = false;
+    ui.label("Settings here are remembered for this ROM and override the ones above.");
+
+    let keys: Vec<String> = config.palette_keys().into_iter().map(String::from).collect();
+    let selected = preferences.palette.as_deref().map_or("Default", palette_name);
+    egui::ComboBox::from_label("Palette").selected_text(selected).show_ui(ui, |ui| {
+        changed |= ui.selectable_value(&mut preferences.palette, None, "Default").changed();
+        for key in keys {
+            let name = palette_name(&key);
+            changed |= ui.selectable_value(&mut preferences.palette, Some(key.clone()), name).changed();
+        }
+    });
+
+    ui.horizontal(|ui| {
+        let mut custom_speed = preferences.instructions_per_frame.is_some();
+        if ui.checkbox(&mut custom_speed, "Own speed").changed() {
+            preferences.instructions_per_frame = custom_speed.then_some(config.instructions_per_frame);
+            changed = true;
+        }
+        if let Some(instructions) = &mut preferences.instructions_per_frame {
+            let slider = egui::Slider::new(instructions, 1..=1000).logarithmic(true).text("Instructions per frame");
+            changed |= ui.add(slider).changed();
+        }
+    });
+
+    ui.label("Quirks");
+    changed |= quirks(ui, &mut preferences.quirks);
+    changed
+}
+
+fn palette_name(key: &str) -> &str {
+    if key == CUSTOM_PALETTE {
+        "Custom"
+    }
+    else {
+        palette::preset(key).map_or(key, |p| p.name)
+    }
+}
+
+fn quirks(ui: &mut Ui, quirks: &mut Quirks) -> bool {
+    let mut changed = false;
+    changed |= option_combo(ui, "Preset", &mut quirks.preset, &[(QuirkPreset::Chip8, "CHIP-8"), (QuirkPreset::SuperChip, "SuperChip"), (QuirkPreset::Eti660, "ETI-660")]);
+    changed |= option_combo(ui, "Shift", &mut quirks.shift, &[(ShiftMode::Original, "VY into VX"), (ShiftMode::SuperChip, "VX in place")]);
+    changed |= option_combo(ui, "Load/store", &mut quirks.load_store, &[(LoadStoreMode::Original, "Increment I"), (LoadStoreMode::SuperChip, "Leave I")]);
+    changed |= option_combo(ui, "Address space", &mut quirks.address_space, &[(AddressSpace::Original, "12 bit"), (AddressSpace::XOChip, "16 bit")]);
+    changed |= option_combo(ui, "Instructions", &mut quirks.allowed_instructions, &[
+        (AllowedInstructions::Original, "CHIP-8"),
+        (AllowedInstructions::SuperChip, "SuperChip"),
+        (AllowedInstructions::XOChip, "XO-Chip"),
+    ]);
+    changed |= option_combo(ui, "BNNN jump", &mut quirks.jump_mode, &[(RelativeJumpMode::Original, "V0"), (RelativeJumpMode::SuperChip, "VX")]);
+    changed |= option_combo(ui, "Collisions", &mut quirks.collisions, &[(CollisionEnumeration::Original, "Flag"), (CollisionEnumeration::SuperChip, "Count rows")]);
+    changed |= option_combo(ui, "Lores scroll", &mut quirks.lores_scroll, &[(LoresScrollMode::Whole, "Whole pixels"), (LoresScrollMode::Half, "Half pixels")]);
+    changed |= option_combo(ui, "DXY0 in lores", &mut quirks.large_sprites, &[(LargeSpriteMode::SuperChip, "8x16"), (LargeSpriteMode::XOChip, "16x16")]);
+    changed |= option_combo(ui, "FX1E overflow", &mut quirks.add_i_overflow, &[(AddIOverflow::Ignore, "Leave VF"), (AddIOverflow::Amiga, "Set VF")]);
+    changed |= option_combo(ui, "CXKK random", &mut quirks.random, &[(RandomMode::Modern, "Modern"), (RandomMode::Vip, "COSMAC VIP")]);
+    changed |= option_combo(ui, "Sprite origin", &mut quirks.draw_origin, &[(DrawOrigin::Wrap, "Wrap around"), (DrawOrigin::Raw, "Use as is")]);
+    let edges = [(SpriteEdge::Auto, "Clip in hires"), (SpriteEdge::Wrap, "Wrap around"), (SpriteEdge::Clip, "Clip")];
+    changed |= option_combo(ui, "Horizontal edge", &mut quirks.horizontal_edge, &edges);
+    changed |= option_combo(ui, "Vertical edge", &mut quirks.vertical_edge, &edges);
+    changed |= option_combo(ui, "Debug prints", &mut quirks.debug_print, &[(false, "Off"), (true, "On")]);
+    changed |= option_combo(ui, "Lores size", &mut quirks.lores_size, &[(LoresSize::Standard, "64x32"), (LoresSize::Eti660, "64x48"), (LoresSize::Square, "64x64")]);
+    // Takes effect when a ROM is loaded
+    changed |= option_combo(ui, "Program start", &mut quirks.program_start, &[(0x200, "0x200"), (0x600, "0x600")]);
+    changed |= option_combo(ui, "Audio voices", &mut quirks.audio_voices, &[(1, "1"), (2, "2"), (3, "3"), (4, "4")]);
+    changed
+}
+
+/// A combo box choosing one of `choices`, or `None` to use the preset's default
+fn option_combo<T: Copy + PartialEq>(ui: &mut Ui, label: &str, value: &mut Option<T>, choices: &[(T, &str)]) -> bool {
+    let selected = choices.iter()
+        .find(|(choice, _)| Some(*choice) == *value)
+        .map_or("Default", |(_, name)| name);
+
+    let mut changed = false;
+    egui::ComboBox::from_label(label).selected_text(selected).show_ui(ui, |ui| {
+        changed |= ui.selectable_value(value, None, "Default").changed();
+        for &(choice, name) in choices {
+            changed |= ui.selectable_value(value, Some(choice), name).changed();
+        }
+    });
+    changed
+}
@@ -0,0 +1,64 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+use egui::{Align2, Context, RichText, Vec2};
+
+/// How long a message stays on screen
+const DURATION: Duration = Duration::from_secs(2);
+/// Older messages are dropped when more than this are shown at once
+const MAX_SHOWN: usize = 4;
+
+
+struct Toast {
+    text: String,
+    expires: Instant,
+}
+
+
+/// Short status messages shown over the display, like "State saved" or "Volume: 50%"
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+}
+impl Toasts {
+    pub fn new() -> Self {
+        Self {
+            toasts: VecDeque::with_capacity(MAX_SHOWN),
+        }
+    }
+
+    pub fn push(&mut self, text: &str) {
+        // Repeating a message, like when holding the volume key, only extends it
+        self.toasts.retain(|toast| toast.text != text);
+        if self.toasts.len() == MAX_SHOWN {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast {
+            text: text.to_string(),
+            expires: Instant::now() + DURATION,
+        });
+    }
+
+    /// Removes the messages that have been shown long enough
+    pub fn expire(&mut self, now: Instant) {
+        self.toasts.retain(|toast| toast.expires > now);
+    }
+    /// When the next message disappears, so an idle window knows when to redraw
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.toasts.iter().map(|toast| toast.expires).min()
+    }
+
+    pub fn show(&self, ctx: &Context) {
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        egui::Area::new("toasts")
+            .anchor(Align2::CENTER_BOTTOM, Vec2::new(0.0, -16.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                for toast in &self.toasts {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(RichText::new(&toast.text).size(16.0));
+                    });
+                }
+            });
+    }
+}
@@ -0,0 +1,163 @@
+use std::{io::stdout, process::ExitCode};
+use tracing::{error, info, trace_span, warn};
+use crate::{cli::{Args, DumpFormat}, config::{Config, CONFIG_PATH}, coverage::Coverage, crash, debugger::Debugger, emulator::{keys::Keys, machine::Machine, palette::Palette, screen::{ImageFormat, TextStyle}}, flags::FlagsFile, peripheral::{Peripherals, console::Console, test_harness::TestHarness}, stats::RunStats, trace::Tracer};
+
+/// Exit code of a run that hit the frame limit, matching `timeout`
+const TIMEOUT_EXIT_CODE: u8 = 124;
+/// Exit code of a run that the emulator could not continue, matching Rust's panic exit code
+const CRASH_EXIT_CODE: u8 = 101;
+
+
+/// Runs the program without a window until it exits or the frame limit is reached.
+/// Timers tick once per frame, so the program sees the same timing as in a window.
+pub fn run(args: &Args) -> ExitCode {
+    let config = Config::load(CONFIG_PATH);
+    let program = match crate::read_program(&args.program, &args.patch) {
+        Ok(program) => program,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let settings = crate::load_rom_settings(&config, &args.program, &program);
+    let comp = settings.quirks.build();
+    let (_, palette) = settings.palette();
+    let mut machine = match crate::new_machine(&program, &comp, args.machine_seed()) {
+        Ok(machine) => machine,
+        Err(e) => {
+            error!("Could not load {}: {}", args.program.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut peripherals = Peripherals::new();
+    if let Some(address) = settings.console_address {
+        peripherals.add(Console::new(address));
+    }
+    let result = args.result_address.map(|address| {
+        let (harness, result) = TestHarness::new(address);
+        peripherals.add(harness);
+        result
+    });
+    machine.set_write_logging(!peripherals.is_empty());
+    machine.set_execution_logging(args.coverage.is_some());
+    let mut flags = args.flags.as_deref().map(FlagsFile::at);
+    if let Some(flags) = &mut flags {
+        flags.load(&mut machine);
+    }
+
+    let mut debugger = Debugger::new();
+    debugger.set_symbols(args.symbols());
+    debugger.set_profiling(args.profile.is_some());
+    if let Some(path) = &args.trace {
+        match Tracer::create(path, args.trace_format) {
+            Ok(tracer) => debugger.set_tracer(Some(tracer)),
+            Err(e) => error!("Could not create trace file {}: {}", path.display(), e),
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    let mut jit = match args.jit.then(chippy::emulator::jit::Jit::new).transpose() {
+        Ok(jit) => jit,
+        Err(e) => {
+            error!("Could not set up the JIT: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let keys = Keys::new();
+    let mut stats = RunStats::new();
+    let mut coverage = Coverage::new();
+    let screenshots = args.screenshots();
+    let mut frame = 0;
+    while !machine.has_exited() {
+        let _span = trace_span!("frame", number = frame).entered();
+        if args.frames.is_some_and(|limit| frame >= limit) {
+            warn!("Program did not exit within {} frames", frame);
+            report(args, &machine, &debugger, &palette, &stats, &coverage);
+            return ExitCode::from(TIMEOUT_EXIT_CODE);
+        }
+
+        debugger.tick(&mut machine);
+        let budget = settings.instructions_per_frame;
+        #[cfg(feature = "jit")]
+        let ran = match &mut jit {
+            Some(jit) => crash::run_with(&mut debugger, &mut machine, |debugger, machine| {
+                debugger.run_jit(jit, machine, &comp, &keys, budget);
+            }),
+            None => crash::run_frame(&mut debugger, &mut machine, &comp, &keys, budget),
+        };
+        #[cfg(not(feature = "jit"))]
+        let ran = crash::run_frame(&mut debugger, &mut machine, &comp, &keys, budget);
+        coverage.record(&machine.take_executed());
+        if let Err(crash) = ran {
+            error!("{}", crash);
+            report(args, &machine, &debugger, &palette, &stats, &coverage);
+            return ExitCode::from(CRASH_EXIT_CODE);
+        }
+        stats.count_frame(machine.take_counters());
+        for event in machine.take_screen_events() {
+            info!("Screen {}", event);
+        }
+        // Nothing ever rewinds a headless run
+        debugger.clear_history();
+        if let Some(flags) = &mut flags {
+            flags.save_changes(&machine);
+        }
+        peripherals.dispatch(&machine.take_writes());
+        for line in machine.take_debug_output() {
+            println!("{}", line);
+        }
+        frame += 1;
+        if let Some(screenshots) = &screenshots {
+            screenshots.frame(frame, machine.screen(), &palette);
+        }
+
+        if let Some(value) = result.as_ref().and_then(|r| r.get()) {
+            println!("Test result: {}", value);
+            report(args, &machine, &debugger, &palette, &stats, &coverage);
+            return ExitCode::from(value);
+        }
+    }
+
+    report(args, &machine, &debugger, &palette, &stats, &coverage);
+    ExitCode::from(machine.registers()[0])
+}
+
+/// Prints the state and the screen and writes the statistics, coverage and profile, as requested by the arguments
+fn report(args: &Args, machine: &Machine, debugger: &Debugger, palette: &Palette, stats: &RunStats, coverage: &Coverage) {
+    if let Some(path) = &args.stats {
+        if let Err(e) = stats.summary(&args.program).write(path) {
+            error!("Could not write statistics to {}: {}", path.display(), e);
+        }
+    }
+    if let Some(path) = &args.coverage {
+        if let Err(e) = coverage.write(path, &args.program, machine.program(), machine.program_start()) {
+            error!("Could not write coverage to {}: {}", path.display(), e);
+        }
+    }
+    if let (Some(path), Some(profile)) = (&args.profile, debugger.profile()) {
+        if let Err(e) = profile.write(path, machine, debugger.symbols()) {
+            error!("Could not write profile to {}: {}", path.display(), e);
+        }
+    }
+    if args.dump_state {
+        let program = machine.program_start()..machine.program_start() + machine.program().len();
+        println!("{}", machine.dump_json(&[program]));
+    }
+    if !args.dump_screen {
+        return;
+    }
+
+    let screen = machine.screen();
+    let result = match args.dump_format {
+        DumpFormat::Text => screen.write_text(stdout(), TextStyle::Ascii),
+        DumpFormat::HalfBlock => screen.write_text(stdout(), TextStyle::HalfBlock),
+        DumpFormat::Braille => screen.write_text(stdout(), TextStyle::Braille),
+        DumpFormat::Pbm => screen.write_image(stdout(), ImageFormat::Pbm, palette),
+        DumpFormat::Ppm => screen.write_image(stdout(), ImageFormat::Ppm, palette),
+    };
+    if let Err(e) = result {
+        error!("Could not dump screen: {}", e);
+    }
+}
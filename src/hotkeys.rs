@@ -0,0 +1,178 @@
+use serde::{Serialize, Deserialize};
+use winit::event::VirtualKeyCode;
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    ToggleMute,
+    VolumeUp,
+    VolumeDown,
+    CyclePalette,
+    ToggleSpriteOverlay,
+    ToggleDebugger,
+    PauseResume,
+    StepInto,
+    StepOver,
+    StepOut,
+    StepBack,
+    ToggleCheats,
+    Reset,
+    SaveState,
+    LoadState,
+    ToggleMagnifier,
+    StartStopTimer,
+    ResetTimer,
+    ToggleSettings,
+    DumpScreen,
+    ToggleProfiler,
+    ToggleFullscreen,
+    CycleVisiblePlanes,
+    SelectQuirk,
+    FlipQuirk,
+    TogglePerfGraph,
+    TogglePauseMenu,
+    ToggleHelp,
+}
+impl Action {
+    /// What the action does, as shown in the help
+    pub fn describe(self) -> &'static str {
+        match self {
+            Action::ToggleMute => "Mute or unmute",
+            Action::VolumeUp => "Volume up",
+            Action::VolumeDown => "Volume down",
+            Action::CyclePalette => "Next palette",
+            Action::ToggleSpriteOverlay => "Show or hide the sprite overlay",
+            Action::ToggleDebugger => "Show or hide the debugger",
+            Action::PauseResume => "Pause or resume",
+            Action::StepInto => "Step into",
+            Action::StepOver => "Step over",
+            Action::StepOut => "Step out",
+            Action::StepBack => "Step back",
+            Action::ToggleCheats => "Show or hide cheats",
+            Action::Reset => "Reset",
+            Action::SaveState => "Save state",
+            Action::LoadState => "Load state",
+            Action::ToggleMagnifier => "Show or hide the magnifier",
+            Action::StartStopTimer => "Start or stop the run timer",
+            Action::ResetTimer => "Reset the run timer",
+            Action::ToggleSettings => "Show or hide settings",
+            Action::DumpScreen => "Dump the screen as text",
+            Action::ToggleProfiler => "Show or hide the profiler",
+            Action::ToggleFullscreen => "Toggle fullscreen",
+            Action::CycleVisiblePlanes => "Cycle the visible planes",
+            Action::SelectQuirk => "Select the next quirk",
+            Action::FlipQuirk => "Flip the selected quirk",
+            Action::TogglePerfGraph => "Show or hide the performance graph",
+            Action::TogglePauseMenu => "Open or close the pause menu",
+            Action::ToggleHelp => "Show or hide this help",
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hotkeys {
+    pub toggle_mute: VirtualKeyCode,
+    pub volume_up: VirtualKeyCode,
+    pub volume_down: VirtualKeyCode,
+    pub cycle_palette: VirtualKeyCode,
+    pub toggle_sprite_overlay: VirtualKeyCode,
+    pub toggle_debugger: VirtualKeyCode,
+    pub pause_resume: VirtualKeyCode,
+    pub step_into: VirtualKeyCode,
+    pub step_over: VirtualKeyCode,
+    pub step_out: VirtualKeyCode,
+    pub step_back: VirtualKeyCode,
+    pub toggle_cheats: VirtualKeyCode,
+    pub reset: VirtualKeyCode,
+    pub save_state: VirtualKeyCode,
+    pub load_state: VirtualKeyCode,
+    pub toggle_magnifier: VirtualKeyCode,
+    pub start_stop_timer: VirtualKeyCode,
+    pub reset_timer: VirtualKeyCode,
+    pub toggle_settings: VirtualKeyCode,
+    pub dump_screen: VirtualKeyCode,
+    pub toggle_profiler: VirtualKeyCode,
+    pub toggle_fullscreen: VirtualKeyCode,
+    pub cycle_visible_planes: VirtualKeyCode,
+    pub select_quirk: VirtualKeyCode,
+    pub flip_quirk: VirtualKeyCode,
+    pub toggle_perf_graph: VirtualKeyCode,
+    pub toggle_pause_menu: VirtualKeyCode,
+    pub toggle_help: VirtualKeyCode,
+}
+impl Hotkeys {
+    pub fn bindings(&self) -> Vec<(Action, VirtualKeyCode)> {
+        vec![
+            (Action::ToggleMute, self.toggle_mute),
+            (Action::VolumeUp, self.volume_up),
+            (Action::VolumeDown, self.volume_down),
+            (Action::CyclePalette, self.cycle_palette),
+            (Action::ToggleSpriteOverlay, self.toggle_sprite_overlay),
+            (Action::ToggleDebugger, self.toggle_debugger),
+            (Action::PauseResume, self.pause_resume),
+            (Action::StepInto, self.step_into),
+            (Action::StepOver, self.step_over),
+            (Action::StepOut, self.step_out),
+            (Action::StepBack, self.step_back),
+            (Action::ToggleCheats, self.toggle_cheats),
+            (Action::Reset, self.reset),
+            (Action::SaveState, self.save_state),
+            (Action::LoadState, self.load_state),
+            (Action::ToggleMagnifier, self.toggle_magnifier),
+            (Action::StartStopTimer, self.start_stop_timer),
+            (Action::ResetTimer, self.reset_timer),
+            (Action::ToggleSettings, self.toggle_settings),
+            (Action::DumpScreen, self.dump_screen),
+            (Action::ToggleProfiler, self.toggle_profiler),
+            (Action::ToggleFullscreen, self.toggle_fullscreen),
+            (Action::CycleVisiblePlanes, self.cycle_visible_planes),
+            (Action::SelectQuirk, self.select_quirk),
+            (Action::FlipQuirk, self.flip_quirk),
+            (Action::TogglePerfGraph, self.toggle_perf_graph),
+            (Action::TogglePauseMenu, self.toggle_pause_menu),
+            (Action::ToggleHelp, self.toggle_help),
+        ]
+    }
+    pub fn action(&self, code: VirtualKeyCode) -> Option<Action> {
+        self.bindings()
+            .into_iter()
+            .find(|&(_, key)| key == code)
+            .map(|(action, _)| action)
+    }
+}
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self {
+            toggle_mute: VirtualKeyCode::M,
+            volume_up: VirtualKeyCode::Equals,
+            volume_down: VirtualKeyCode::Minus,
+            cycle_palette: VirtualKeyCode::P,
+            toggle_sprite_overlay: VirtualKeyCode::F2,
+            toggle_debugger: VirtualKeyCode::F3,
+            pause_resume: VirtualKeyCode::F6,
+            step_into: VirtualKeyCode::F7,
+            step_over: VirtualKeyCode::F8,
+            step_out: VirtualKeyCode::F9,
+            step_back: VirtualKeyCode::F4,
+            toggle_cheats: VirtualKeyCode::F10,
+            reset: VirtualKeyCode::F5,
+            save_state: VirtualKeyCode::F11,
+            load_state: VirtualKeyCode::F12,
+            toggle_magnifier: VirtualKeyCode::L,
+            start_stop_timer: VirtualKeyCode::T,
+            reset_timer: VirtualKeyCode::Back,
+            toggle_settings: VirtualKeyCode::O,
+            dump_screen: VirtualKeyCode::Snapshot,
+            toggle_profiler: VirtualKeyCode::G,
+            toggle_fullscreen: VirtualKeyCode::Return,
+            cycle_visible_planes: VirtualKeyCode::B,
+            select_quirk: VirtualKeyCode::H,
+            flip_quirk: VirtualKeyCode::J,
+            toggle_perf_graph: VirtualKeyCode::I,
+            toggle_pause_menu: VirtualKeyCode::Escape,
+            toggle_help: VirtualKeyCode::F1,
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Serialize, Deserialize, Serializer, Deserializer, de};
+use winit::event::VirtualKeyCode;
+
+
+/// The keyboard key bound to each of the 16 keypad keys, indexed by keypad value.
+///
+/// In config files it is either the name of a preset, like `keymap = "numpad"`, or a list of 16 keys.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Keymap(pub [VirtualKeyCode; 16]);
+impl Keymap {
+    /// The keypad key bound to `code`
+    pub fn key(&self, code: VirtualKeyCode) -> Option<u8> {
+        self.0.iter().position(|&k| k == code).map(|k| k as u8)
+    }
+
+    /// The preset called `name`
+    pub fn preset(name: &str) -> Option<Keymap> {
+        PRESETS.iter().find(|(n, _)| *n == name).map(|&(_, keymap)| keymap)
+    }
+    /// The name of the preset this keymap equals, if any
+    pub fn preset_name(&self) -> Option<&'static str> {
+        PRESETS.iter().find(|(_, keymap)| keymap == self).map(|&(name, _)| name)
+    }
+}
+impl Default for Keymap {
+    fn default() -> Self {
+        QWERTY
+    }
+}
+impl Serialize for Keymap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.preset_name() {
+            Some(name) => serializer.serialize_str(name),
+            None => self.0.serialize(serializer),
+        }
+    }
+}
+impl<'de> Deserialize<'de> for Keymap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Preset(String),
+            Keys([VirtualKeyCode; 16]),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Preset(name) => Keymap::preset(&name).ok_or_else(|| {
+                let names: Vec<_> = PRESETS.iter().map(|(name, _)| *name).collect();
+                de::Error::custom(format!("unknown keymap preset {}, expected one of {}", name, names.join(", ")))
+            }),
+            Repr::Keys(keys) => Ok(Keymap(keys)),
+        }
+    }
+}
+
+
+/// The left side of a QWERTY keyboard, laid out like the COSMAC VIP's keypad
+const QWERTY: Keymap = {
+    use VirtualKeyCode::*;
+    Keymap([
+        X, Key1, Key2, Key3,
+        Q, W, E, A,
+        S, D, Z, C,
+        Key4, R, F, V,
+    ])
+};
+/// The digits on the numeric keypad, A to F on the keys around them
+const NUMPAD: Keymap = {
+    use VirtualKeyCode::*;
+    Keymap([
+        Numpad0, Numpad1, Numpad2, Numpad3,
+        Numpad4, Numpad5, Numpad6, Numpad7,
+        Numpad8, Numpad9, NumpadDecimal, NumpadEnter,
+        NumpadDivide, NumpadMultiply, NumpadSubtract, NumpadAdd,
+    ])
+};
+pub const PRESETS: [(&str, Keymap); 2] = [("qwerty", QWERTY), ("numpad", NUMPAD)];
@@ -0,0 +1,8 @@
+//! The chippy emulator core and the pieces shared by every frontend
+
+pub mod audio;
+pub mod emulator;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
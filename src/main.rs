@@ -1,31 +1,137 @@
 #![allow(dead_code)]
 
-use std::{time::{Instant, Duration}};
-use emulator::{machine::Machine, comp_mode::{CompBuilder, CompatibilityMode}, keys::Keys};
+use std::{fs::File, io::{self, BufWriter, IsTerminal, Write}, path::{Path, PathBuf}, process::ExitCode, time::{Instant, Duration, SystemTime, UNIX_EPOCH}};
+use sound::Audio;
+use cheats::Cheats;
+use clap::Parser;
+use cli::{Args, Command};
+use config::{Config, Quirks, CONFIG_PATH};
+use coverage::Coverage;
+use debugger::{Debugger, symbols::Symbols};
+use flags::FlagsFile;
+use chippy::{audio::Pattern, emulator::{self, machine::{LoadError, Machine}, screen::{WIDTH, HEIGHT}, comp_mode::CompatibilityMode, keys::Keys, palette::Palette}};
+use gui::{Gui, archive_picker::ArchivePicker, cheat_view::CheatView, crash_view::{CrashChoice, CrashView}, debug_view::DebugView, debug_window::DebugWindow, help::Help, magnifier::Magnifier, pause_menu::{MenuChoice, PauseMenu}, perf_graph::PerfGraph, profiler::{FrameTimes, Profiler}, run_timer::RunTimer, settings_view::{Changes, SettingsView}, toasts::Toasts};
+use hotkeys::Action;
+use keymap::Keymap;
+use overlay::Overlay;
+use overrides::RomOverrides;
+use peripheral::{Peripherals, console::Console};
+use preferences::RomPreferences;
+use quirk_toggle::Quirk;
+use remote::Host;
+use renderer::ScalingRenderer;
+use screenshots::Screenshots;
+use stats::RunStats;
+use trace::Tracer;
+use udp_input::UdpInput;
 use pixels::{PixelsBuilder, SurfaceTexture, Pixels};
-use winit::{window::{Window, WindowBuilder}, event_loop::{EventLoop, ControlFlow}, platform::run_return::EventLoopExtRunReturn, event::{VirtualKeyCode, KeyboardInput, ElementState}};
+use tracing::{error, info, trace_span, warn};
+use winit::{dpi::{LogicalSize, PhysicalPosition, PhysicalSize}, window::{Fullscreen, Window, WindowBuilder}, event_loop::{EventLoop, EventLoopWindowTarget, ControlFlow}, platform::run_return::EventLoopExtRunReturn, event::{KeyboardInput, ElementState}};
 use rand::prelude::*;
 
-mod emulator;
+mod cheats;
+mod cli;
+mod config;
+mod coverage;
+mod crash;
+mod debugger;
+mod disasm;
+mod flags;
+mod flow_graph;
+mod gui;
+mod headless;
+mod hotkeys;
+mod monitor;
+mod mosaic;
+mod keymap;
+mod overlay;
+mod overrides;
+mod peripheral;
+mod preferences;
+mod quirk_toggle;
+mod remote;
+mod renderer;
+mod rom;
+mod save_state;
+mod screenshots;
+mod sound;
+mod stats;
+mod trace;
+mod transpile;
+mod udp_input;
 
 const PROGRAM_START: usize = 0x200;
-const PROGRAM: &str = "./programs/rockto.ch8";
-const INSTRUCTIONS_PER_FRAME: usize = 10;
+const WINDOW_TITLE: &str = "chippy";
+const VOLUME_STEP: f32 = 0.1;
+/// How long FX0A has to wait before the display gets a pulsing border, so short waits don't flash it
+const WAITING_INDICATOR_DELAY: Duration = Duration::from_millis(500);
+const WAITING_PULSE_PERIOD: Duration = Duration::from_millis(1500);
+/// Stalls longer than this, like the computer sleeping, are skipped instead of caught up on
+const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+/// Logical pixels per hires pixel of a new window
+const INITIAL_SCALE: f64 = 6.0;
 
-fn main() {
-    let (mut state, mut ev_loop) = State::new();
+fn main() -> ExitCode {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_max_level(args.log_level)
+        .with_writer(std::io::stderr)
+        .init();
+    crash::install_panic_hook();
 
-    ev_loop.run_return(|ev, _, cf| {
+    match &args.command {
+        Some(Command::Monitor { program, patch }) => return monitor::run(program, patch),
+        Some(Command::View { address }) => return remote::view(address),
+        Some(Command::Mosaic { programs, patch, compare_presets, columns }) => return mosaic::run(programs, patch, *compare_presets, *columns),
+        Some(Command::Transpile { program, patch, output }) => return transpile::run(program, patch, output.as_deref()),
+        Some(Command::Disasm { program, patch, html, trace, output }) => return disasm::run(program, patch, *html, trace.as_deref(), output.as_deref()),
+        Some(Command::Cfg { program, patch, output }) => return flow_graph::run(program, patch, output.as_deref()),
+        None => (),
+    }
+    if args.headless {
+        return headless::run(&args);
+    }
+    let (mut state, mut ev_loop) = match State::new(&args) {
+        Ok(state) => state,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    ev_loop.run_return(|ev, target, cf| {
         use winit::event::Event;
         use winit::event::WindowEvent;
         match ev {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => state.running = false,
-                WindowEvent::Resized(size) => state.resize(size.width, size.height),
-                WindowEvent::KeyboardInput { input, .. } => state.key_input(input),
-                _ => ()
+            Event::WindowEvent { window_id, event } if state.debug_window.as_ref().is_some_and(|w| w.id() == window_id) => {
+                let window = state.debug_window.as_mut().unwrap();
+                let consumed = window.handle_event(&event);
+                match event {
+                    WindowEvent::CloseRequested => state.debug_view.visible = false,
+                    WindowEvent::Resized(size) => window.resize(size.width, size.height),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => window.resize(new_inner_size.width, new_inner_size.height),
+                    WindowEvent::KeyboardInput { input, .. } if !consumed => state.key_input(input),
+                    WindowEvent::Focused(focused) => state.focus_changed(focused),
+                    _ => ()
+                }
+            }
+            Event::WindowEvent { event, .. } => {
+                let consumed = state.gui.handle_event(&event);
+                match event {
+                    WindowEvent::CloseRequested => state.running = false,
+                    WindowEvent::Resized(size) => state.resize(size.width, size.height),
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => state.resize(new_inner_size.width, new_inner_size.height),
+                    WindowEvent::Moved(position) => state.moved(position),
+                    WindowEvent::KeyboardInput { input, .. } if !consumed => state.key_input(input),
+                    WindowEvent::Focused(focused) => state.focus_changed(focused),
+                    WindowEvent::DroppedFile(path) => state.file_dropped(&path),
+                    _ => ()
+                }
             }
+            Event::Suspended => state.set_suspended(true),
+            Event::Resumed => state.set_suspended(false),
             Event::MainEventsCleared => {
+                state.update_debug_window(target);
                 state.update();
                 state.render();
                 state.configure_cf(cf);
@@ -33,119 +139,973 @@ fn main() {
             _ => (),
         }
     });
+
+    state.save_window_geometry();
+    state.write_stats();
+    state.write_coverage();
+    state.write_profile();
+    ExitCode::SUCCESS
 }
 
 
 struct State {
+    config: Config,
+    rom_path: PathBuf,
     comp: CompatibilityMode,
+    instructions_per_frame: usize,
     machine: Machine,
     next_decrement: Instant,
     decrement_time: Duration,
+    /// Tick the timers exactly once per emulated frame
+    deterministic: bool,
+    /// Seed for machines of newly loaded ROMs
+    seed: Option<u64>,
     window: Window,
     running: bool,
+    paused: bool,
+    /// The application was suspended by the system, which also pauses it
+    suspended: bool,
     pixels: Pixels,
+    renderer: ScalingRenderer,
+    palette: Palette,
+    sprite_overlay: bool,
+    /// Shapes drawn above the display for this frame
+    overlay: Overlay,
+    /// When the machine started waiting for a key with FX0A, if it is waiting
+    waiting_since: Option<Instant>,
+    magnifier: Magnifier,
+    debug_window: Option<DebugWindow>,
+    run_timer: RunTimer,
+    profiler: Profiler,
+    perf_graph: PerfGraph,
+    help: Help,
+    pause_menu: PauseMenu,
+    /// Time spent in the parts of the current frame, recorded once it is presented
+    frame_times: FrameTimes,
+    gui: Gui,
+    debug_view: DebugView,
+    debugger: Debugger,
+    cheats: Cheats,
+    cheat_view: CheatView,
+    settings_view: SettingsView,
+    crash_view: CrashView,
+    archive_picker: ArchivePicker,
+    /// Choices remembered for the running ROM
+    preferences: RomPreferences,
+    /// Quirks flipped at runtime, applied over the settings until another ROM is loaded
+    flipped_quirks: Quirks,
+    /// The quirk the flip hotkey flips
+    selected_quirk: Quirk,
+    peripherals: Peripherals,
+    /// Viewers playing along over the network
+    host: Option<Host>,
+    /// Key presses from controllers over the network
+    udp_input: Option<UdpInput>,
     keys: Keys,
+    keymap: Keymap,
+    stats: RunStats,
+    /// Where the statistics are written on exit
+    stats_path: Option<PathBuf>,
+    coverage: Coverage,
+    /// Where the coverage is written on exit
+    coverage_path: Option<PathBuf>,
+    /// Where the profile is written on exit
+    profile_path: Option<PathBuf>,
+    screenshots: Option<Screenshots>,
+    /// Where the user flags are kept, replaced when another ROM is loaded unless given on the command line
+    flags: FlagsFile,
+    /// Whether the flags file was given on the command line
+    fixed_flags: bool,
+    audio: Audio,
+    toasts: Toasts,
+    /// Frames run so far, for logging
+    frame: u64,
 }
 impl State {
-    fn new() -> (Self, EventLoop<()>) {
-        let program = std::fs::read(PROGRAM).unwrap();
-        let comp = CompBuilder::superchip_preset()
-            .build();
+    /// Sets up the window and the machine, the error says why the program couldn't be loaded
+    fn new(args: &Args) -> Result<(Self, EventLoop<()>), String> {
+        let config = Config::load(CONFIG_PATH);
+        let program = read_program(&args.program, &args.patch)?;
+        let settings = load_rom_settings(&config, &args.program, &program);
+        let comp = settings.quirks.build();
+        let mut machine = new_machine(&program, &comp, args.machine_seed())
+            .map_err(|e| format!("Could not load {}: {}", args.program.display(), e))?;
 
-        let mut machine = Machine::new(thread_rng().gen());
-        machine.init_instruction_pointer(PROGRAM_START as u16);
-        machine.load_sprites();
-        machine.load_program(&program, PROGRAM_START);
+        let mut peripherals = Peripherals::new();
+        if let Some(address) = config.console_address {
+            peripherals.add(Console::new(address));
+        }
+        // Writes go to the peripherals and the debugger's write heat
+        machine.set_write_logging(true);
+        machine.set_execution_logging(args.coverage.is_some());
+        let mut flags = match &args.flags {
+            Some(path) => FlagsFile::at(path),
+            None => FlagsFile::for_rom(&program),
+        };
+        flags.load(&mut machine);
 
         let next_decrement = Instant::now();
         let decrement_time = Duration::from_secs_f64(1.0 / 60.0);
 
         let ev_loop = EventLoop::new();
-        let window = WindowBuilder::new()
-            .build(&ev_loop)
-            .unwrap();
+        // Logical sizes, so the display is as large on a HiDPI screen as on any other
+        let geometry = config.window;
+        let width = geometry.width.unwrap_or(WIDTH as f64 * INITIAL_SCALE).max(WIDTH as f64);
+        let height = geometry.height.unwrap_or(HEIGHT as f64 * INITIAL_SCALE).max(HEIGHT as f64);
+        let mut builder = WindowBuilder::new()
+            .with_title(WINDOW_TITLE)
+            .with_inner_size(LogicalSize::new(width, height))
+            .with_min_inner_size(LogicalSize::new(WIDTH as f64, HEIGHT as f64))
+            .with_fullscreen(geometry.fullscreen.then_some(Fullscreen::Borderless(None)));
+        if let (Some(x), Some(y)) = (geometry.x, geometry.y) {
+            builder = builder.with_position(PhysicalPosition::new(x, y));
+        }
+        let window = builder.build(&ev_loop).unwrap();
 
-        let surface_texture = SurfaceTexture::new(window.inner_size().width, window.inner_size().height, &window);
-        let pixels = PixelsBuilder::new(128, 64, surface_texture)
+        let size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+        let pixels = PixelsBuilder::new(WIDTH as u32, HEIGHT as u32, surface_texture)
             .build().unwrap();
+        let renderer = ScalingRenderer::new(&pixels, size.width, size.height, config.scale_filter, config.scale_mode);
+        let gui = Gui::new(&ev_loop, &window, &pixels);
+
+        let audio = Audio::new(config.volume, config.muted, &config.audio_output);
+        let (_, palette) = settings.palette();
+
+        let mut debugger = Debugger::new();
+        debugger.set_rewind_minutes(config.rewind_minutes);
+        debugger.set_symbols(args.symbols());
+        debugger.set_profiling(args.profile.is_some());
+        if let Some(path) = &args.trace {
+            match Tracer::create(path, args.trace_format) {
+                Ok(tracer) => debugger.set_tracer(Some(tracer)),
+                Err(e) => error!("Could not create trace file {}: {}", path.display(), e),
+            }
+        }
+
+        let host = args.host.as_ref().and_then(|address| match Host::bind(address) {
+            Ok(host) => Some(host),
+            Err(e) => {
+                error!("Could not host on {}: {}", address, e);
+                None
+            }
+        });
+
+        let udp_input = args.udp_input.as_ref().and_then(|address| match UdpInput::bind(address) {
+            Ok(input) => Some(input),
+            Err(e) => {
+                error!("Could not accept UDP input on {}: {}", address, e);
+                None
+            }
+        });
 
         let ret = Self {
+            config,
+            rom_path: args.program.clone(),
             comp,
+            instructions_per_frame: settings.instructions_per_frame,
             machine,
             next_decrement,
             decrement_time,
+            deterministic: args.deterministic,
+            seed: args.machine_seed(),
             window,
             running: true,
+            paused: false,
+            suspended: false,
             pixels,
+            renderer,
+            palette,
+            sprite_overlay: false,
+            overlay: Overlay::new(),
+            waiting_since: None,
+            magnifier: Magnifier::new(),
+            debug_window: None,
+            run_timer: RunTimer::new(),
+            profiler: Profiler::new(),
+            perf_graph: PerfGraph::new(),
+            help: Help::new(),
+            pause_menu: PauseMenu::new(),
+            frame_times: FrameTimes::default(),
+            gui,
+            debug_view: DebugView::new(),
+            debugger,
+            cheats: Cheats::new(),
+            cheat_view: CheatView::new(),
+            settings_view: SettingsView::new(),
+            crash_view: CrashView::new(),
+            archive_picker: ArchivePicker::new(),
+            preferences: RomPreferences::load(&program),
+            flipped_quirks: Quirks::default(),
+            selected_quirk: Quirk::Shift,
+            peripherals,
+            host,
+            udp_input,
             keys: Keys::new(),
+            keymap: settings.keymap,
+            stats: RunStats::new(),
+            stats_path: args.stats.clone(),
+            coverage: Coverage::new(),
+            coverage_path: args.coverage.clone(),
+            profile_path: args.profile.clone(),
+            screenshots: args.screenshots(),
+            flags,
+            fixed_flags: args.flags.is_some(),
+            audio,
+            toasts: Toasts::new(),
+            frame: 0,
         };
 
         
 
 
-        (ret, ev_loop)
+        Ok((ret, ev_loop))
     }
 
     fn resize(&mut self, width: u32, height: u32) {
         self.pixels.resize_surface(width, height).unwrap();
+        self.renderer.resize(&self.pixels, width, height);
+        self.gui.resize(width, height);
+
+        // Remember the windowed size, so leaving fullscreen on the next run restores it
+        if self.window.fullscreen().is_none() && width > 0 && height > 0 {
+            let size = PhysicalSize::new(width, height).to_logical::<f64>(self.window.scale_factor());
+            self.config.window.width = Some(size.width);
+            self.config.window.height = Some(size.height);
+        }
+    }
+    fn moved(&mut self, position: PhysicalPosition<i32>) {
+        if self.window.fullscreen().is_none() {
+            self.config.window.x = Some(position.x);
+            self.config.window.y = Some(position.y);
+        }
+    }
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = self.window.fullscreen().is_none();
+        self.window.set_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
+        self.config.window.fullscreen = fullscreen;
     }
     fn configure_cf(&self, cf: &mut ControlFlow) {
-        if self.running {
-            *cf = ControlFlow::Poll;
+        if !self.running {
+            *cf = ControlFlow::Exit;
+        }
+        else if self.is_idle() && !self.run_timer.is_running() && self.host.is_none() && self.udp_input.is_none() {
+            // Nothing changes until an input event arrives, so sleep until then
+            *cf = match (self.toasts.next_expiry(), self.waiting_since) {
+                // Keep the waiting indicator pulsing
+                (_, Some(_)) => ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(33)),
+                (Some(expires), None) => ControlFlow::WaitUntil(expires),
+                (None, None) => ControlFlow::Wait,
+            };
         }
         else {
-            *cf = ControlFlow::Exit;
+            *cf = ControlFlow::Poll;
         }
     }
+    /// Whether the machine can't make progress without user input
+    fn is_idle(&self) -> bool {
+        if self.paused || self.debugger.is_paused() || self.machine.has_exited() {
+            return true;
+        }
+
+        // The sound timer still has to run out to stop the beep
+        self.machine.is_blocked(&self.keys) && self.machine.sound_timer() == 0
+    }
 
     fn key_input(&mut self, i: KeyboardInput) {
         if let Some(code) = i.virtual_keycode {
-            for &(key, val) in KEY_MAP {
-                if key == code {
-                    let is_down = i.state == ElementState::Pressed;
-                    self.keys.set_key(val, is_down);
+            if i.state == ElementState::Pressed {
+                if let Some(key) = self.settings_view.rebinding.take() {
+                    self.config.keymap.0[key] = code;
+                    self.apply_config();
+                    return;
                 }
+                if let Some(action) = self.config.hotkeys.action(code) {
+                    self.perform_action(action);
+                    return;
+                }
+            }
+
+            if let Some(key) = self.keymap.key(code) {
+                let is_down = i.state == ElementState::Pressed;
+                if self.keys.is_pressed(key) != is_down {
+                    self.stats.count_key_event();
+                }
+                self.keys.set_key(key, is_down);
+            }
+        }
+    }
+    fn focus_changed(&mut self, focused: bool) {
+        if !focused {
+            // Key releases are not delivered to unfocused windows
+            self.keys = Keys::new();
+        }
+        if self.config.pause_on_focus_loss {
+            self.set_paused(!focused || self.pause_menu.visible);
+        }
+    }
+    fn file_dropped(&mut self, path: &Path) {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("Could not read {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        if save_state::is_save_state(&bytes) {
+            self.restore_state(&bytes);
+        }
+        else if rom::archive::is_archive(&bytes) {
+            match rom::archive::rom_names(&bytes) {
+                Ok(names) if names.len() == 1 => self.load_from_archive(path, &bytes, &names[0]),
+                Ok(names) => self.archive_picker.open(path, bytes, names),
+                Err(e) => self.show_message(&format!("Could not open {}: {}", path.display(), e)),
+            }
+        }
+        else {
+            self.load_rom(path, &bytes);
+        }
+    }
+    fn load_from_archive(&mut self, path: &Path, archive: &[u8], name: &str) {
+        match rom::archive::extract(archive, name) {
+            Ok(program) => self.load_rom(path, &program),
+            Err(e) => self.show_message(&format!("Could not extract {}: {}", name, e)),
+        }
+    }
+    /// Replaces the running program, applying the overrides for the new ROM
+    fn load_rom(&mut self, path: &Path, program: &[u8]) {
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+        let settings = load_rom_settings(&self.config, path, program);
+        let comp = settings.quirks.build();
+        let machine = match new_machine(program, &comp, self.seed) {
+            Ok(machine) => machine,
+            Err(e) => {
+                error!("Could not load {}: {}", path.display(), e);
+                self.show_message(&format!("Could not load {}: {}", name, e));
+                return;
+            }
+        };
+
+        self.preferences = RomPreferences::load(program);
+        self.flipped_quirks = Quirks::default();
+        self.comp = comp;
+        self.instructions_per_frame = settings.instructions_per_frame;
+        self.palette = settings.palette().1;
+        self.keymap = settings.keymap;
+
+        self.replace_machine(machine);
+        if !self.fixed_flags {
+            self.flags = FlagsFile::for_rom(program);
+        }
+        self.flags.load(&mut self.machine);
+        self.coverage.clear();
+        self.rom_path = path.to_path_buf();
+        self.cheats = Cheats::new();
+        // The symbols given on the command line belong to the first ROM
+        self.debugger.set_symbols(Symbols::default());
+        self.debugger.set_profiling(self.profile_path.is_some());
+        self.show_message(&format!("Loaded {}", name));
+    }
+
+    fn replace_machine(&mut self, machine: Machine) {
+        self.machine = machine;
+        self.machine.set_draw_logging(self.sprite_overlay);
+        self.machine.set_write_logging(true);
+        self.machine.set_execution_logging(self.coverage_path.is_some());
+        self.debugger.clear_history();
+        self.crash_view.clear();
+    }
+
+    fn state_path(&self) -> PathBuf {
+        let mut path = self.rom_path.as_os_str().to_owned();
+        path.push(".state");
+        PathBuf::from(path)
+    }
+    fn save_state(&mut self) {
+        let path = self.state_path();
+        match save_state::save(&path, &self.machine) {
+            Ok(()) => self.show_message("State saved"),
+            Err(e) => error!("Could not save state to {}: {}", path.display(), e),
+        }
+    }
+    fn load_state(&mut self) {
+        let path = self.state_path();
+        match std::fs::read(&path) {
+            Ok(bytes) => self.restore_state(&bytes),
+            Err(e) => error!("Could not read state from {}: {}", path.display(), e),
+        }
+    }
+    fn restore_state(&mut self, bytes: &[u8]) {
+        match save_state::load(bytes, &self.machine) {
+            Ok(machine) => {
+                self.replace_machine(machine);
+                self.show_message("State loaded");
+            }
+            Err(e) => self.show_message(&format!("Could not load state: {}", e)),
+        }
+    }
+
+    /// Writes the screen as text to stdout when it is a terminal, otherwise to a timestamped file
+    fn dump_screen(&mut self) {
+        if io::stdout().is_terminal() {
+            if let Err(e) = self.machine.write_screen(io::stdout().lock()) {
+                error!("Could not write screen: {}", e);
+            }
+            return;
+        }
+
+        let result = create_timestamped_file("chippy-screen").and_then(|(path, file)| {
+            let mut out = BufWriter::new(file);
+            self.machine.write_screen(&mut out)?;
+            out.flush()?;
+            Ok(path)
+        });
+        match result {
+            Ok(path) => self.show_message(&format!("Screen written to {}", path.display())),
+            Err(e) => error!("Could not write screen: {}", e),
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if self.paused && !paused {
+            // Don't catch up on the timer ticks that passed while paused
+            self.next_decrement = Instant::now();
+        }
+        if paused {
+            self.audio.set_playing(false);
+        }
+        self.paused = paused;
+    }
+
+    /// Opens the pause menu and pauses, or closes it and resumes
+    fn show_pause_menu(&mut self, visible: bool) {
+        self.pause_menu.visible = visible;
+        self.set_paused(visible);
+    }
+    fn pause_menu_chosen(&mut self, choice: MenuChoice) {
+        match choice {
+            MenuChoice::Resume => self.show_pause_menu(false),
+            MenuChoice::Reset => {
+                self.reset();
+                self.show_pause_menu(false);
+            }
+            MenuChoice::Open(path) => {
+                self.file_dropped(&path);
+                self.show_pause_menu(false);
+            }
+            MenuChoice::Settings => self.settings_view.visible = true,
+            MenuChoice::SaveState => self.save_state(),
+            MenuChoice::LoadState => {
+                self.load_state();
+                self.show_pause_menu(false);
+            }
+            MenuChoice::Quit => self.running = false,
+        }
+    }
+
+    fn set_suspended(&mut self, suspended: bool) {
+        if suspended != self.suspended {
+            self.suspended = suspended;
+            self.set_paused(suspended || self.pause_menu.visible);
+        }
+    }
+
+    fn perform_action(&mut self, action: Action) {
+        match action {
+            Action::ToggleMute => self.set_muted(!self.audio.is_muted()),
+            Action::VolumeUp => self.change_volume(VOLUME_STEP),
+            Action::VolumeDown => self.change_volume(-VOLUME_STEP),
+            Action::CyclePalette => self.cycle_palette(),
+            Action::ToggleSpriteOverlay => self.toggle_sprite_overlay(),
+            Action::ToggleDebugger => self.debug_view.visible = !self.debug_view.visible,
+            Action::PauseResume => self.pause_resume(),
+            Action::StepInto => self.debugger.step_into(),
+            Action::StepOver => self.debugger.step_over(&self.machine),
+            Action::StepOut => self.debugger.step_out(&self.machine),
+            Action::StepBack => self.step_back(),
+            Action::ToggleCheats => self.cheat_view.visible = !self.cheat_view.visible,
+            Action::Reset => self.reset(),
+            Action::SaveState => self.save_state(),
+            Action::LoadState => self.load_state(),
+            Action::ToggleMagnifier => self.magnifier.visible = !self.magnifier.visible,
+            Action::StartStopTimer => self.start_stop_timer(),
+            Action::ResetTimer => {
+                self.run_timer.reset();
+                self.show_message("Timer reset");
+            }
+            Action::ToggleSettings => self.settings_view.visible = !self.settings_view.visible,
+            Action::DumpScreen => self.dump_screen(),
+            Action::ToggleProfiler => self.profiler.visible = !self.profiler.visible,
+            Action::TogglePerfGraph => self.perf_graph.visible = !self.perf_graph.visible,
+            Action::ToggleHelp => self.help.visible = !self.help.visible,
+            Action::TogglePauseMenu => self.show_pause_menu(!self.pause_menu.visible),
+            Action::ToggleFullscreen => self.toggle_fullscreen(),
+            Action::SelectQuirk => {
+                self.selected_quirk = self.selected_quirk.next();
+                let quirk = self.selected_quirk;
+                self.show_message(&format!("Quirk: {} ({})", quirk.name(), quirk.value(&self.comp)));
+            }
+            Action::FlipQuirk => self.flip_quirk(self.selected_quirk),
+            Action::CycleVisiblePlanes => {
+                let message = self.debug_view.cycle_visible_planes();
+                self.show_message(&message);
+            }
+        }
+    }
+    fn set_muted(&mut self, muted: bool) {
+        self.audio.set_muted(muted);
+        self.config.muted = muted;
+        self.save_config();
+        self.show_volume();
+    }
+    fn change_volume(&mut self, delta: f32) {
+        let volume = self.audio.volume() + delta;
+        self.audio.set_volume(volume);
+        self.config.volume = self.audio.volume();
+        self.save_config();
+        self.show_volume();
+    }
+    fn show_volume(&mut self) {
+        if self.audio.is_muted() {
+            self.show_message("Muted");
+        }
+        else {
+            let percent = (self.audio.volume() * 100.0).round();
+            self.show_message(&format!("Volume: {}%", percent));
+        }
+    }
+    /// Switches to the next palette, remembering the choice for the running ROM
+    fn cycle_palette(&mut self) {
+        let settings = rom_settings(&self.config, &self.rom_path, self.machine.program());
+        let keys = settings.palette_keys();
+        let current = keys.iter().position(|&k| k == settings.palette);
+        let next = current.map_or(0, |i| (i + 1) % keys.len());
+        self.preferences.palette = Some(keys[next].to_string());
+
+        self.save_preferences();
+        self.apply_settings();
+        let settings = rom_settings(&self.config, &self.rom_path, self.machine.program());
+        self.show_message(&format!("Palette: {}", settings.palette().0));
+    }
+    fn toggle_sprite_overlay(&mut self) {
+        self.sprite_overlay = !self.sprite_overlay;
+        self.machine.set_draw_logging(self.sprite_overlay);
+        let state = if self.sprite_overlay { "on" } else { "off" };
+        self.show_message(&format!("Sprite overlay: {}", state));
+    }
+    fn pause_resume(&mut self) {
+        if self.debugger.is_paused() {
+            self.debugger.resume();
+            self.show_message("Resumed");
+        }
+        else {
+            self.debugger.pause();
+            self.show_message("Paused");
+        }
+    }
+    /// Switches `quirk` to its other setting for the rest of the session
+    fn flip_quirk(&mut self, quirk: Quirk) {
+        quirk.flip(&self.comp, &mut self.flipped_quirks);
+        self.apply_settings();
+        self.show_message(&format!("{}: {}", quirk.name(), quirk.value(&self.comp)));
+    }
+    fn start_stop_timer(&mut self) {
+        self.run_timer.start_stop();
+        self.show_message(if self.run_timer.is_running() { "Timer started" } else { "Timer stopped" });
+    }
+    fn step_back(&mut self) {
+        if !self.debugger.step_back(&mut self.machine, &self.comp) {
+            self.show_message("No earlier state recorded");
+        }
+    }
+    fn reset(&mut self) {
+        self.machine.reset();
+        self.debugger.clear_history();
+        self.crash_view.clear();
+        self.show_message("Reset");
+    }
+    fn crash_chosen(&mut self, choice: CrashChoice) {
+        self.crash_view.clear();
+        match choice {
+            CrashChoice::Reset => {
+                self.reset();
+                self.debugger.resume();
+            }
+            CrashChoice::Debug => self.debug_view.visible = true,
+            CrashChoice::Quit => self.running = false,
+        }
+    }
+    /// Applies the edited configuration to the running emulator and saves it
+    fn apply_config(&mut self) {
+        self.apply_settings();
+        self.save_config();
+    }
+    /// Applies the config, overrides and preferences of the running ROM to the emulator
+    fn apply_settings(&mut self) {
+        let settings = rom_settings(&self.config, &self.rom_path, self.machine.program());
+        self.comp = settings.quirks.merged(&self.flipped_quirks).build();
+        self.instructions_per_frame = settings.instructions_per_frame;
+        self.palette = settings.palette().1;
+        self.keymap = settings.keymap;
+        self.audio.set_volume(self.config.volume);
+        self.audio.set_muted(self.config.muted);
+    }
+    fn save_preferences(&self) {
+        if let Err(e) = self.preferences.save(self.machine.program()) {
+            error!("Could not save preferences for {}: {}", self.rom_path.display(), e);
+        }
+    }
+    /// Stores where the window is in the config file, keeping anything else edited in it meanwhile
+    fn save_window_geometry(&self) {
+        let mut config = Config::load(CONFIG_PATH);
+        if config.window != self.config.window {
+            config.window = self.config.window;
+            if let Err(e) = config.save(CONFIG_PATH) {
+                error!("Could not save config file {}: {}", CONFIG_PATH, e);
             }
         }
     }
+    fn save_config(&self) {
+        if let Err(e) = self.config.save(CONFIG_PATH) {
+            error!("Could not save config file {}: {}", CONFIG_PATH, e);
+        }
+    }
+
+    /// Briefly shows a status message over the display
+    fn show_message(&mut self, message: &str) {
+        self.toasts.push(message);
+    }
+
     fn update(&mut self) {
         let now = Instant::now();
-        while self.next_decrement <= now {
-            self.machine.decrement_counters();
-            self.next_decrement += self.decrement_time;
+        self.toasts.expire(now);
+        if let Some(host) = &mut self.host {
+            host.update(self.machine.screen(), &mut self.keys);
+        }
+        if let Some(input) = &mut self.udp_input {
+            input.poll(&mut self.keys);
+        }
+        if self.paused {
+            return;
+        }
+        let started = Instant::now();
+        if self.debugger.is_paused() {
+            // Timers are frozen while the debugger holds the machine
+            self.next_decrement = now + self.decrement_time;
+        }
+        let behind = now.saturating_duration_since(self.next_decrement);
+        if behind > MAX_CATCH_UP {
+            info!("Emulation stalled for {:.1} s, skipping the missed frames", behind.as_secs_f64());
+            self.next_decrement = now;
+        }
+        if self.config.frame_skip == 0 && !self.deterministic {
+            while self.next_decrement <= now {
+                self.debugger.tick(&mut self.machine);
+                self.next_decrement += self.decrement_time;
+            }
+            self.run_frame();
+        }
+        else {
+            // Run one frame per timer tick, so the game keeps its speed even if not every
+            // frame gets rendered. This also keeps emulation independent of the wall clock,
+            // which only decides when the next frame is due.
+            let mut frames = 0;
+            while self.next_decrement <= now && frames <= self.config.frame_skip {
+                self.debugger.tick(&mut self.machine);
+                self.next_decrement += self.decrement_time;
+                self.run_frame();
+                frames += 1;
+            }
+            if self.next_decrement <= now {
+                // Too far behind to catch up, give up on the missed frames
+                self.next_decrement = now + self.decrement_time;
+            }
+        }
+        let pitch = self.machine.pitch();
+        self.audio.set_patterns(self.machine.audio_patterns().iter().map(|&samples| Pattern::with_pitch(samples, pitch)).collect());
+        self.audio.set_playing(self.machine.is_sound_playing());
+        self.frame_times.emulation = started.elapsed();
+    }
+
+    /// Opens or closes the separate debugger window to match the debugger's visibility
+    fn update_debug_window<T>(&mut self, target: &EventLoopWindowTarget<T>) {
+        let wanted = self.config.separate_debugger_window && self.debug_view.visible;
+        if wanted && self.debug_window.is_none() {
+            self.debug_window = Some(DebugWindow::new(target));
+        }
+        else if !wanted {
+            self.debug_window = None;
+        }
+    }
+
+    /// Runs one frame's worth of instructions along with everything that reacts to them
+    fn run_frame(&mut self) {
+        self.frame += 1;
+        let _span = trace_span!("frame", number = self.frame).entered();
+        if !self.debugger.is_paused() {
+            self.run_timer.count_frame();
+        }
+        if let Err(crash) = crash::run_frame(&mut self.debugger, &mut self.machine, &self.comp, &self.keys, self.instructions_per_frame) {
+            error!("{}", crash);
+            self.crash_view.set(&crash, &self.machine);
+        }
+        let counters = self.machine.take_counters();
+        self.perf_graph.count_instructions(counters.instructions);
+        self.stats.count_frame(counters);
+        self.coverage.record(&self.machine.take_executed());
+        for event in self.machine.take_screen_events() {
+            info!("Screen {}", event);
+        }
+        self.cheats.apply(&mut self.machine, &mut self.debugger);
+        self.flags.save_changes(&self.machine);
+        let writes = self.machine.take_writes();
+        self.debugger.record_writes(&writes);
+        self.peripherals.dispatch(&writes);
+        for line in self.machine.take_debug_output() {
+            println!("{}", line);
+        }
+        if let Some(screenshots) = &self.screenshots {
+            screenshots.frame(self.frame, self.machine.screen(), &self.palette);
+        }
+        if self.machine.has_exited() && !self.debugger.is_paused() {
+            self.debugger.pause();
+            let code = self.machine.registers()[0];
+            self.show_message(&format!("Program exited with code {}", code));
+        }
+    }
+
+    fn write_stats(&self) {
+        let Some(path) = &self.stats_path else {
+            return;
+        };
+        if let Err(e) = self.stats.summary(&self.rom_path).write(path) {
+            error!("Could not write statistics to {}: {}", path.display(), e);
         }
+    }
 
-        for _ in 0..INSTRUCTIONS_PER_FRAME {
-            self.machine.decode_and_execute(&self.comp, &self.keys);
+    /// Pulses a border around the display while the program waits for a key press
+    fn show_waiting_indicator(&mut self, now: Instant) {
+        let waiting = self.machine.waiting_for_key().is_some() && !self.debugger.is_paused() && !self.paused;
+        if !waiting {
+            self.waiting_since = None;
+            return;
+        }
+        let since = *self.waiting_since.get_or_insert(now);
+        let Some(waited) = now.checked_duration_since(since + WAITING_INDICATOR_DELAY) else {
+            return;
+        };
+
+        let phase = waited.as_secs_f32() / WAITING_PULSE_PERIOD.as_secs_f32() * std::f32::consts::TAU;
+        let alpha = (40.0 + 160.0 * (1.0 - phase.cos()) / 2.0) as u8;
+        let color = egui::Color32::from_rgba_unmultiplied(255, 255, 255, alpha);
+        self.overlay.outline(0, 0, WIDTH, HEIGHT, color);
+        self.overlay.outline(1, 1, WIDTH - 2, HEIGHT - 2, color);
+    }
+
+    fn write_coverage(&self) {
+        let Some(path) = &self.coverage_path else {
+            return;
+        };
+        if let Err(e) = self.coverage.write(path, &self.rom_path, self.machine.program(), self.machine.program_start()) {
+            error!("Could not write coverage to {}: {}", path.display(), e);
+        }
+    }
+
+    fn write_profile(&self) {
+        let (Some(path), Some(profile)) = (&self.profile_path, self.debugger.profile()) else {
+            return;
+        };
+        if let Err(e) = profile.write(path, &self.machine, self.debugger.symbols()) {
+            error!("Could not write profile to {}: {}", path.display(), e);
         }
     }
 
     fn render(&mut self) {
-        self.machine.screen().render_to_pixel_buffer(self.pixels.get_frame_mut());
-        self.pixels.render().unwrap();
+        let started = Instant::now();
+        self.overlay.clear();
+        if self.sprite_overlay {
+            let draws = self.machine.take_draws();
+            self.overlay.sprite_outlines(&draws);
+        }
+        self.show_waiting_indicator(started);
+        let frame = self.pixels.get_frame_mut();
+        self.machine.screen().render_planes_to_pixel_buffer(frame, &self.palette, self.debug_view.visible_planes);
+        let (machine, debug_view, debugger) = (&mut self.machine, &mut self.debug_view, &mut self.debugger);
+        let (cheat_view, cheats, peripherals) = (&mut self.cheat_view, &mut self.cheats, &mut self.peripherals);
+        let (magnifier, renderer, frame) = (&self.magnifier, &self.renderer, &*frame);
+        let (run_timer, profiler, perf_graph, toasts, overlay) = (&self.run_timer, &self.profiler, &self.perf_graph, &self.toasts, &self.overlay);
+        let (settings_view, config, preferences) = (&mut self.settings_view, &mut self.config, &mut self.preferences);
+        let (help, keymap) = (&self.help, &self.keymap);
+        let (crash_view, archive_picker, pause_menu) = (&self.crash_view, &mut self.archive_picker, &mut self.pause_menu);
+        let (mut crash_choice, mut archive_choice, mut menu_choice, mut flipped_quirk) = (None, None, None, None);
+        let mut changes = Changes::default();
+        let comp = &self.comp;
+        let separate_debugger = self.debug_window.is_some();
+        self.gui.prepare(&self.window, |ctx| {
+            overlay.show(ctx, renderer);
+            if !separate_debugger {
+                flipped_quirk = debug_view.show(ctx, machine, comp, debugger);
+            }
+            cheat_view.show(ctx, cheats, machine, debugger);
+            peripherals.show(ctx);
+            magnifier.show(ctx, frame, renderer, machine.screen().is_lowres().then_some(comp.lores_size));
+            run_timer.show(ctx);
+            profiler.show(ctx);
+            perf_graph.show(ctx, started);
+            toasts.show(ctx);
+            help.show(ctx, keymap, &config.hotkeys);
+            changes = settings_view.show(ctx, config, preferences);
+            crash_choice = crash_view.show(ctx);
+            archive_choice = archive_picker.show(ctx);
+            menu_choice = pause_menu.show(ctx);
+        });
+        if let Some(choice) = menu_choice {
+            self.pause_menu_chosen(choice);
+        }
+        if let Some((path, archive, name)) = archive_choice {
+            self.load_from_archive(&path, &archive, &name);
+        }
+        if let Some(choice) = crash_choice {
+            self.crash_chosen(choice);
+        }
+        if let Some(quirk) = flipped_quirk {
+            self.flip_quirk(quirk);
+        }
+        if changes.rom {
+            self.save_preferences();
+        }
+        if changes.config {
+            self.save_config();
+        }
+        if changes.config || changes.rom {
+            self.apply_settings();
+        }
+
+        let presenting = Instant::now();
+        self.frame_times.render = presenting - started;
+        let (renderer, gui) = (&self.renderer, &mut self.gui);
+        self.pixels.render_with(|encoder, render_target, context| {
+            renderer.render(encoder, render_target);
+            gui.render(encoder, render_target, context);
+            Ok(())
+        }).unwrap();
+        self.frame_times.present = presenting.elapsed();
+        self.profiler.record(std::mem::take(&mut self.frame_times));
+        self.perf_graph.record_frame(Instant::now());
+
+        if let Some(window) = &mut self.debug_window {
+            let mut flipped_quirk = None;
+            window.render(|ctx| flipped_quirk = self.debug_view.show(ctx, &mut self.machine, &self.comp, &mut self.debugger));
+            if let Some(quirk) = flipped_quirk {
+                self.flip_quirk(quirk);
+            }
+        }
     }
 }
 
 
+/// Reads the program at `path` and applies `patches` to it.
+/// Patches that can't be applied are skipped, the error says why the program itself couldn't be read.
+fn read_program(path: &Path, patches: &[PathBuf]) -> Result<Vec<u8>, String> {
+    let mut program = std::fs::read(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    if rom::archive::is_archive(&program) {
+        program = extract_from_archive(&program)
+            .map_err(|e| format!("Could not read a ROM from {}: {}", path.display(), e))?;
+    }
+    for path in patches {
+        let result = std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|patch| rom::patch::apply(&mut program, &patch).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Could not apply patch {}: {}", path.display(), e);
+        }
+    }
+    Ok(program)
+}
 
-static KEY_MAP: &[(VirtualKeyCode, u8)] = &[
-    (VirtualKeyCode::Key1, 0x1),
-    (VirtualKeyCode::Key2, 0x2),
-    (VirtualKeyCode::Key3, 0x3),
-    (VirtualKeyCode::Key4, 0xC),
+/// Creates `./{prefix}-{timestamp}.txt` for writing, never replacing an existing file.
+/// The timestamp is in milliseconds, a counter is added to it if that name is taken already.
+fn create_timestamped_file(prefix: &str) -> io::Result<(PathBuf, File)> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    let mut attempt = 0;
+    loop {
+        let path = match attempt {
+            0 => PathBuf::from(format!("./{}-{}.txt", prefix, timestamp)),
+            n => PathBuf::from(format!("./{}-{}-{}.txt", prefix, timestamp, n)),
+        };
+        match File::options().write(true).create_new(true).open(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            result => return result.map(|file| (path, file)),
+        }
+    }
+}
 
-    (VirtualKeyCode::Q, 0x4),
-    (VirtualKeyCode::W, 0x5),
-    (VirtualKeyCode::E, 0x6),
-    (VirtualKeyCode::R, 0xD),
+/// Extracts the only ROM of an archive, asking on the terminal which one to use if there are several
+fn extract_from_archive(archive: &[u8]) -> Result<Vec<u8>, rom::archive::ArchiveError> {
+    let names = rom::archive::rom_names(archive)?;
+    if names.len() == 1 || !io::stdin().is_terminal() {
+        if names.len() > 1 {
+            warn!("Archive contains {} ROMs, using {}", names.len(), names[0]);
+        }
+        return rom::archive::extract(archive, &names[0]);
+    }
 
-    (VirtualKeyCode::A, 0x7),
-    (VirtualKeyCode::S, 0x8),
-    (VirtualKeyCode::D, 0x9),
-    (VirtualKeyCode::F, 0xE),
+    for (i, name) in names.iter().enumerate() {
+        println!("{:3}  {}", i + 1, name);
+    }
+    let choice = loop {
+        print!("Which ROM? ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            break 0;
+        }
+        match line.trim().parse::<usize>() {
+            Ok(n) if (1..=names.len()).contains(&n) => break n - 1,
+            _ => println!("Enter a number from 1 to {}", names.len()),
+        }
+    };
+    rom::archive::extract(archive, &names[choice])
+}
+
+/// The user config layered with the overrides and remembered preferences for the ROM at `path`
+fn rom_settings(config: &Config, path: &Path, program: &[u8]) -> Config {
+    let overrides = RomOverrides::find(path, program).map(|(_, overrides)| overrides);
+    layer_rom_settings(config, overrides, program)
+}
+
+/// [`rom_settings`] for a ROM that is being loaded. Says which overrides are used and warns about
+/// problems with the ROM that are likely to make it fail with these settings, once per load.
+fn load_rom_settings(config: &Config, path: &Path, program: &[u8]) -> Config {
+    let overrides = RomOverrides::find(path, program).map(|(overrides_path, overrides)| {
+        info!("Using overrides from {}", overrides_path.display());
+        overrides
+    });
+    let config = layer_rom_settings(config, overrides, program);
 
-    (VirtualKeyCode::Z, 0xA),
-    (VirtualKeyCode::X, 0x0),
-    (VirtualKeyCode::C, 0xB),
-    (VirtualKeyCode::V, 0xF),
-];
+    let comp = config.quirks.build();
+    for warning in rom::check::check(program, comp.program_start as usize, &comp) {
+        warn!("{}: {}", path.display(), warning);
+    }
+    config
+}
+
+fn layer_rom_settings(config: &Config, overrides: Option<RomOverrides>, program: &[u8]) -> Config {
+    let config = match overrides {
+        Some(overrides) => overrides.apply(config),
+        None => config.clone(),
+    };
+    RomPreferences::load(program).apply(&config)
+}
+
+/// A machine running `program` from where `comp` loads programs, with a random generator
+/// seeded by `seed` or randomly without one
+fn new_machine(program: &[u8], comp: &CompatibilityMode, seed: Option<u64>) -> Result<Machine, LoadError> {
+    let mut machine = Machine::new(seed.unwrap_or_else(|| thread_rng().gen()));
+    machine.init_instruction_pointer(comp.program_start);
+    machine.load_sprites();
+    machine.load_program(program, comp.program_start as usize)?;
+    Ok(machine)
+}
@@ -0,0 +1,323 @@
+use std::{collections::BTreeMap, io::{self, BufRead, Write}, path::{Path, PathBuf}, process::ExitCode};
+use crate::{config::{Config, CONFIG_PATH}, crash, debugger::{Debugger, InstructionClass, expression::Expr, search::Pattern}, emulator::{comp_mode::CompatibilityMode, instruction::disassemble, keys::Keys, machine::Machine}};
+
+/// How many frames `go` runs at most without a count, so a program without breakpoints returns
+const DEFAULT_GO_FRAMES: u64 = 3600;
+const DEFAULT_MEM_LENGTH: usize = 0x40;
+const DEFAULT_DIS_LINES: usize = 16;
+const MAX_FIND_RESULTS: usize = 32;
+
+const HELP: &str = "\
+regs                 show registers, timers and the next instruction
+mem <addr> [len]     hex dump memory
+poke <addr> <byte>.. write bytes to memory
+find <bytes>         list where the bytes are in memory, '??' matches any byte
+findv <value>        list where a one or two byte value is in memory
+dis [addr] [lines]   disassemble, starting at the PC by default
+bp [addr] [cond]     toggle a breakpoint, set one that stops only if the condition
+                     holds, like 'bp 2A4 V[3] == 2', or list them
+eval <expr>          evaluate an expression, like 'mem[I + 2]' or 'stack.len()'
+step [n]             execute n instructions
+back                 undo the last instruction
+go [frames]          run until a breakpoint, exit or the frame limit
+until <class>        run to the next draw, call, random or store instruction
+reset                restart the program
+quit                 leave the monitor
+Addresses and bytes are hexadecimal. Timers tick once per frame run by step and go.";
+
+
+/// A classic machine monitor on the terminal, driving the machine through the debugger
+pub fn run(path: &Path, patches: &[PathBuf]) -> ExitCode {
+    let config = Config::load(CONFIG_PATH);
+    let program = match crate::read_program(path, patches) {
+        Ok(program) => program,
+        Err(e) => {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let settings = crate::load_rom_settings(&config, path, &program);
+    let comp = settings.quirks.build();
+    let machine = match crate::new_machine(&program, &comp, None) {
+        Ok(machine) => machine,
+        Err(e) => {
+            println!("Could not load {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut monitor = Monitor {
+        comp,
+        instructions_per_frame: settings.instructions_per_frame,
+        machine,
+        debugger: Debugger::new(),
+        breakpoints: BTreeMap::new(),
+    };
+
+    println!("chippy monitor, type 'help' for a list of commands");
+    monitor.regs();
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let words: Vec<_> = line.split_whitespace().collect();
+        let Some((&command, arguments)) = words.split_first() else {
+            continue;
+        };
+        if command == "quit" || command == "q" {
+            break;
+        }
+
+        if let Err(e) = monitor.command(command, arguments) {
+            println!("error: {}", e);
+        }
+        for line in monitor.machine.take_debug_output() {
+            println!("{}", line);
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+
+struct Monitor {
+    comp: CompatibilityMode,
+    instructions_per_frame: usize,
+    machine: Machine,
+    debugger: Debugger,
+    /// Breakpoints by address, with the text and expression of their condition if they have one
+    breakpoints: BTreeMap<u16, Option<(String, Expr)>>,
+}
+impl Monitor {
+    fn command(&mut self, command: &str, arguments: &[&str]) -> Result<(), String> {
+        match command {
+            "help" | "h" | "?" => println!("{}", HELP),
+            "regs" | "r" => self.regs(),
+            "mem" | "m" => {
+                let address = argument(arguments, 0)?.ok_or("expected an address")?;
+                let length = argument(arguments, 1)?.map_or(DEFAULT_MEM_LENGTH, |l| l as usize);
+                self.mem(address, length);
+            }
+            "poke" => {
+                let address = argument(arguments, 0)?.ok_or("expected an address")?;
+                if arguments.len() < 2 {
+                    return Err("expected bytes to write".into());
+                }
+                for (offset, byte) in arguments[1..].iter().enumerate() {
+                    let byte = u8::from_str_radix(byte, 16).map_err(|_| format!("invalid byte '{}'", byte))?;
+                    self.debugger.poke(&mut self.machine, address.wrapping_add(offset as u16), byte);
+                }
+            }
+            "find" | "f" => self.find(Pattern::parse(&arguments.join(" "))?),
+            "findv" => self.find(Pattern::value(&arguments.join(" "))?),
+            "dis" | "d" => {
+                let address = argument(arguments, 0)?.unwrap_or(self.machine.pc());
+                let lines = argument(arguments, 1)?.map_or(DEFAULT_DIS_LINES, |l| l as usize);
+                self.dis(address, lines);
+            }
+            "bp" | "b" => match argument(arguments, 0)? {
+                Some(address) if arguments.len() > 1 => {
+                    let text = arguments[1..].join(" ");
+                    let condition = Expr::parse(&text).map_err(|e| format!("invalid condition: {}", e))?;
+                    println!("Set breakpoint at {:03X} if {}", address, text);
+                    self.breakpoints.insert(address, Some((text, condition)));
+                }
+                Some(address) => {
+                    if self.breakpoints.remove(&address).is_some() {
+                        println!("Removed breakpoint at {:03X}", address);
+                    }
+                    else {
+                        self.breakpoints.insert(address, None);
+                        println!("Set breakpoint at {:03X}", address);
+                    }
+                }
+                None if self.breakpoints.is_empty() => println!("No breakpoints"),
+                None => {
+                    for (address, condition) in &self.breakpoints {
+                        match condition {
+                            Some((text, _)) => println!("{:03X} if {}", address, text),
+                            None => println!("{:03X}", address),
+                        }
+                    }
+                }
+            },
+            "eval" | "e" => {
+                if arguments.is_empty() {
+                    return Err("expected an expression".into());
+                }
+                let expr = Expr::parse(&arguments.join(" ")).map_err(|e| e.to_string())?;
+                let value = expr.eval(&self.machine).map_err(|e| e.to_string())?;
+                println!("{:#X} ({})", value, value);
+            }
+            "step" | "s" => {
+                let mut count = argument(arguments, 0)?.map_or(1, |n| n.max(1) as u64);
+                self.debugger.run_until(Box::new(move |_| {
+                    count -= 1;
+                    count == 0
+                }));
+                self.run(DEFAULT_GO_FRAMES);
+                self.regs();
+            }
+            "back" => {
+                if !self.debugger.step_back(&mut self.machine, &self.comp) {
+                    return Err("no earlier state recorded".into());
+                }
+                self.regs();
+            }
+            "go" | "g" => {
+                let frames = argument(arguments, 0)?.map_or(DEFAULT_GO_FRAMES, |f| f as u64);
+                let breakpoints = self.breakpoints.clone();
+                self.debugger.run_until(Box::new(move |m| match breakpoints.get(&m.pc()) {
+                    Some(Some((_, condition))) => condition.holds(m),
+                    Some(None) => true,
+                    None => false,
+                }));
+                if self.run(frames) {
+                    println!("Stopped after {} frames", frames);
+                }
+                self.regs();
+            }
+            "until" | "u" => {
+                let name = arguments.first().ok_or("expected draw, call, random or store")?;
+                let class = InstructionClass::from_name(name).ok_or_else(|| format!("unknown instruction class '{}'", name))?;
+                self.debugger.run_to_next(class);
+                if self.run(DEFAULT_GO_FRAMES) {
+                    println!("No {} instruction within {} frames", class.name(), DEFAULT_GO_FRAMES);
+                }
+                self.regs();
+            }
+            "reset" => {
+                self.machine.reset();
+                self.debugger.clear_history();
+                self.regs();
+            }
+            _ => return Err(format!("unknown command '{}', type 'help' for a list", command)),
+        }
+        Ok(())
+    }
+
+    /// Runs frames until the debugger pauses, the program exits or waits for a key, or `frames` have passed.
+    /// Returns whether the frame limit stopped it.
+    fn run(&mut self, frames: u64) -> bool {
+        // The monitor has no keypad, so a program waiting for a key can't go on
+        let keys = Keys::new();
+        let mut ran = 0;
+        while ran < frames && !self.debugger.is_paused() && !self.machine.has_exited() {
+            if self.machine.is_blocked(&keys) {
+                self.debugger.pause();
+                println!("Program is waiting for a key");
+                return false;
+            }
+            self.debugger.tick(&mut self.machine);
+            if let Err(crash) = crash::run_frame(&mut self.debugger, &mut self.machine, &self.comp, &keys, self.instructions_per_frame) {
+                println!("{}", crash);
+                return false;
+            }
+            ran += 1;
+        }
+        let limited = !self.debugger.is_paused() && !self.machine.has_exited();
+        self.debugger.pause();
+
+        if self.machine.has_exited() {
+            println!("Program exited with code {}", self.machine.registers()[0]);
+        }
+        limited
+    }
+
+    fn regs(&self) {
+        let m = &self.machine;
+        let registers: Vec<_> = m.registers().iter().enumerate()
+            .map(|(x, value)| format!("V{:X}={:02X}", x, value))
+            .collect();
+        println!("{}", registers[..8].join(" "));
+        println!("{}", registers[8..].join(" "));
+        println!("PC={:03X} I={:03X} SP={} DT={:02X} ST={:02X}", m.pc(), m.i(), m.stack().len(), m.delay_timer(), m.sound_timer());
+        if let Some(x) = m.waiting_for_key() {
+            println!("Waiting for a key press to store in {}", x);
+        }
+        self.dis(m.pc(), 1);
+    }
+
+    fn mem(&self, address: u16, length: usize) {
+        let memory = self.machine.memory();
+        let start = address as usize;
+        let end = (start + length).min(memory.len());
+        for (row, bytes) in memory[start..end].chunks(16).enumerate() {
+            let hex: Vec<_> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let text: String = bytes.iter().map(|&b| if b.is_ascii_graphic() { b as char } else { '.' }).collect();
+            println!("{:04X}  {:<47}  {}", start + row * 16, hex.join(" "), text);
+        }
+    }
+
+    fn find(&self, pattern: Pattern) {
+        let matches = pattern.find(self.machine.memory());
+        if matches.is_empty() {
+            println!("Not found");
+        }
+        for &address in matches.iter().take(MAX_FIND_RESULTS) {
+            self.mem(address, pattern.len());
+        }
+        if matches.len() > MAX_FIND_RESULTS {
+            println!("{} more matches", matches.len() - MAX_FIND_RESULTS);
+        }
+    }
+
+    fn dis(&self, address: u16, lines: usize) {
+        let memory = self.machine.memory();
+        let mut address = address as usize;
+        for _ in 0..lines {
+            if address >= memory.len() {
+                break;
+            }
+            let (text, length) = disassemble(&memory[address..]);
+            let marker = if address == self.machine.pc() as usize { ">" } else if self.breakpoints.contains_key(&(address as u16)) { "*" } else { " " };
+            println!("{} {:03X}  {}", marker, address, text);
+            address += length as usize;
+        }
+    }
+}
+
+
+/// Parses the hexadecimal argument at `index`, if present
+fn argument(arguments: &[&str], index: usize) -> Result<Option<u16>, String> {
+    let Some(text) = arguments.get(index) else {
+        return Ok(None);
+    };
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16)
+        .map(Some)
+        .map_err(|_| format!("invalid number '{}'", text))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::comp_mode::CompBuilder;
+
+    fn monitor(program: &[u8]) -> Monitor {
+        let comp = CompBuilder::new().build();
+        Monitor {
+            machine: crate::new_machine(program, &comp, Some(0)).unwrap(),
+            comp,
+            instructions_per_frame: 10,
+            debugger: Debugger::new(),
+            breakpoints: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn stepping_a_machine_waiting_for_a_key_returns() {
+        let mut monitor = monitor(&[0xF0, 0x0A, 0x12, 0x00]);
+        monitor.command("step", &[]).unwrap();
+        assert!(monitor.machine.waiting_for_key().is_some());
+        monitor.command("step", &["4"]).unwrap();
+        assert_eq!(monitor.machine.pc(), 0x202);
+        assert!(monitor.debugger.is_paused());
+    }
+}
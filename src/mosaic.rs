@@ -0,0 +1,166 @@
+//! Runs several machines side by side in one window.
+//!
+//! Every tile is an independent machine with the settings its ROM would get on its own.
+//! With `--compare-presets`, each ROM gets one tile per quirk preset instead, which makes
+//! differences between the presets easy to spot. Key presses go to all tiles.
+
+use std::{path::{Path, PathBuf}, process::ExitCode, time::{Duration, Instant}};
+use pixels::{PixelsBuilder, SurfaceTexture};
+use tracing::{error, info};
+use winit::{dpi::LogicalSize, event::{ElementState, Event, WindowEvent}, event_loop::{ControlFlow, EventLoop}, platform::run_return::EventLoopExtRunReturn, window::WindowBuilder};
+use crate::{config::{Config, QuirkPreset, CONFIG_PATH}, crash, debugger::Debugger, emulator::{comp_mode::CompatibilityMode, keys::Keys, machine::Machine, palette::Palette, screen::{HEIGHT, WIDTH}}, renderer::ScalingRenderer};
+
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+
+
+struct Tile {
+    name: String,
+    machine: Machine,
+    comp: CompatibilityMode,
+    instructions_per_frame: usize,
+    palette: Palette,
+    /// Only used for crash reports, tiles can't be debugged
+    debugger: Debugger,
+    /// Set once the machine failed, the tile then keeps its last screen
+    crashed: bool,
+}
+impl Tile {
+    fn load(config: &Config, path: &Path, patches: &[PathBuf], preset: Option<QuirkPreset>) -> Option<Self> {
+        let program = match crate::read_program(path, patches) {
+            Ok(program) => program,
+            Err(e) => {
+                error!("{}", e);
+                return None;
+            }
+        };
+        let mut settings = crate::load_rom_settings(config, path, &program);
+        let mut name = path.display().to_string();
+        if let Some(preset) = preset {
+            settings.quirks.preset = Some(preset);
+            name = format!("{} ({:?})", name, preset);
+        }
+
+        let comp = settings.quirks.build();
+        let machine = match crate::new_machine(&program, &comp, None) {
+            Ok(machine) => machine,
+            Err(e) => {
+                error!("Could not load {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        Some(Self {
+            name,
+            machine,
+            comp,
+            instructions_per_frame: settings.instructions_per_frame,
+            palette: settings.palette().1,
+            debugger: Debugger::new(),
+            crashed: false,
+        })
+    }
+
+    fn run_frame(&mut self, keys: &Keys) {
+        if self.crashed {
+            return;
+        }
+        let (comp, budget) = (&self.comp, self.instructions_per_frame);
+        let ran = crash::run_with(&mut self.debugger, &mut self.machine, |_, machine| {
+            machine.run_frame(budget, comp, keys);
+        });
+        if let Err(crash) = ran {
+            error!("{}: {}", self.name, crash);
+            self.crashed = true;
+        }
+    }
+}
+
+
+/// Runs the programs in a grid with `columns` columns, or a roughly square one without
+pub fn run(programs: &[PathBuf], patches: &[PathBuf], compare_presets: bool, columns: Option<usize>) -> ExitCode {
+    let config = Config::load(CONFIG_PATH);
+    let presets: &[Option<QuirkPreset>] = if compare_presets {
+        &[Some(QuirkPreset::Chip8), Some(QuirkPreset::SuperChip)]
+    }
+    else {
+        &[None]
+    };
+    let mut tiles: Vec<_> = programs.iter()
+        .flat_map(|path| presets.iter().map(move |&preset| (path, preset)))
+        .filter_map(|(path, preset)| Tile::load(&config, path, patches, preset))
+        .collect();
+    if tiles.is_empty() {
+        error!("No program could be loaded");
+        return ExitCode::FAILURE;
+    }
+
+    let columns = columns.unwrap_or_else(|| (tiles.len() as f64).sqrt().ceil() as usize).clamp(1, tiles.len());
+    let rows = tiles.len().div_ceil(columns);
+    for (index, tile) in tiles.iter().enumerate() {
+        info!("Tile {} at row {}, column {}: {}", index, index / columns + 1, index % columns + 1, tile.name);
+    }
+
+    let (width, height) = (WIDTH * columns, HEIGHT * rows);
+    // Grids open a bit larger than the window of a single machine
+    let scale = (crate::INITIAL_SCALE * 1.5 / columns.max(rows) as f64).clamp(1.0, crate::INITIAL_SCALE);
+    let mut ev_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(format!("chippy - mosaic of {} machines", tiles.len()))
+        .with_inner_size(LogicalSize::new(width as f64 * scale, height as f64 * scale))
+        .with_min_inner_size(LogicalSize::new(width as f64, height as f64))
+        .build(&ev_loop)
+        .unwrap();
+    let size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+    let mut pixels = PixelsBuilder::new(width as u32, height as u32, surface_texture).build().unwrap();
+    let mut renderer = ScalingRenderer::new(&pixels, size.width, size.height, config.scale_filter, config.scale_mode);
+
+    let mut keys = Keys::new();
+    let mut tile_buffer = vec![0; WIDTH * HEIGHT * 4];
+    let mut next_frame = Instant::now();
+    ev_loop.run_return(|event, _, cf| {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *cf = ControlFlow::Exit,
+                WindowEvent::Resized(size) | WindowEvent::ScaleFactorChanged { new_inner_size: &mut size, .. } => {
+                    pixels.resize_surface(size.width, size.height).unwrap();
+                    renderer.resize(&pixels, size.width, size.height);
+                }
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if let Some(key) = input.virtual_keycode.and_then(|code| config.keymap.key(code)) {
+                        keys.set_key(key, input.state == ElementState::Pressed);
+                    }
+                }
+                _ => (),
+            },
+            Event::MainEventsCleared => {
+                let now = Instant::now();
+                if now >= next_frame {
+                    for tile in &mut tiles {
+                        tile.run_frame(&keys);
+                    }
+                    next_frame = (next_frame + FRAME_DURATION).max(now);
+                    window.request_redraw();
+                }
+                *cf = ControlFlow::WaitUntil(next_frame);
+            }
+            Event::RedrawRequested(_) => {
+                let frame = pixels.get_frame_mut();
+                for (index, tile) in tiles.iter().enumerate() {
+                    tile.machine.screen().render_to_pixel_buffer(&mut tile_buffer, &tile.palette);
+                    let (left, top) = (index % columns * WIDTH, index / columns * HEIGHT);
+                    for (y, row) in tile_buffer.chunks_exact(WIDTH * 4).enumerate() {
+                        let start = ((top + y) * width + left) * 4;
+                        frame[start..start + row.len()].copy_from_slice(row);
+                    }
+                }
+                pixels.render_with(|encoder, render_target, _| {
+                    renderer.render(encoder, render_target);
+                    Ok(())
+                }).unwrap();
+            }
+            _ => (),
+        }
+    });
+
+    ExitCode::SUCCESS
+}
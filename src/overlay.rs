@@ -0,0 +1,91 @@
+//! Shapes and text drawn above the emulated screen without touching its pixels.
+//!
+//! Positions and sizes are in hires pixels, so the overlay follows the display however it is
+//! scaled, and the machine's screen and the pixel buffer stay exactly what the program drew.
+
+use egui::{Align2, Color32, Context, FontId, Id, LayerId, Order, Pos2, Rect, Stroke};
+use crate::{emulator::screen::SpriteDraw, renderer::ScalingRenderer};
+
+
+enum Shape {
+    Outline { rect: Rect, color: Color32 },
+    Fill { rect: Rect, color: Color32 },
+    /// `size` is the height of the text
+    Text { position: Pos2, text: String, size: f32, color: Color32 },
+}
+
+
+/// A layer of shapes composited above the display, collected anew every frame
+pub struct Overlay {
+    shapes: Vec<Shape>,
+}
+impl Overlay {
+    pub fn new() -> Self {
+        Self {
+            shapes: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    /// Outlines the pixels from `x, y` to `x + width - 1, y + height - 1`
+    pub fn outline(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color32) {
+        self.shapes.push(Shape::Outline { rect: pixel_rect(x, y, width, height), color });
+    }
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color32) {
+        self.shapes.push(Shape::Fill { rect: pixel_rect(x, y, width, height), color });
+    }
+    /// Writes `text` with its top left corner at `x, y`, `size` pixels high
+    pub fn text(&mut self, x: usize, y: usize, text: &str, size: f32, color: Color32) {
+        let position = Pos2::new(x as f32, y as f32);
+        self.shapes.push(Shape::Text { position, text: text.to_string(), size, color });
+    }
+
+    /// Outlines the area of every recorded draw, coloured by the planes it was drawn to
+    pub fn sprite_outlines(&mut self, draws: &[SpriteDraw]) {
+        for draw in draws {
+            let color = match draw.planes {
+                [true, false] => Color32::from_rgb(255, 0, 0),
+                [false, true] => Color32::from_rgb(0, 128, 255),
+                _ => Color32::from_rgb(255, 0, 255),
+            };
+            self.outline(draw.x, draw.y, draw.width, draw.height, color);
+        }
+    }
+
+    /// Paints the shapes over the display, below any windows
+    pub fn show(&self, ctx: &Context, renderer: &ScalingRenderer) {
+        if self.shapes.is_empty() {
+            return;
+        }
+
+        let pixels_per_point = ctx.pixels_per_point();
+        let (origin_x, origin_y, scale_x, scale_y) = renderer.texel_transform();
+        let to_screen = |p: Pos2| Pos2::new(
+            (origin_x + p.x * scale_x) / pixels_per_point,
+            (origin_y + p.y * scale_y) / pixels_per_point,
+        );
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("overlay")));
+        for shape in &self.shapes {
+            match shape {
+                Shape::Outline { rect, color } => {
+                    let rect = Rect::from_min_max(to_screen(rect.min), to_screen(rect.max));
+                    painter.rect_stroke(rect.shrink(0.5), 0.0, Stroke::new(1.0, *color));
+                }
+                Shape::Fill { rect, color } => {
+                    painter.rect_filled(Rect::from_min_max(to_screen(rect.min), to_screen(rect.max)), 0.0, *color);
+                }
+                Shape::Text { position, text, size, color } => {
+                    let font = FontId::monospace(size * scale_y / pixels_per_point);
+                    painter.text(to_screen(*position), Align2::LEFT_TOP, text, font, *color);
+                }
+            }
+        }
+    }
+}
+
+fn pixel_rect(x: usize, y: usize, width: usize, height: usize) -> Rect {
+    Rect::from_min_size(Pos2::new(x as f32, y as f32), egui::vec2(width as f32, height as f32))
+}
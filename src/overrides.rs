@@ -0,0 +1,74 @@
+use std::{fs, io, path::{Path, PathBuf}};
+use serde::Deserialize;
+use tracing::warn;
+use crate::{config::{Config, Quirks}, emulator::palette::Palette, keymap::Keymap, rom};
+
+/// Directory holding override files named after the hash of the ROM they apply to
+pub const OVERRIDES_DIR: &str = "./overrides";
+
+
+/// Settings pinned for a single ROM, taking precedence over the user config.
+///
+/// They are read from a sidecar file next to the ROM (`game.ch8.chippy.toml`),
+/// or otherwise from `overrides/<hash>.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RomOverrides {
+    pub quirks: Quirks,
+    pub instructions_per_frame: Option<usize>,
+    pub palette: Option<String>,
+    pub custom_palette: Option<Palette>,
+    pub keymap: Option<Keymap>,
+}
+impl RomOverrides {
+    /// Loads the overrides for the ROM at `rom_path`, if there are any
+    pub fn find(rom_path: &Path, program: &[u8]) -> Option<(PathBuf, Self)> {
+        let mut sidecar = rom_path.as_os_str().to_owned();
+        sidecar.push(".chippy.toml");
+        let by_hash = Path::new(OVERRIDES_DIR).join(format!("{}.toml", rom::hash_string(program)));
+
+        for path in [PathBuf::from(sidecar), by_hash] {
+            if let Some(overrides) = Self::load(&path) {
+                return Some((path, overrides));
+            }
+        }
+        None
+    }
+    fn load(path: &Path) -> Option<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Could not read override file {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        match toml::from_str(&text) {
+            Ok(overrides) => Some(overrides),
+            Err(e) => {
+                warn!("Could not parse override file {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// The settings to run the ROM with, `config` layered with these overrides
+    pub fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        config.quirks = config.quirks.merged(&self.quirks);
+        if let Some(instructions) = self.instructions_per_frame {
+            config.instructions_per_frame = instructions;
+        }
+        if let Some(palette) = &self.palette {
+            config.palette = palette.clone();
+        }
+        if self.custom_palette.is_some() {
+            config.custom_palette = self.custom_palette;
+        }
+        if let Some(keymap) = self.keymap {
+            config.keymap = keymap;
+        }
+        config
+    }
+}
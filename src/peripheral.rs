@@ -0,0 +1,58 @@
+use egui::{Context, Ui};
+use crate::emulator::machine::MemoryWrite;
+
+pub mod console;
+pub mod test_harness;
+
+
+/// A device mapped into the address space, notified of every write the program makes to it
+pub trait Peripheral {
+    fn name(&self) -> &str;
+    fn handles(&self, address: u16) -> bool;
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Draws the panel of the peripheral, if it has one
+    fn show(&mut self, _ui: &mut Ui) {}
+    fn has_panel(&self) -> bool {
+        false
+    }
+}
+
+
+pub struct Peripherals {
+    devices: Vec<Box<dyn Peripheral>>,
+}
+impl Peripherals {
+    pub fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, device: impl Peripheral + 'static) {
+        self.devices.push(Box::new(device));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Forwards `writes`, taken from the machine, to the devices mapped there.
+    /// The machine only records writes while write logging is enabled.
+    pub fn dispatch(&mut self, writes: &[MemoryWrite]) {
+        for write in writes {
+            for device in &mut self.devices {
+                if device.handles(write.address) {
+                    device.write(write.address, write.value);
+                }
+            }
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        for device in &mut self.devices {
+            if device.has_panel() {
+                egui::Window::new(device.name()).show(ctx, |ui| device.show(ui));
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+use egui::Ui;
+use super::Peripheral;
+
+/// How many finished lines the log pane keeps
+const MAX_LINES: usize = 500;
+
+
+/// Treats every byte written to one address as a character of text output.
+/// Finished lines are printed to stdout and kept for the log pane.
+pub struct Console {
+    address: u16,
+    line: String,
+    lines: VecDeque<String>,
+}
+impl Console {
+    pub fn new(address: u16) -> Self {
+        Self {
+            address,
+            line: String::new(),
+            lines: VecDeque::new(),
+        }
+    }
+
+    fn finish_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        println!("{}", line);
+        if self.lines.len() >= MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+impl Peripheral for Console {
+    fn name(&self) -> &str {
+        "Console"
+    }
+    fn handles(&self, address: u16) -> bool {
+        address == self.address
+    }
+    fn write(&mut self, _address: u16, value: u8) {
+        match value {
+            b'\n' => self.finish_line(),
+            b'\r' => (),
+            _ => self.line.push(value as char),
+        }
+    }
+
+    fn show(&mut self, ui: &mut Ui) {
+        egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+            for line in &self.lines {
+                ui.monospace(line);
+            }
+            if !self.line.is_empty() {
+                ui.monospace(&self.line);
+            }
+        });
+    }
+    fn has_panel(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,39 @@
+//! Protocol for self-checking test ROMs.
+//!
+//! The host watches a single result address given with `--result-address`. The first byte the
+//! program writes there ends a headless run: chippy reports the value, optionally dumps the
+//! screen with `--dump-screen`, and exits with the value as exit code. By convention 0 means
+//! every check passed and anything else identifies the failed check.
+
+use std::{cell::Cell, rc::Rc};
+use super::Peripheral;
+
+
+pub struct TestHarness {
+    address: u16,
+    result: Rc<Cell<Option<u8>>>,
+}
+impl TestHarness {
+    /// Creates the harness along with a handle to read the reported result from
+    pub fn new(address: u16) -> (Self, Rc<Cell<Option<u8>>>) {
+        let result = Rc::new(Cell::new(None));
+        let harness = Self {
+            address,
+            result: result.clone(),
+        };
+        (harness, result)
+    }
+}
+impl Peripheral for TestHarness {
+    fn name(&self) -> &str {
+        "Test harness"
+    }
+    fn handles(&self, address: u16) -> bool {
+        address == self.address
+    }
+    fn write(&mut self, _address: u16, value: u8) {
+        if self.result.get().is_none() {
+            self.result.set(Some(value));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{fs, io, path::{Path, PathBuf}};
+use serde::{Serialize, Deserialize};
+use tracing::warn;
+use crate::{config::{Config, Quirks}, rom};
+
+/// Directory holding preference files named after the hash of the ROM they belong to
+pub const PREFERENCES_DIR: &str = "./preferences";
+
+
+/// Choices made at runtime for a single ROM, remembered across runs by the ROM's hash.
+///
+/// Unlike [`RomOverrides`](crate::overrides::RomOverrides), which are written by hand,
+/// these are saved by chippy itself and take precedence over both the config and the overrides.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomPreferences {
+    pub palette: Option<String>,
+    pub instructions_per_frame: Option<usize>,
+    pub quirks: Quirks,
+}
+impl RomPreferences {
+    fn path(program: &[u8]) -> PathBuf {
+        Path::new(PREFERENCES_DIR).join(format!("{}.toml", rom::hash_string(program)))
+    }
+
+    /// Loads the preferences for `program`, empty if none were saved or they are unreadable
+    pub fn load(program: &[u8]) -> Self {
+        let path = Self::path(program);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                warn!("Could not read preferences {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+
+        match toml::from_str(&text) {
+            Ok(preferences) => preferences,
+            Err(e) => {
+                warn!("Could not parse preferences {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+    pub fn save(&self, program: &[u8]) -> io::Result<()> {
+        let text = toml::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(PREFERENCES_DIR)?;
+        fs::write(Self::path(program), text)
+    }
+
+    /// The settings to run the ROM with, `config` layered with these preferences
+    pub fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        config.quirks = config.quirks.merged(&self.quirks);
+        if let Some(instructions) = self.instructions_per_frame {
+            config.instructions_per_frame = instructions;
+        }
+        if let Some(palette) = &self.palette {
+            config.palette = palette.clone();
+        }
+        config
+    }
+}
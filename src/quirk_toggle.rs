@@ -0,0 +1,180 @@
+//! Quirks flipped while a ROM runs, to find the settings a misbehaving ROM needs by trying them.
+//!
+//! Flipped quirks are layered on top of the ROM's settings until another ROM is loaded,
+//! they are not saved.
+
+use crate::{config::Quirks, emulator::comp_mode::*};
+
+
+/// A quirk that can be flipped at runtime
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Quirk {
+    Shift,
+    LoadStore,
+    AddressSpace,
+    Instructions,
+    JumpMode,
+    Collisions,
+    LoresScroll,
+    LargeSprites,
+    AddIOverflow,
+    Random,
+    DrawOrigin,
+    HorizontalEdge,
+    VerticalEdge,
+    DebugPrint,
+}
+impl Quirk {
+    pub const ALL: [Quirk; 14] = [
+        Quirk::Shift, Quirk::LoadStore, Quirk::AddressSpace, Quirk::Instructions, Quirk::JumpMode, Quirk::Collisions,
+        Quirk::LoresScroll, Quirk::LargeSprites, Quirk::AddIOverflow, Quirk::Random, Quirk::DrawOrigin,
+        Quirk::HorizontalEdge, Quirk::VerticalEdge, Quirk::DebugPrint,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Quirk::Shift => "Shift",
+            Quirk::LoadStore => "Load/store",
+            Quirk::AddressSpace => "Address space",
+            Quirk::Instructions => "Instructions",
+            Quirk::JumpMode => "BNNN jump",
+            Quirk::Collisions => "Collisions",
+            Quirk::LoresScroll => "Lores scroll",
+            Quirk::LargeSprites => "DXY0 in lores",
+            Quirk::AddIOverflow => "FX1E overflow",
+            Quirk::Random => "CXKK random",
+            Quirk::DrawOrigin => "Sprite origin",
+            Quirk::HorizontalEdge => "Horizontal edge",
+            Quirk::VerticalEdge => "Vertical edge",
+            Quirk::DebugPrint => "Debug prints",
+        }
+    }
+    /// The quirk after this one, wrapping around to the first
+    pub fn next(self) -> Quirk {
+        let i = Self::ALL.iter().position(|&q| q == self).unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// How `comp` sets this quirk
+    pub fn value(self, comp: &CompatibilityMode) -> &'static str {
+        match self {
+            Quirk::Shift => match comp.shift {
+                ShiftMode::Original => "VY into VX",
+                ShiftMode::SuperChip => "VX in place",
+            },
+            Quirk::LoadStore => match comp.load_store {
+                LoadStoreMode::Original => "Increment I",
+                LoadStoreMode::SuperChip => "Leave I",
+            },
+            Quirk::AddressSpace => match comp.address_space {
+                AddressSpace::Original => "12 bit",
+                AddressSpace::XOChip => "16 bit",
+            },
+            Quirk::Instructions => match comp.allowed_instructions {
+                AllowedInstructions::Original => "CHIP-8",
+                AllowedInstructions::SuperChip => "SuperChip",
+                AllowedInstructions::XOChip => "XO-Chip",
+            },
+            Quirk::JumpMode => match comp.jump_mode {
+                RelativeJumpMode::Original => "V0",
+                RelativeJumpMode::SuperChip => "VX",
+            },
+            Quirk::Collisions => match comp.collisions {
+                CollisionEnumeration::Original => "Flag",
+                CollisionEnumeration::SuperChip => "Count rows",
+            },
+            Quirk::LoresScroll => match comp.lores_scroll {
+                LoresScrollMode::Whole => "Whole pixels",
+                LoresScrollMode::Half => "Half pixels",
+            },
+            Quirk::LargeSprites => match comp.large_sprites {
+                LargeSpriteMode::SuperChip => "8x16",
+                LargeSpriteMode::XOChip => "16x16",
+            },
+            Quirk::AddIOverflow => match comp.add_i_overflow {
+                AddIOverflow::Ignore => "Leave VF",
+                AddIOverflow::Amiga => "Set VF",
+            },
+            Quirk::Random => match comp.random {
+                RandomMode::Modern => "Modern",
+                RandomMode::Vip => "COSMAC VIP",
+            },
+            Quirk::DrawOrigin => match comp.draw_origin {
+                DrawOrigin::Wrap => "Wrap around",
+                DrawOrigin::Raw => "Use as is",
+            },
+            Quirk::HorizontalEdge => edge_name(comp.horizontal_edge),
+            Quirk::VerticalEdge => edge_name(comp.vertical_edge),
+            Quirk::DebugPrint => if comp.debug_print { "On" } else { "Off" },
+        }
+    }
+
+    /// Sets the quirk in `quirks` to the setting after the one `comp` uses
+    pub fn flip(self, comp: &CompatibilityMode, quirks: &mut Quirks) {
+        match self {
+            Quirk::Shift => quirks.shift = Some(match comp.shift {
+                ShiftMode::Original => ShiftMode::SuperChip,
+                ShiftMode::SuperChip => ShiftMode::Original,
+            }),
+            Quirk::LoadStore => quirks.load_store = Some(match comp.load_store {
+                LoadStoreMode::Original => LoadStoreMode::SuperChip,
+                LoadStoreMode::SuperChip => LoadStoreMode::Original,
+            }),
+            Quirk::AddressSpace => quirks.address_space = Some(match comp.address_space {
+                AddressSpace::Original => AddressSpace::XOChip,
+                AddressSpace::XOChip => AddressSpace::Original,
+            }),
+            Quirk::Instructions => quirks.allowed_instructions = Some(match comp.allowed_instructions {
+                AllowedInstructions::Original => AllowedInstructions::SuperChip,
+                AllowedInstructions::SuperChip => AllowedInstructions::XOChip,
+                AllowedInstructions::XOChip => AllowedInstructions::Original,
+            }),
+            Quirk::JumpMode => quirks.jump_mode = Some(match comp.jump_mode {
+                RelativeJumpMode::Original => RelativeJumpMode::SuperChip,
+                RelativeJumpMode::SuperChip => RelativeJumpMode::Original,
+            }),
+            Quirk::Collisions => quirks.collisions = Some(match comp.collisions {
+                CollisionEnumeration::Original => CollisionEnumeration::SuperChip,
+                CollisionEnumeration::SuperChip => CollisionEnumeration::Original,
+            }),
+            Quirk::LoresScroll => quirks.lores_scroll = Some(match comp.lores_scroll {
+                LoresScrollMode::Whole => LoresScrollMode::Half,
+                LoresScrollMode::Half => LoresScrollMode::Whole,
+            }),
+            Quirk::LargeSprites => quirks.large_sprites = Some(match comp.large_sprites {
+                LargeSpriteMode::SuperChip => LargeSpriteMode::XOChip,
+                LargeSpriteMode::XOChip => LargeSpriteMode::SuperChip,
+            }),
+            Quirk::AddIOverflow => quirks.add_i_overflow = Some(match comp.add_i_overflow {
+                AddIOverflow::Ignore => AddIOverflow::Amiga,
+                AddIOverflow::Amiga => AddIOverflow::Ignore,
+            }),
+            Quirk::Random => quirks.random = Some(match comp.random {
+                RandomMode::Modern => RandomMode::Vip,
+                RandomMode::Vip => RandomMode::Modern,
+            }),
+            Quirk::DrawOrigin => quirks.draw_origin = Some(match comp.draw_origin {
+                DrawOrigin::Wrap => DrawOrigin::Raw,
+                DrawOrigin::Raw => DrawOrigin::Wrap,
+            }),
+            Quirk::HorizontalEdge => quirks.horizontal_edge = Some(next_edge(comp.horizontal_edge)),
+            Quirk::VerticalEdge => quirks.vertical_edge = Some(next_edge(comp.vertical_edge)),
+            Quirk::DebugPrint => quirks.debug_print = Some(!comp.debug_print),
+        }
+    }
+}
+
+fn edge_name(edge: SpriteEdge) -> &'static str {
+    match edge {
+        SpriteEdge::Auto => "Clip in hires",
+        SpriteEdge::Wrap => "Wrap around",
+        SpriteEdge::Clip => "Clip",
+    }
+}
+fn next_edge(edge: SpriteEdge) -> SpriteEdge {
+    match edge {
+        SpriteEdge::Auto => SpriteEdge::Wrap,
+        SpriteEdge::Wrap => SpriteEdge::Clip,
+        SpriteEdge::Clip => SpriteEdge::Auto,
+    }
+}
@@ -0,0 +1,245 @@
+//! Remote play over TCP.
+//!
+//! A host runs the machine as usual and accepts viewers, which show the host's screen and send
+//! their key presses back, so several people can play on one machine.
+//!
+//! Messages are bincode encoded and prefixed with their length as a little-endian `u32`.
+//! The host sends the whole screen whenever it changed, viewers send every key press and release.
+
+use std::{io::{self, Read, Write}, net::{TcpListener, TcpStream, ToSocketAddrs}, process::ExitCode, time::{Duration, Instant}};
+use pixels::{PixelsBuilder, SurfaceTexture};
+use serde::{Serialize, Deserialize};
+use tracing::{error, info, warn};
+use winit::{dpi::LogicalSize, event::{ElementState, Event, WindowEvent}, event_loop::{ControlFlow, EventLoop}, platform::run_return::EventLoopExtRunReturn, window::WindowBuilder};
+use crate::{config::{Config, CONFIG_PATH}, emulator::{keys::Keys, screen::{Screen, WIDTH, HEIGHT}}, renderer::ScalingRenderer};
+
+/// Messages longer than this are treated as a broken connection
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+/// How often a viewer checks for new screens
+const VIEWER_POLL_INTERVAL: Duration = Duration::from_millis(1000 / 60);
+
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Message {
+    /// The host's screen changed
+    Screen(Box<Screen>),
+    /// A viewer pressed or released a keypad key
+    Key { key: u8, pressed: bool },
+}
+
+
+/// One end of a connection, buffering partial messages in both directions
+struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        let bytes = bincode::serialize(message).map_err(io::Error::other)?;
+        self.outgoing.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        self.outgoing.extend_from_slice(&bytes);
+        self.flush()
+    }
+    /// Writes as much of the queued data as the socket takes without blocking
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => drop(self.outgoing.drain(..written)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// The messages that arrived completely since the last call
+    fn receive(&mut self) -> io::Result<Vec<Message>> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut messages = Vec::new();
+        while self.incoming.len() >= 4 {
+            let length = u32::from_le_bytes(self.incoming[..4].try_into().unwrap()) as usize;
+            if length > MAX_MESSAGE_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "message too long"));
+            }
+            if self.incoming.len() < 4 + length {
+                break;
+            }
+            let message = bincode::deserialize(&self.incoming[4..4 + length]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            messages.push(message);
+            self.incoming.drain(..4 + length);
+        }
+        Ok(messages)
+    }
+}
+
+
+/// Accepts viewers, sends them the screen and applies their key presses
+pub struct Host {
+    listener: TcpListener,
+    viewers: Vec<Connection>,
+    last_screen: Option<Screen>,
+}
+impl Host {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Hosting remote play on {}", listener.local_addr()?);
+        Ok(Self {
+            listener,
+            viewers: Vec::new(),
+            last_screen: None,
+        })
+    }
+
+    /// Accepts new viewers, applies their key presses to `keys` and sends them `screen` if it changed
+    pub fn update(&mut self, screen: &Screen, keys: &mut Keys) {
+        let mut joined = false;
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => match Connection::new(stream) {
+                    Ok(connection) => {
+                        info!("Viewer {} connected", address);
+                        self.viewers.push(connection);
+                        joined = true;
+                    }
+                    Err(e) => warn!("Could not set up connection to {}: {}", address, e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("Could not accept viewer: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let changed = joined || self.last_screen.as_ref() != Some(screen);
+        self.last_screen = Some(*screen);
+        self.viewers.retain_mut(|viewer| {
+            let result = viewer.receive().and_then(|messages| {
+                for message in messages {
+                    if let Message::Key { key, pressed } = message {
+                        if key < 16 {
+                            keys.set_key(key, pressed);
+                        }
+                    }
+                }
+                if changed {
+                    viewer.send(&Message::Screen(Box::new(*screen)))
+                }
+                else {
+                    viewer.flush()
+                }
+            });
+            if let Err(e) = &result {
+                info!("Viewer disconnected: {}", e);
+            }
+            result.is_ok()
+        });
+    }
+}
+
+
+/// Connects to a host and shows its screen in a window, sending key presses back
+pub fn view(address: &str) -> ExitCode {
+    let config = Config::load(CONFIG_PATH);
+    let (_, palette) = config.palette();
+    let mut host = match TcpStream::connect(address).and_then(Connection::new) {
+        Ok(host) => host,
+        Err(e) => {
+            error!("Could not connect to {}: {}", address, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut ev_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(format!("chippy - viewing {}", address))
+        .with_inner_size(LogicalSize::new(WIDTH as f64 * crate::INITIAL_SCALE, HEIGHT as f64 * crate::INITIAL_SCALE))
+        .with_min_inner_size(LogicalSize::new(WIDTH as f64, HEIGHT as f64))
+        .build(&ev_loop)
+        .unwrap();
+    let size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+    let mut pixels = PixelsBuilder::new(WIDTH as u32, HEIGHT as u32, surface_texture).build().unwrap();
+    let mut renderer = ScalingRenderer::new(&pixels, size.width, size.height, config.scale_filter, config.scale_mode);
+
+    let mut screen = Screen::new();
+    let mut exit_code = ExitCode::SUCCESS;
+    ev_loop.run_return(|event, _, cf| {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *cf = ControlFlow::Exit,
+                WindowEvent::Resized(size) | WindowEvent::ScaleFactorChanged { new_inner_size: &mut size, .. } => {
+                    pixels.resize_surface(size.width, size.height).unwrap();
+                    renderer.resize(&pixels, size.width, size.height);
+                }
+                WindowEvent::KeyboardInput { input, .. } => {
+                    let key = input.virtual_keycode.and_then(|code| config.keymap.key(code));
+                    if let Some(key) = key {
+                        let pressed = input.state == ElementState::Pressed;
+                        if let Err(e) = host.send(&Message::Key { key, pressed }) {
+                            error!("Lost connection to {}: {}", address, e);
+                            exit_code = ExitCode::FAILURE;
+                            *cf = ControlFlow::Exit;
+                        }
+                    }
+                }
+                _ => (),
+            },
+            Event::MainEventsCleared => {
+                match host.receive().and_then(|messages| host.flush().map(|()| messages)) {
+                    Ok(messages) => {
+                        let mut changed = false;
+                        for message in messages {
+                            if let Message::Screen(new_screen) = message {
+                                screen = *new_screen;
+                                changed = true;
+                            }
+                        }
+                        if changed {
+                            window.request_redraw();
+                        }
+                    }
+                    Err(e) => {
+                        error!("Lost connection to {}: {}", address, e);
+                        exit_code = ExitCode::FAILURE;
+                        *cf = ControlFlow::Exit;
+                        return;
+                    }
+                }
+                *cf = ControlFlow::WaitUntil(Instant::now() + VIEWER_POLL_INTERVAL);
+            }
+            Event::RedrawRequested(_) => {
+                screen.render_to_pixel_buffer(pixels.get_frame_mut(), &palette);
+                pixels.render_with(|encoder, render_target, _| {
+                    renderer.render(encoder, render_target);
+                    Ok(())
+                }).unwrap();
+            }
+            _ => (),
+        }
+    });
+
+    exit_code
+}
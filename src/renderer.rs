@@ -0,0 +1,289 @@
+use pixels::{Pixels, wgpu::{self, util::DeviceExt}};
+use serde::{Serialize, Deserialize};
+
+
+/// Scales the pixel buffer to the window, like the default renderer of `pixels`,
+/// but with a configurable texture filter and scaling mode.
+pub struct ScalingRenderer {
+    vertex_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    texture_size: (f32, f32),
+    mode: ScaleMode,
+    clip_rect: Rect<u32>,
+    /// The whole scaled image on the surface, which may extend past its edges
+    image_rect: Rect<f32>,
+}
+impl ScalingRenderer {
+    pub fn new(pixels: &Pixels, surface_width: u32, surface_height: u32, filter: ScaleFilter, mode: ScaleMode) -> Self {
+        let device = pixels.device();
+        let context = pixels.context();
+        let texture_view = context.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_size = (context.texture_extent.width as f32, context.texture_extent.height as f32);
+
+        let shader = wgpu::include_wgsl!("../shaders/scale.wgsl");
+        let module = device.create_shader_module(shader);
+
+        let filter_mode = match filter {
+            ScaleFilter::Nearest => wgpu::FilterMode::Nearest,
+            ScaleFilter::Linear => wgpu::FilterMode::Linear,
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("chippy_scaling_renderer_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter_mode,
+            min_filter: filter_mode,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 1.0,
+            ..Default::default()
+        });
+
+        // One triangle covering the whole surface
+        let vertex_data: [f32; 6] = [-1.0, -1.0, 3.0, -1.0, -1.0, 3.0];
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("chippy_scaling_renderer_vertex_buffer"),
+            contents: &f32s_to_bytes(&vertex_data),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: 2 * 4,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            }],
+        };
+
+        let surface_size = (surface_width as f32, surface_height as f32);
+        let (transform, clip_rect, image_rect) = scaling_transform(texture_size, surface_size, mode);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("chippy_scaling_renderer_matrix_uniform_buffer"),
+            contents: &f32s_to_bytes(&transform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("chippy_scaling_renderer_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("chippy_scaling_renderer_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("chippy_scaling_renderer_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("chippy_scaling_renderer_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[vertex_buffer_layout],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: pixels.render_texture_format(),
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            vertex_buffer,
+            uniform_buffer,
+            bind_group,
+            render_pipeline,
+            texture_size,
+            mode,
+            clip_rect,
+            image_rect,
+        }
+    }
+
+    pub fn resize(&mut self, pixels: &Pixels, width: u32, height: u32) {
+        let surface_size = (width as f32, height as f32);
+        let (transform, clip_rect, image_rect) = scaling_transform(self.texture_size, surface_size, self.mode);
+        pixels.queue().write_buffer(&self.uniform_buffer, 0, &f32s_to_bytes(&transform));
+        self.clip_rect = clip_rect;
+        self.image_rect = image_rect;
+    }
+
+    /// Maps a position on the surface, in physical pixels, to the texel shown there
+    pub fn texel_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let (image_x, image_y, width, height) = self.image_rect;
+        let u = (x - image_x) / width;
+        let v = (y - image_y) / height;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        let (texture_width, texture_height) = self.texture_size;
+        Some(((u * texture_width) as usize, (v * texture_height) as usize))
+    }
+
+    /// Where texel `0, 0` starts on the surface and how many physical pixels a texel covers,
+    /// as `(x, y, width, height)`
+    pub fn texel_transform(&self) -> (f32, f32, f32, f32) {
+        let (image_x, image_y, width, height) = self.image_rect;
+        let (texture_width, texture_height) = self.texture_size;
+        (image_x, image_y, width / texture_width, height / texture_height)
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("chippy_scaling_renderer_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        let (x, y, width, height) = self.clip_rect;
+        rpass.set_scissor_rect(x, y, width, height);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleFilter {
+    /// Keep pixel edges sharp
+    Nearest,
+    /// Blend neighbouring pixels
+    Linear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleMode {
+    /// Scale by the largest whole number that fits, snapped to the pixel grid
+    PixelPerfect,
+    /// Scale as large as possible while keeping the aspect ratio
+    Fit,
+    /// Fill the whole window, ignoring the aspect ratio
+    Stretch,
+}
+
+
+/// A rectangle as x, y, width and height
+type Rect<T> = (T, T, T, T);
+
+fn scaling_transform(texture_size: (f32, f32), surface_size: (f32, f32), mode: ScaleMode) -> ([f32; 16], Rect<u32>, Rect<f32>) {
+    let (texture_width, texture_height) = texture_size;
+    let (surface_width, surface_height) = surface_size;
+    let scale_x = surface_width / texture_width;
+    let scale_y = surface_height / texture_height;
+
+    let (scale_x, scale_y) = match mode {
+        ScaleMode::PixelPerfect => {
+            let scale = scale_x.min(scale_y).floor().max(1.0);
+            (scale, scale)
+        }
+        ScaleMode::Fit => {
+            let scale = scale_x.min(scale_y);
+            (scale, scale)
+        }
+        ScaleMode::Stretch => (scale_x, scale_y),
+    };
+
+    let scaled_width = texture_width * scale_x;
+    let scaled_height = texture_height * scale_y;
+
+    let sw = scaled_width / surface_width;
+    let sh = scaled_height / surface_height;
+    let (tx, ty) = if mode == ScaleMode::PixelPerfect {
+        // Shift by half a pixel on odd surface sizes so texels land on whole pixels
+        ((surface_width / 2.0).fract() / surface_width, (surface_height / 2.0).fract() / surface_height)
+    }
+    else {
+        (0.0, 0.0)
+    };
+    #[rustfmt::skip]
+    let transform = [
+        sw,  0.0, 0.0, 0.0,
+        0.0, sh,  0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        tx,  ty,  0.0, 1.0,
+    ];
+
+    let image_x = (surface_width - scaled_width) / 2.0;
+    let image_y = (surface_height - scaled_height) / 2.0;
+    let image_rect = (image_x, image_y, scaled_width, scaled_height);
+
+    let scaled_width = scaled_width.min(surface_width);
+    let scaled_height = scaled_height.min(surface_height);
+    let x = ((surface_width - scaled_width) / 2.0) as u32;
+    let y = ((surface_height - scaled_height) / 2.0) as u32;
+    let clip_rect = (x, y, scaled_width as u32, scaled_height as u32);
+
+    (transform, clip_rect, image_rect)
+}
+
+fn f32s_to_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_ne_bytes()).collect()
+}
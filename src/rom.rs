@@ -0,0 +1,17 @@
+pub mod archive;
+pub mod check;
+pub mod patch;
+
+
+/// A stable 64-bit FNV-1a hash of a program, used to identify ROMs across runs
+pub fn hash(program: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    program.iter().fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+/// The hash of a program formatted as used in file names
+pub fn hash_string(program: &[u8]) -> String {
+    format!("{:016x}", hash(program))
+}
@@ -0,0 +1,180 @@
+//! ROMs inside zip and gzip archives, the way ROM collections are usually distributed.
+//!
+//! Only what ROM archives need is supported: zip entries that are stored or deflated,
+//! and gzip files holding a single ROM.
+
+use std::fmt::{self, Display, Formatter};
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+/// File extensions of ROMs, preferred over other entries of a zip archive
+const ROM_EXTENSIONS: &[&str] = &["ch8", "sc8", "xo8", "c8"];
+/// Extracted files are larger than any ROM can be beyond this, so it is treated as an error
+const MAX_SIZE: usize = 1 << 20;
+
+const ZIP_LOCAL_HEADER: u32 = 0x04034b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x06054b50;
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+
+/// Whether `bytes` are a zip or gzip archive rather than a ROM
+pub fn is_archive(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ZIP_LOCAL_HEADER.to_le_bytes()) || bytes.starts_with(&GZIP_MAGIC)
+}
+
+/// Names of the ROMs in the archive, in the order they are stored.
+/// Zip entries with a ROM extension are preferred, if there are none all files are listed.
+pub fn rom_names(bytes: &[u8]) -> Result<Vec<String>, ArchiveError> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let (name, _) = gzip_header(bytes)?;
+        return Ok(vec![name.unwrap_or_default()]);
+    }
+
+    let files: Vec<_> = zip_entries(bytes)?.into_iter()
+        .map(|entry| entry.name)
+        .filter(|name| !name.ends_with('/'))
+        .collect();
+    let roms: Vec<_> = files.iter().filter(|name| is_rom_name(name)).cloned().collect();
+    match (roms.is_empty(), files.is_empty()) {
+        (_, true) => Err(ArchiveError::NoRoms),
+        (true, false) => Ok(files),
+        (false, false) => Ok(roms),
+    }
+}
+
+/// Extracts the ROM called `name`, a gzip archive only holds one and ignores the name
+pub fn extract(bytes: &[u8], name: &str) -> Result<Vec<u8>, ArchiveError> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let (_, start) = gzip_header(bytes)?;
+        return inflate(&bytes[start..]);
+    }
+
+    let entry = zip_entries(bytes)?.into_iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| ArchiveError::NotFound(name.to_string()))?;
+    let header = bytes.get(entry.offset..entry.offset + 30).ok_or(ArchiveError::Corrupt)?;
+    if read_u32(header, 0) != ZIP_LOCAL_HEADER {
+        return Err(ArchiveError::Corrupt);
+    }
+    let start = entry.offset + 30 + read_u16(header, 26) as usize + read_u16(header, 28) as usize;
+    let data = bytes.get(start..start + entry.compressed_size).ok_or(ArchiveError::Corrupt)?;
+    match entry.method {
+        0 => Ok(data.to_vec()),
+        8 => inflate(data),
+        method => Err(ArchiveError::UnsupportedMethod(method)),
+    }
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let extension = name.rsplit_once('.').map_or("", |(_, extension)| extension);
+    ROM_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(extension))
+}
+
+
+/// Why a ROM could not be taken from an archive
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ArchiveError {
+    /// The archive is truncated or malformed
+    Corrupt,
+    /// A zip entry is compressed with something other than deflate
+    UnsupportedMethod(u16),
+    /// The compressed data is invalid or unreasonably large
+    Decompress,
+    /// The archive contains no files
+    NoRoms,
+    NotFound(String),
+}
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::Corrupt => write!(f, "archive is corrupt"),
+            ArchiveError::UnsupportedMethod(method) => write!(f, "unsupported compression method {}", method),
+            ArchiveError::Decompress => write!(f, "could not decompress"),
+            ArchiveError::NoRoms => write!(f, "archive contains no files"),
+            ArchiveError::NotFound(name) => write!(f, "archive contains no file {}", name),
+        }
+    }
+}
+
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: usize,
+    /// Offset of the local header
+    offset: usize,
+}
+
+/// Lists the entries of the central directory
+fn zip_entries(bytes: &[u8]) -> Result<Vec<ZipEntry>, ArchiveError> {
+    // The end of directory record is followed by a comment of up to 64 KiB
+    let search_start = bytes.len().saturating_sub(22 + u16::MAX as usize);
+    let end = (search_start..bytes.len().saturating_sub(21)).rev()
+        .find(|&i| read_u32(bytes, i) == ZIP_END_OF_DIRECTORY)
+        .ok_or(ArchiveError::Corrupt)?;
+    let count = read_u16(bytes, end + 10) as usize;
+    let mut position = read_u32(bytes, end + 16) as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = bytes.get(position..position + 46).ok_or(ArchiveError::Corrupt)?;
+        if read_u32(header, 0) != ZIP_CENTRAL_HEADER {
+            return Err(ArchiveError::Corrupt);
+        }
+        let name_length = read_u16(header, 28) as usize;
+        let extra_length = read_u16(header, 30) as usize;
+        let comment_length = read_u16(header, 32) as usize;
+        let name = bytes.get(position + 46..position + 46 + name_length).ok_or(ArchiveError::Corrupt)?;
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: read_u16(header, 10),
+            compressed_size: read_u32(header, 20) as usize,
+            offset: read_u32(header, 42) as usize,
+        });
+        position += 46 + name_length + extra_length + comment_length;
+    }
+    Ok(entries)
+}
+
+/// The original file name stored in a gzip header, if any, and where the compressed data starts
+fn gzip_header(bytes: &[u8]) -> Result<(Option<String>, usize), ArchiveError> {
+    const EXTRA: u8 = 0x04;
+    const NAME: u8 = 0x08;
+    const COMMENT: u8 = 0x10;
+    const HEADER_CRC: u8 = 0x02;
+
+    let flags = *bytes.get(3).ok_or(ArchiveError::Corrupt)?;
+    let mut position = 10;
+    if flags & EXTRA != 0 {
+        let length = bytes.get(position..position + 2).ok_or(ArchiveError::Corrupt)?;
+        position += 2 + read_u16(length, 0) as usize;
+    }
+    let string = |position: &mut usize| {
+        let rest = bytes.get(*position..).ok_or(ArchiveError::Corrupt)?;
+        let length = rest.iter().position(|&b| b == 0).ok_or(ArchiveError::Corrupt)?;
+        *position += length + 1;
+        Ok(String::from_utf8_lossy(&rest[..length]).into_owned())
+    };
+    let name = if flags & NAME != 0 { Some(string(&mut position)?) } else { None };
+    if flags & COMMENT != 0 {
+        string(&mut position)?;
+    }
+    if flags & HEADER_CRC != 0 {
+        position += 2;
+    }
+    if position > bytes.len() {
+        return Err(ArchiveError::Corrupt);
+    }
+    Ok((name, position))
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>, ArchiveError> {
+    decompress_to_vec_with_limit(data, MAX_SIZE).map_err(|_| ArchiveError::Decompress)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    bytes.get(offset..offset + 2).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]))
+}
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    bytes.get(offset..offset + 4).map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
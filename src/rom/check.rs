@@ -0,0 +1,71 @@
+//! Sanity checks on a ROM before it runs.
+//!
+//! These only catch obvious mistakes, like loading a ROM for another variant or a file that
+//! isn't a program at all, so the user gets a hint before the emulator fails mid-game.
+
+use std::fmt::{self, Display, Formatter};
+use crate::emulator::{comp_mode::{AddressSpace, CompatibilityMode}, instruction::{Address, Instruction}};
+
+
+/// Checks `program` loaded at `start` for problems when run with `comp`
+pub fn check(program: &[u8], start: usize, comp: &CompatibilityMode) -> Vec<RomWarning> {
+    let mut warnings = Vec::new();
+
+    let memory = match comp.address_space {
+        AddressSpace::Original => 0x1000,
+        AddressSpace::XOChip => 0x10000,
+    };
+    let available = memory - start.min(memory);
+    if program.len() > available {
+        warnings.push(RomWarning::TooLarge { size: program.len(), available });
+    }
+    if !program.len().is_multiple_of(2) {
+        warnings.push(RomWarning::OddLength(program.len()));
+    }
+
+    if program.len() < 2 {
+        warnings.push(RomWarning::Empty);
+        return warnings;
+    }
+    let opcode = u16::from_be_bytes([program[0], program[1]]);
+    match Instruction::decode(program) {
+        Some(instruction) if !comp.allowed_instructions.is_legal(&instruction) => warnings.push(RomWarning::InvalidFirstInstruction(opcode)),
+        None => warnings.push(RomWarning::InvalidFirstInstruction(opcode)),
+        Some(Instruction::Jump(Address(target)) | Instruction::Call(Address(target))) => {
+            let target = target as usize;
+            if target < start || target >= start + program.len() {
+                warnings.push(RomWarning::JumpOutside(target as u16));
+            }
+        }
+        Some(_) => (),
+    }
+
+    warnings
+}
+
+
+/// An obvious problem with a ROM that will likely make it fail
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RomWarning {
+    /// The ROM doesn't fit in the memory of the selected variant
+    TooLarge { size: usize, available: usize },
+    /// Instructions are two bytes long, so programs usually are of even length
+    OddLength(usize),
+    /// The ROM doesn't hold a single instruction
+    Empty,
+    /// The first instruction can't be decoded or isn't supported by the selected variant
+    InvalidFirstInstruction(u16),
+    /// The program begins by jumping to an address outside of itself
+    JumpOutside(u16),
+}
+impl Display for RomWarning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RomWarning::TooLarge { size, available } => write!(f, "ROM is {} bytes, but only {} fit in the memory of the selected variant", size, available),
+            RomWarning::OddLength(size) => write!(f, "ROM has an odd length of {} bytes, it may be truncated or not a CHIP-8 program", size),
+            RomWarning::Empty => write!(f, "ROM does not contain a single instruction"),
+            RomWarning::InvalidFirstInstruction(opcode) => write!(f, "first instruction {:04X} is invalid for the selected variant", opcode),
+            RomWarning::JumpOutside(target) => write!(f, "program starts with a jump to {:03X}, outside of the ROM", target),
+        }
+    }
+}
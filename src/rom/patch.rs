@@ -0,0 +1,112 @@
+//! Patch files applied to a ROM at load time.
+//!
+//! Two formats are supported:
+//! - IPS, detected by its `PATCH` header
+//! - Text with one `offset: bytes` entry per line, like `0x1A4: 12 34 FF`.
+//!   Offsets are decimal or `0x`-prefixed hexadecimal, bytes are hexadecimal,
+//!   and everything after a `#` is a comment.
+//!
+//! Offsets are relative to the start of the ROM file, the ROM grows if a patch writes past its end.
+
+use std::fmt::{self, Display, Formatter};
+use crate::{PROGRAM_START, debugger::expression::parse_number};
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_FOOTER: &[u8] = b"EOF";
+/// Patches can't grow a ROM beyond the end of the address space
+const MAX_ROM_SIZE: usize = 0x10000 - PROGRAM_START;
+
+
+/// Applies `patch` to `program`, leaving it unchanged if the patch is malformed
+pub fn apply(program: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    let mut patched = program.clone();
+    if let Some(records) = patch.strip_prefix(IPS_HEADER) {
+        apply_ips(&mut patched, records)?;
+    }
+    else {
+        let text = std::str::from_utf8(patch).map_err(|_| PatchError("not an IPS patch or text file".into()))?;
+        apply_text(&mut patched, text)?;
+    }
+
+    *program = patched;
+    Ok(())
+}
+
+fn apply_ips(program: &mut Vec<u8>, mut records: &[u8]) -> Result<(), PatchError> {
+    loop {
+        if let Some(rest) = records.strip_prefix(IPS_FOOTER) {
+            // Some patches append the size to truncate the ROM to
+            if let [a, b, c] = *rest {
+                program.truncate(u32::from_be_bytes([0, a, b, c]) as usize);
+            }
+            return Ok(());
+        }
+
+        let offset = take(&mut records, 3)?;
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let size = u16::from_be_bytes(take(&mut records, 2)?.try_into().unwrap()) as usize;
+        if size == 0 {
+            // Run-length encoded record
+            let count = u16::from_be_bytes(take(&mut records, 2)?.try_into().unwrap()) as usize;
+            let value = take(&mut records, 1)?[0];
+            write(program, offset, &vec![value; count])?;
+        }
+        else {
+            let bytes = take(&mut records, size)?;
+            write(program, offset, bytes)?;
+        }
+    }
+}
+fn take<'a>(data: &mut &'a [u8], count: usize) -> Result<&'a [u8], PatchError> {
+    if data.len() < count {
+        return Err(PatchError("IPS patch ends in the middle of a record".into()));
+    }
+    let (taken, rest) = data.split_at(count);
+    *data = rest;
+    Ok(taken)
+}
+
+fn apply_text(program: &mut Vec<u8>, text: &str) -> Result<(), PatchError> {
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |message: &str| PatchError(format!("line {}: {}", number + 1, message));
+        let Some((offset, bytes)) = line.split_once(':') else {
+            return Err(error("expected 'offset: bytes'"));
+        };
+        let offset = parse_number(offset.trim()).ok()
+            .and_then(|o| usize::try_from(o).ok())
+            .ok_or_else(|| error("invalid offset"))?;
+        let bytes = bytes.split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error("invalid byte"))?;
+
+        write(program, offset, &bytes).map_err(|e| error(&e.0))?;
+    }
+    Ok(())
+}
+
+fn write(program: &mut Vec<u8>, offset: usize, bytes: &[u8]) -> Result<(), PatchError> {
+    let end = offset + bytes.len();
+    if end > MAX_ROM_SIZE {
+        return Err(PatchError(format!("write to {:#X} is outside the address space", end - 1)));
+    }
+    if end > program.len() {
+        program.resize(end, 0);
+    }
+    program[offset..end].copy_from_slice(bytes);
+    Ok(())
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchError(pub String);
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,87 @@
+use std::{fmt::{self, Display, Formatter}, fs, io, path::Path};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+use serde::{Serialize, Deserialize};
+use crate::{emulator::machine::Machine, rom};
+
+/// Every save state starts with these bytes, followed by the format version
+const MAGIC: &[u8] = b"CHIPPYST";
+/// Since version 4 the state after the version is deflated
+const VERSION: u32 = 4;
+/// The last version whose states weren't compressed, they can still be loaded
+const UNCOMPRESSED_VERSION: u32 = 3;
+/// Deflate level of save state files, from 1 to 10
+const FILE_LEVEL: u8 = 6;
+/// Deflate level of snapshots, which are taken often while running, so speed matters more
+const SNAPSHOT_LEVEL: u8 = 1;
+/// Decompressed states larger than this are corrupt, machines are much smaller
+const MAX_SIZE: usize = 1 << 22;
+
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+    /// Hash of the program the state was saved from
+    rom_hash: u64,
+    machine: Machine,
+}
+
+
+/// Whether `bytes` look like a save state rather than a program image
+pub fn is_save_state(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn save(path: impl AsRef<Path>, machine: &Machine) -> io::Result<()> {
+    let state = SaveState {
+        rom_hash: rom::hash(machine.program()),
+        machine: machine.clone(),
+    };
+    let data = bincode::serialize(&state).map_err(io::Error::other)?;
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&compress_to_vec(&data, FILE_LEVEL));
+    fs::write(path, bytes)
+}
+
+/// Restores the machine from a save state, which must belong to the program `current` is running
+pub fn load(bytes: &[u8], current: &Machine) -> Result<Machine, SaveStateError> {
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        return Err(SaveStateError("not a save state".into()));
+    };
+    let (version, data) = rest.split_at(rest.len().min(4));
+    let data = match version.try_into().map(u32::from_le_bytes) {
+        Ok(VERSION) => decompress(data)?,
+        Ok(UNCOMPRESSED_VERSION) => data.to_vec(),
+        _ => return Err(SaveStateError("unsupported save state version".into())),
+    };
+
+    let state: SaveState = bincode::deserialize(&data).map_err(|e| SaveStateError(format!("corrupt save state: {}", e)))?;
+    if state.rom_hash != rom::hash(current.program()) {
+        return Err(SaveStateError("save state belongs to a different ROM".into()));
+    }
+    Ok(state.machine)
+}
+
+/// The machine serialized and compressed, for keeping many states in memory.
+/// Like save states, snapshots leave out logs and custom opcodes.
+pub fn snapshot(machine: &Machine) -> Vec<u8> {
+    let data = bincode::serialize(machine).expect("machines can always be serialized");
+    compress_to_vec(&data, SNAPSHOT_LEVEL)
+}
+/// Restores a machine from [`snapshot`]
+pub fn restore_snapshot(snapshot: &[u8]) -> Result<Machine, SaveStateError> {
+    let data = decompress(snapshot)?;
+    bincode::deserialize(&data).map_err(|e| SaveStateError(format!("corrupt snapshot: {}", e)))
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, SaveStateError> {
+    decompress_to_vec_with_limit(data, MAX_SIZE).map_err(|_| SaveStateError("could not decompress save state".into()))
+}
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveStateError(pub String);
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
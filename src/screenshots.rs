@@ -0,0 +1,37 @@
+//! Numbered screenshots taken every few frames, so builds of a ROM can show how far it gets.
+//!
+//! Screenshots are PPM images in the palette's colours, named after the frame they show, like
+//! `frame-000600.ppm`.
+
+use std::{fs::{self, File}, io::{self, BufWriter}, path::{Path, PathBuf}};
+use tracing::error;
+use chippy::emulator::{palette::Palette, screen::{ImageFormat, Screen}};
+
+
+pub struct Screenshots {
+    dir: PathBuf,
+    /// Frames between screenshots
+    every: u64,
+}
+impl Screenshots {
+    /// Creates `dir` if it doesn't exist yet
+    pub fn new(dir: &Path, every: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            every,
+        })
+    }
+
+    /// Saves the screen if `frame` is a multiple of the interval
+    pub fn frame(&self, frame: u64, screen: &Screen, palette: &Palette) {
+        if !frame.is_multiple_of(self.every) {
+            return;
+        }
+        let path = self.dir.join(format!("frame-{:06}.ppm", frame));
+        let result = File::create(&path).and_then(|file| screen.write_image(BufWriter::new(file), ImageFormat::Ppm, palette));
+        if let Err(e) = result {
+            error!("Could not write screenshot {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{path::PathBuf, time::Duration};
+use chippy::audio::{AudioSink, Pattern, null::NullSink, command::CommandSink, midi::MidiSink};
+use serde::{Serialize, Deserialize};
+use tracing::error;
+
+
+/// Where the buzzer is played
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum AudioOutput {
+    /// The system's default sound device, if built with the `sound` feature
+    Speaker {
+        /// Sample frames per buffer of the audio stream, the device's default if unset.
+        /// Smaller buffers start and stop the beep sooner, but crackle if the system cannot keep up.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        buffer_size: Option<u32>,
+        /// Shortest beep in milliseconds, so a sound timer of 1 is heard as a beep rather than a click
+        #[serde(default = "default_min_beep_ms")]
+        min_beep_ms: u32,
+    },
+    /// Note on and off messages to a raw MIDI device
+    Midi {
+        device: PathBuf,
+        /// Channel 0 to 15
+        #[serde(default)]
+        channel: u8,
+        /// MIDI note number, 69 is the A at 440 Hz
+        #[serde(default = "default_midi_note")]
+        note: u8,
+    },
+    /// Commands run when the buzzer starts and stops
+    Command {
+        on: String,
+        off: String,
+    },
+}
+impl Default for AudioOutput {
+    fn default() -> Self {
+        AudioOutput::Speaker { buffer_size: None, min_beep_ms: default_min_beep_ms() }
+    }
+}
+fn default_min_beep_ms() -> u32 {
+    30
+}
+fn default_midi_note() -> u8 {
+    69
+}
+
+
+/// Plays the buzzer tone while the sound timer of the machine is running.
+/// Without the `sound` feature, only the volume settings are tracked and nothing is played.
+pub struct Audio {
+    volume: f32,
+    muted: bool,
+    playing: bool,
+    patterns: Vec<Pattern>,
+    sink: Box<dyn AudioSink>,
+}
+impl Audio {
+    pub fn new(volume: f32, muted: bool, output: &AudioOutput) -> Self {
+        let mut audio = Self {
+            volume: volume.clamp(0.0, 1.0),
+            muted,
+            playing: false,
+            patterns: Vec::new(),
+            sink: open_sink(output),
+        };
+        audio.update_volume();
+        audio
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        if playing != self.playing {
+            self.playing = playing;
+            self.sink.set_buzzer(playing);
+        }
+    }
+    /// Mixes `patterns` while the buzzer is on instead of the plain tone, or plays the tone again with none
+    pub fn set_patterns(&mut self, patterns: Vec<Pattern>) {
+        if patterns != self.patterns {
+            self.sink.set_patterns(&patterns);
+            self.patterns = patterns;
+        }
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        self.update_volume();
+    }
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.update_volume();
+    }
+
+    fn update_volume(&mut self) {
+        let volume = if self.muted { 0.0 } else { self.volume };
+        self.sink.set_volume(volume);
+    }
+}
+
+
+fn open_sink(output: &AudioOutput) -> Box<dyn AudioSink> {
+    match output {
+        AudioOutput::Speaker { buffer_size, min_beep_ms } => open_speaker(*buffer_size, Duration::from_millis(*min_beep_ms as u64)),
+        AudioOutput::Midi { device, channel, note } => match MidiSink::open(device, *channel, *note) {
+            Ok(sink) => Box::new(sink),
+            Err(e) => {
+                error!("Could not open MIDI device {}: {}", device.display(), e);
+                Box::new(NullSink)
+            }
+        },
+        AudioOutput::Command { on, off } => Box::new(CommandSink::new(on, off)),
+    }
+}
+
+#[cfg(feature = "sound")]
+fn open_speaker(buffer_size: Option<u32>, min_beep: Duration) -> Box<dyn AudioSink> {
+    match chippy::audio::cpal::CpalSink::new(buffer_size, min_beep) {
+        Ok(sink) => Box::new(sink),
+        Err(e) => {
+            tracing::warn!("Could not open audio output: {}", e);
+            Box::new(NullSink)
+        }
+    }
+}
+#[cfg(not(feature = "sound"))]
+fn open_speaker(_buffer_size: Option<u32>, _min_beep: Duration) -> Box<dyn AudioSink> {
+    Box::new(NullSink)
+}
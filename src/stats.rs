@@ -0,0 +1,86 @@
+//! Statistics about a run, written when chippy exits.
+//!
+//! JSON files are replaced, CSV files get one row appended per run, so they can collect the
+//! history of a ROM or of chippy's performance over time.
+
+use std::{fs::{self, OpenOptions}, io::{self, Write}, path::Path, time::Instant};
+use serde::Serialize;
+use chippy::emulator::machine::Counters;
+
+
+/// Accumulates the activity of the machine over a whole run
+pub struct RunStats {
+    started: Instant,
+    frames: u64,
+    counters: Counters,
+    key_events: u64,
+}
+impl RunStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            frames: 0,
+            counters: Counters::default(),
+            key_events: 0,
+        }
+    }
+
+    /// Counts a frame that ran with the activity in `counters`
+    pub fn count_frame(&mut self, counters: Counters) {
+        self.frames += 1;
+        self.counters.instructions += counters.instructions;
+        self.counters.draws += counters.draws;
+    }
+    /// Counts a keypad key being pressed or released
+    pub fn count_key_event(&mut self) {
+        self.key_events += 1;
+    }
+
+    pub fn summary(&self, rom: &Path) -> Summary {
+        let seconds = self.started.elapsed().as_secs_f64();
+        Summary {
+            rom: rom.display().to_string(),
+            frames: self.frames,
+            instructions: self.counters.instructions,
+            draws: self.counters.draws,
+            key_events: self.key_events,
+            seconds,
+            instructions_per_second: if seconds > 0.0 { self.counters.instructions as f64 / seconds } else { 0.0 },
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub rom: String,
+    pub frames: u64,
+    pub instructions: u64,
+    pub draws: u64,
+    pub key_events: u64,
+    /// Wall clock time of the run
+    pub seconds: f64,
+    pub instructions_per_second: f64,
+}
+impl Summary {
+    const CSV_HEADER: &'static str = "rom,frames,instructions,draws,key_events,seconds,instructions_per_second";
+
+    /// Appends a row to `path` if it ends in `.csv`, otherwise writes it as JSON
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv")) {
+            let new = !path.exists();
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            if new {
+                writeln!(file, "{}", Self::CSV_HEADER)?;
+            }
+            writeln!(
+                file, "\"{}\",{},{},{},{},{:.6},{:.0}",
+                self.rom.replace('"', "\"\""), self.frames, self.instructions, self.draws, self.key_events, self.seconds, self.instructions_per_second,
+            )
+        }
+        else {
+            let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+            fs::write(path, json)
+        }
+    }
+}
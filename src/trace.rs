@@ -0,0 +1,111 @@
+//! Execution traces with one line per executed instruction, describing the machine state
+//! right before the instruction runs. The format is stable so traces can be diffed against
+//! other emulators.
+//!
+//! CSV traces start with the header
+//! `step,pc,opcode,skipped,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i,sp,dt,st,instruction`.
+//! `step` counts from 0 and is decimal, all other numbers are uppercase hexadecimal without prefix.
+//! `skipped` is 1 if the instruction is skipped by a preceding skip instruction, otherwise 0.
+//! `sp` is the number of return addresses on the stack, and `instruction` the disassembly.
+//!
+//! JSON lines traces contain one object per line with the same fields as decimal numbers,
+//! except `v` holding all sixteen registers in an array and `skipped` being a boolean:
+//! `{"step":0,"pc":512,"opcode":24832,"skipped":false,"v":[0,...],"i":0,"sp":0,"dt":0,"st":0,"instruction":"LD V1, 0x00"}`
+
+use std::{collections::HashMap, fs::File, io::{self, BufRead, BufReader, BufWriter, Write}, path::Path};
+use clap::ValueEnum;
+use crate::emulator::{machine::Machine, instruction::disassemble};
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum TraceFormat {
+    Csv,
+    JsonLines,
+}
+
+
+pub struct Tracer {
+    out: BufWriter<File>,
+    format: TraceFormat,
+    step: u64,
+}
+impl Tracer {
+    pub fn create(path: impl AsRef<Path>, format: TraceFormat) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        if format == TraceFormat::Csv {
+            writeln!(out, "step,pc,opcode,skipped,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i,sp,dt,st,instruction")?;
+        }
+
+        Ok(Self {
+            out,
+            format,
+            step: 0,
+        })
+    }
+
+    /// Records the instruction the machine is about to execute
+    pub fn record(&mut self, machine: &Machine) -> io::Result<()> {
+        let pc = machine.pc() as usize;
+        let memory = machine.memory();
+        let opcode = memory.get(pc..pc + 2).map_or(0, |b| u16::from_be_bytes([b[0], b[1]]));
+        let (instruction, _) = disassemble(&memory[pc..]);
+        let v = machine.registers();
+
+        match self.format {
+            TraceFormat::Csv => {
+                write!(self.out, "{},{:X},{:04X},{}", self.step, pc, opcode, machine.is_skipping() as u8)?;
+                for value in v {
+                    write!(self.out, ",{:X}", value)?;
+                }
+                writeln!(
+                    self.out, ",{:X},{:X},{:X},{:X},{}",
+                    machine.i(), machine.stack().len(), machine.delay_timer(), machine.sound_timer(), instruction,
+                )?;
+            }
+            TraceFormat::JsonLines => {
+                let v: Vec<_> = v.iter().map(u8::to_string).collect();
+                writeln!(
+                    self.out,
+                    r#"{{"step":{},"pc":{},"opcode":{},"skipped":{},"v":[{}],"i":{},"sp":{},"dt":{},"st":{},"instruction":"{}"}}"#,
+                    self.step, pc, opcode, machine.is_skipping(), v.join(","),
+                    machine.i(), machine.stack().len(), machine.delay_timer(), machine.sound_timer(), instruction,
+                )?;
+            }
+        }
+
+        self.step += 1;
+        Ok(())
+    }
+}
+
+
+/// Counts how often the instruction at every address was executed in a trace of either format.
+/// Skipped instructions don't count.
+pub fn read_counts(path: impl AsRef<Path>) -> io::Result<HashMap<u16, u64>> {
+    let invalid = |line: usize| io::Error::new(io::ErrorKind::InvalidData, format!("line {} is not a trace entry", line + 1));
+    let mut counts = HashMap::new();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with("step,") {
+            continue;
+        }
+
+        let (pc, skipped) = if line.starts_with('{') {
+            let entry: serde_json::Value = serde_json::from_str(&line).map_err(|_| invalid(number))?;
+            let pc = entry["pc"].as_u64().and_then(|pc| u16::try_from(pc).ok());
+            (pc, entry["skipped"].as_bool())
+        }
+        else {
+            let mut fields = line.split(',').skip(1);
+            let pc = fields.next().and_then(|pc| u16::from_str_radix(pc, 16).ok());
+            (pc, fields.nth(1).map(|skipped| skipped == "1"))
+        };
+        let (Some(pc), Some(skipped)) = (pc, skipped) else {
+            return Err(invalid(number));
+        };
+        if !skipped {
+            *counts.entry(pc).or_insert(0) += 1;
+        }
+    }
+    Ok(counts)
+}
@@ -0,0 +1,288 @@
+//! Static recompilation of a ROM into Rust source.
+//!
+//! Every address of the ROM that holds a valid instruction becomes one arm of a `match` on the PC,
+//! with the quirks of the ROM's settings resolved at transpile time. The generated `Game` runs
+//! against chippy's `Screen` and `Keys`, so it can be studied or built into a port without the
+//! interpreter. Code the program writes at runtime is not picked up.
+
+use std::{fmt::Write as _, fs, io::{self, Write}, path::{Path, PathBuf}, process::ExitCode};
+use tracing::error;
+use crate::{config::{Config, CONFIG_PATH}, emulator::{comp_mode::*, instruction::{Address, Constant, Instruction, Register}}};
+
+/// Where chippy's machine keeps the small and the large font
+const FONT_ADDRESS: u16 = 0;
+const LARGE_FONT_ADDRESS: u16 = 5 * 16;
+
+
+/// Transpiles the program at `path` and writes the source to `output`, or stdout without one
+pub fn run(path: &Path, patches: &[PathBuf], output: Option<&Path>) -> ExitCode {
+    let config = Config::load(CONFIG_PATH);
+    let program = match crate::read_program(path, patches) {
+        Ok(program) => program,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let settings = crate::load_rom_settings(&config, path, &program);
+    let comp = settings.quirks.build();
+    let machine = match crate::new_machine(&program, &comp, None) {
+        Ok(machine) => machine,
+        Err(e) => {
+            error!("Could not load {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let end = machine.program_start() + program.len();
+    let source = transpile(&name, &machine.memory()[..end], &comp);
+    let result = match output {
+        Some(output) => fs::write(output, source),
+        None => io::stdout().write_all(source.as_bytes()),
+    };
+    if let Err(e) = result {
+        error!("Could not write transpiled source: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+
+/// Rust source of a `Game` running the program in `memory`, which holds the font and the program
+fn transpile(name: &str, memory: &[u8], comp: &CompatibilityMode) -> String {
+    let mut arms = String::new();
+    for address in comp.program_start as usize..memory.len().saturating_sub(1) {
+        let Some(instruction) = Instruction::decode(&memory[address..]) else {
+            continue;
+        };
+        let body = statement(&instruction, address as u16, comp);
+        writeln!(arms, "            // {}", instruction).unwrap();
+        writeln!(arms, "            0x{:03X} => {{ {} }}", address, body).unwrap();
+    }
+
+    let bytes: Vec<_> = memory.iter().map(|b| format!("0x{:02X}", b)).collect();
+    let memory_rows: Vec<_> = bytes.chunks(16).map(|row| format!("    {},", row.join(", "))).collect();
+
+    format!(r#"//! {name}, transpiled by chippy {version} with {comp:?}.
+//! Code the program writes to memory at runtime is not executed.
+#![allow(unused)]
+
+use chippy::emulator::{{comp_mode::{{LargeSpriteMode, LoresSize, SpriteEdge}}, keys::Keys, screen::Screen}};
+
+/// The font followed by the program
+const INITIAL_MEMORY: &[u8] = &[
+{memory}
+];
+
+
+pub struct Game {{
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// The XO-Chip audio pattern of each voice loaded by F002, if any
+    pub audio_patterns: Vec<[u8; 16]>,
+    /// The XO-Chip pitch register, see `chippy::audio::pitch_rate`
+    pub pitch: u8,
+    pub memory: Vec<u8>,
+    pub screen: Screen,
+    pub user_flags: [u8; 16],
+    pub exited: bool,
+    rng: u32,
+}}
+impl Game {{
+    pub fn new(seed: u32) -> Self {{
+        let mut memory = vec![0; 0x10000];
+        memory[..INITIAL_MEMORY.len()].copy_from_slice(INITIAL_MEMORY);
+        Self {{
+            v: [0; 16],
+            i: 0,
+            pc: 0x{start:03X},
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            audio_patterns: Vec::new(),
+            pitch: 64,
+            memory,
+            screen: Screen::new(),
+            user_flags: [0; 16],
+            exited: false,
+            rng: seed | 1,
+        }}
+    }}
+
+    /// Counts the timers down, call this 60 times a second
+    pub fn tick_timers(&mut self) {{
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }}
+
+    fn random(&mut self) -> u8 {{
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as u8
+    }}
+
+    /// Executes one instruction
+    pub fn step(&mut self, keys: &Keys) {{
+        if self.exited {{
+            return;
+        }}
+        let pc = self.pc;
+        self.pc = pc.wrapping_add(2);
+        match pc {{
+{arms}            _ => panic!("No code at address {{:03X}}", pc),
+        }}
+    }}
+}}
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+        memory = memory_rows.join("\n"),
+        start = comp.program_start,
+    )
+}
+
+/// The body of the match arm executing `instruction`, which was decoded at `address`
+fn statement(instruction: &Instruction, address: u16, comp: &CompatibilityMode) -> String {
+    use Instruction::*;
+    if instruction.is_debug_print() && !comp.debug_print {
+        return format!("panic!(\"Debug print at address {:03X} used without enabling debug prints\")", address);
+    }
+    if !comp.allowed_instructions.is_legal(instruction) {
+        return format!("panic!(\"Instruction {} at address {:03X} is not legal in {:?}\")", instruction, address, comp.allowed_instructions);
+    }
+
+    let skip = "self.pc = self.pc.wrapping_add(2);";
+    let (scroll_x, scroll_y) = match comp.lores_scroll {
+        LoresScrollMode::Whole => {
+            let (x, y) = comp.lores_size.scale();
+            (format!("if self.screen.is_lowres() {{ {} }} else {{ 1 }}", x), format!("if self.screen.is_lowres() {{ {} }} else {{ 1 }}", y))
+        }
+        LoresScrollMode::Half => ("1".into(), "1".into()),
+    };
+    match *instruction {
+        ClearScreen => "self.screen.clear();".into(),
+        Return => "self.pc = self.stack.pop().expect(\"return without call\");".into(),
+        ScrollDown(Constant(n)) => format!("self.screen.scroll_down({} * {});", n, scroll_y),
+        ScrollRight => format!("self.screen.scroll_right(4 * {});", scroll_x),
+        ScrollLeft => format!("self.screen.scroll_left(4 * {});", scroll_x),
+        Exit => "self.exited = true;".into(),
+        LoRes => "self.screen.disable_hires();".into(),
+        HiRes => "self.screen.enable_hires();".into(),
+        Jump(Address(nnn)) => format!("self.pc = 0x{:03X};", nnn),
+        Call(Address(nnn)) => format!("self.stack.push(self.pc); self.pc = 0x{:03X};", nnn),
+        SkipEqualConstant(Register(x), Constant(kk)) => format!("if self.v[{}] == 0x{:02X} {{ {} }}", x, kk, skip),
+        SkipNotEqualConstant(Register(x), Constant(kk)) => format!("if self.v[{}] != 0x{:02X} {{ {} }}", x, kk, skip),
+        SkipEqual(Register(x), Register(y)) => format!("if self.v[{}] == self.v[{}] {{ {} }}", x, y, skip),
+        SkipNotEqual(Register(x), Register(y)) => format!("if self.v[{}] != self.v[{}] {{ {} }}", x, y, skip),
+        Set(Register(x), Constant(kk)) => format!("self.v[{}] = 0x{:02X};", x, kk),
+        SetSum(Register(x), Constant(kk)) => format!("self.v[{}] = self.v[{}].wrapping_add(0x{:02X});", x, x, kk),
+        Mov(Register(x), Register(y)) => format!("self.v[{}] = self.v[{}];", x, y),
+        Or(Register(x), Register(y)) => format!("self.v[{}] |= self.v[{}];", x, y),
+        And(Register(x), Register(y)) => format!("self.v[{}] &= self.v[{}];", x, y),
+        Xor(Register(x), Register(y)) => format!("self.v[{}] ^= self.v[{}];", x, y),
+        Add(Register(x), Register(y)) => format!("let (sum, carry) = self.v[{}].overflowing_add(self.v[{}]); self.v[{}] = sum; self.v[15] = carry as u8;", x, y, x),
+        Sub(Register(x), Register(y)) => format!("let (difference, borrow) = self.v[{}].overflowing_sub(self.v[{}]); self.v[{}] = difference; self.v[15] = !borrow as u8;", x, y, x),
+        RevSub(Register(x), Register(y)) => format!("let (difference, borrow) = self.v[{}].overflowing_sub(self.v[{}]); self.v[{}] = difference; self.v[15] = !borrow as u8;", y, x, x),
+        ShiftRight(Register(x), Register(y)) | ShiftLeft(Register(x), Register(y)) => {
+            let source = if comp.shift == ShiftMode::Original { y } else { x };
+            let operation = if matches!(instruction, ShiftRight(..)) { "overflowing_shr" } else { "overflowing_shl" };
+            format!("let (shifted, carry) = self.v[{}].{}(1); self.v[{}] = shifted; self.v[15] = carry as u8;", source, operation, x)
+        }
+        LoadI(Address(nnn)) => format!("self.i = 0x{:03X};", nnn),
+        JumpRelative(Address(nnn)) => {
+            let base = match comp.jump_mode {
+                RelativeJumpMode::Original => 0,
+                RelativeJumpMode::SuperChip => (nnn & 0xF00) >> 8,
+            };
+            format!("self.pc = 0x{:03X} + self.v[{}] as u16;", nnn, base)
+        }
+        Random(Register(x), Constant(kk)) => format!("self.v[{}] = self.random() & 0x{:02X};", x, kk),
+        Draw(Register(x), Register(y), Constant(n)) => {
+            let wrap = match comp.draw_origin {
+                DrawOrigin::Wrap => format!("let (width, height) = self.screen.size(LoresSize::{:?}); ", comp.lores_size),
+                DrawOrigin::Raw => String::new(),
+            };
+            let origin = match comp.draw_origin {
+                DrawOrigin::Wrap => "(x % width, y % height)",
+                DrawOrigin::Raw => "(x, y)",
+            };
+            let flag = match comp.collisions {
+                CollisionEnumeration::SuperChip => "if self.screen.is_lowres() { (result.collided_rows != 0) as u8 } else { (result.collided_rows + result.clipped_rows) as u8 }",
+                CollisionEnumeration::Original => "(result.collided_rows != 0) as u8",
+            };
+            format!(
+                "let (x, y) = (self.v[{}] as usize, self.v[{}] as usize); {}let (x, y) = {}; \
+                let result = self.screen.draw_sprite(&self.memory[self.i as usize..], x, y, {}, LargeSpriteMode::{:?}, [SpriteEdge::{:?}, SpriteEdge::{:?}], LoresSize::{:?}); \
+                self.v[15] = {};",
+                x, y, wrap, origin, n, comp.large_sprites, comp.horizontal_edge, comp.vertical_edge, comp.lores_size, flag,
+            )
+        }
+        SkipPressed(Register(x)) => format!("if keys.is_pressed(self.v[{}]) {{ {} }}", x, skip),
+        SkipNotPressed(Register(x)) => format!("if !keys.is_pressed(self.v[{}]) {{ {} }}", x, skip),
+        LoadDelay(Register(x)) => format!("self.v[{}] = self.delay_timer;", x),
+        WaitForKey(Register(x)) => format!("match (0..16).find(|&k| keys.is_pressed(k)) {{ Some(k) => self.v[{}] = k, None => self.pc = pc }}", x),
+        StoreDelay(Register(x)) => format!("self.delay_timer = self.v[{}];", x),
+        StoreSound(Register(x)) => format!("self.sound_timer = self.v[{}];", x),
+        AddI(Register(x)) => {
+            let mut body = format!("self.i = self.i.wrapping_add(self.v[{}] as u16);", x);
+            if comp.add_i_overflow == AddIOverflow::Amiga {
+                body.push_str(" self.v[15] = (self.i > 0xFFF) as u8;");
+            }
+            if comp.address_space == AddressSpace::Original {
+                body.push_str(" self.i %= 4096;");
+            }
+            body
+        }
+        LoadSprite(Register(x)) => format!("self.i = 0x{:03X} + self.v[{}] as u16 * 5;", FONT_ADDRESS, x),
+        LoadLargeSprite(Register(x)) => format!("self.i = 0x{:03X} + self.v[{}] as u16 * 10;", LARGE_FONT_ADDRESS, x),
+        StoreBCD(Register(x)) => format!(
+            "let (value, i) = (self.v[{}], self.i as usize); self.memory[i] = value / 100; self.memory[i + 1] = value / 10 % 10; self.memory[i + 2] = value % 10;",
+            x,
+        ),
+        Store(Register(x)) | Load(Register(x)) => {
+            let copy = if matches!(instruction, Store(_)) {
+                format!("self.memory[i..=i + {}].copy_from_slice(&self.v[..={}]);", x, x)
+            }
+            else {
+                format!("self.v[..={}].copy_from_slice(&self.memory[i..=i + {}]);", x, x)
+            };
+            let increment = match comp.load_store {
+                LoadStoreMode::Original => format!(" self.i += {};", x),
+                LoadStoreMode::SuperChip => String::new(),
+            };
+            format!("let i = self.i as usize; {}{}", copy, increment)
+        }
+        StoreUserFlags(Register(x)) | LoadUserFlags(Register(x)) => {
+            let count = comp.allowed_instructions.user_flag_count();
+            if x as usize >= count {
+                format!("panic!(\"User flag V{:X} at address {:03X} is out of range\")", x, address)
+            }
+            else if matches!(instruction, StoreUserFlags(_)) {
+                format!("self.user_flags[..={}].copy_from_slice(&self.v[..={}]);", x, x)
+            }
+            else {
+                format!("self.v[..={}].copy_from_slice(&self.user_flags[..={}]);", x, x)
+            }
+        }
+        SelectPlanes(Constant(n)) => format!("self.screen.select_planes({});", n),
+        LoadAudio => format!(
+            "let i = self.i as usize; self.audio_patterns = (0..{}).map(|voice| std::array::from_fn(|offset| self.memory[(i + voice * 16 + offset) % 0x10000])).collect();",
+            comp.audio_voices,
+        ),
+        StorePitch(Register(x)) => format!("self.pitch = self.v[{}];", x),
+        DebugRegisters(Register(x), Register(y)) => {
+            let values: Vec<_> = (x..=y).map(|r| format!("V{:X}={{:02X}}", r)).collect();
+            let arguments: Vec<_> = (x..=y).map(|r| format!("self.v[{}]", r)).collect();
+            format!("println!(\"[{:03X}] {}\", {});", address, values.join(" "), arguments.join(", "))
+        }
+        DebugString => format!(
+            "let text: String = self.memory[self.i as usize..].iter().take_while(|&&b| b != 0).map(|&b| b as char).collect(); println!(\"[{:03X}] {{}}\", text);",
+            address,
+        ),
+    }
+}
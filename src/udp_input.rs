@@ -0,0 +1,102 @@
+//! Keypad input from UDP datagrams, for controllers and installations that aren't keyboards.
+//!
+//! Two kinds of datagrams are understood:
+//! - OSC messages, either `/key/<n>` with one argument or `/key` with the key and its state.
+//!   Arguments may be ints, floats or booleans, anything non-zero counts as pressed.
+//! - Text lines like `A down` or `3 up`, with the keypad key in hexadecimal.
+
+use std::{io, net::{ToSocketAddrs, UdpSocket}};
+use tracing::{debug, info, warn};
+use crate::emulator::keys::Keys;
+
+/// Larger datagrams are truncated, no valid message comes close
+const MAX_DATAGRAM_SIZE: usize = 1024;
+
+
+pub struct UdpInput {
+    socket: UdpSocket,
+}
+impl UdpInput {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        info!("Accepting key input on UDP {}", socket.local_addr()?);
+        Ok(Self { socket })
+    }
+
+    /// Applies the key presses of all datagrams received since the last call to `keys`
+    pub fn poll(&mut self, keys: &mut Keys) {
+        let mut buffer = [0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (length, sender) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!("Could not receive key input: {}", e);
+                    return;
+                }
+            };
+
+            let datagram = &buffer[..length];
+            let parsed = if datagram.starts_with(b"/") { parse_osc(datagram) } else { parse_text(datagram) };
+            match parsed {
+                Some((key, pressed)) => {
+                    debug!(key, pressed, "Key input from {}", sender);
+                    keys.set_key(key, pressed);
+                }
+                None => warn!("Ignoring invalid key input from {}", sender),
+            }
+        }
+    }
+}
+
+
+/// Parses `<key> down` or `<key> up`
+fn parse_text(datagram: &[u8]) -> Option<(u8, bool)> {
+    let text = std::str::from_utf8(datagram).ok()?;
+    let mut words = text.split_whitespace();
+    let key = u8::from_str_radix(words.next()?, 16).ok().filter(|&k| k < 16)?;
+    let pressed = match words.next()?.to_ascii_lowercase().as_str() {
+        "down" | "press" | "1" => true,
+        "up" | "release" | "0" => false,
+        _ => return None,
+    };
+    Some((key, pressed))
+}
+
+/// Parses an OSC message, `/key/<n> state` or `/key <n> state`
+fn parse_osc(datagram: &[u8]) -> Option<(u8, bool)> {
+    let (address, rest) = osc_string(datagram)?;
+    let (tags, mut arguments) = osc_string(rest)?;
+    let tags = tags.strip_prefix(',')?;
+
+    let mut values = Vec::new();
+    for tag in tags.chars() {
+        let value = match tag {
+            'i' | 'f' => {
+                let bytes: [u8; 4] = arguments.get(..4)?.try_into().ok()?;
+                arguments = &arguments[4..];
+                if tag == 'i' { i32::from_be_bytes(bytes) as f32 } else { f32::from_be_bytes(bytes) }
+            }
+            'T' => 1.0,
+            'F' => 0.0,
+            _ => return None,
+        };
+        values.push(value);
+    }
+
+    let (key, state) = match address.strip_prefix("/key") {
+        Some("") => (*values.first()? as u8, *values.get(1)?),
+        Some(key) => (key.strip_prefix('/')?.parse().ok()?, *values.first()?),
+        None => return None,
+    };
+    (key < 16).then_some((key, state != 0.0))
+}
+
+/// Splits a null-terminated string padded to four bytes off the front of `bytes`
+fn osc_string(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let end = bytes.iter().position(|&b| b == 0)?;
+    let text = std::str::from_utf8(&bytes[..end]).ok()?;
+    let padded = (end / 4 + 1) * 4;
+    Some((text, bytes.get(padded..)?))
+}
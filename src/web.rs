@@ -0,0 +1,145 @@
+//! JavaScript bindings to the emulator core, for embedding chippy in web pages.
+//!
+//! ```js
+//! const chippy = new Chippy(rom);
+//! function frame() {
+//!     chippy.step();
+//!     context.putImageData(new ImageData(new Uint8ClampedArray(chippy.frameBuffer()), Chippy.width(), Chippy.height()), 0, 0);
+//!     requestAnimationFrame(frame);
+//! }
+//! ```
+
+use wasm_bindgen::prelude::*;
+use crate::emulator::{comp_mode::{CompatibilityMode, CompBuilder}, keys::Keys, machine::Machine, palette::Palette, screen::{HEIGHT, WIDTH}};
+
+const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
+
+
+#[wasm_bindgen]
+pub struct Chippy {
+    machine: Machine,
+    comp: CompatibilityMode,
+    keys: Keys,
+    palette: Palette,
+    instructions_per_frame: usize,
+}
+#[wasm_bindgen]
+impl Chippy {
+    /// Loads `rom` into a fresh machine with the default quirks
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<Chippy, JsError> {
+        let comp = CompBuilder::new().build();
+        let mut machine = Machine::new(rand::random());
+        machine.init_instruction_pointer(comp.program_start);
+        machine.load_sprites();
+        machine.load_program(rom, comp.program_start as usize).map_err(|e| JsError::new(&e.to_string()))?;
+
+        Ok(Self {
+            machine,
+            comp,
+            keys: Keys::new(),
+            palette: Palette::default(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+        })
+    }
+
+    /// Width of the frame buffer in pixels
+    pub fn width() -> usize {
+        WIDTH
+    }
+    /// Height of the frame buffer in pixels
+    pub fn height() -> usize {
+        HEIGHT
+    }
+
+    /// Runs one frame, meant to be called 60 times a second.
+    /// Returns whether the screen changed.
+    pub fn step(&mut self) -> bool {
+        self.machine.run_frame(self.instructions_per_frame, &self.comp, &self.keys).screen_changed
+    }
+    /// Executes a single instruction without touching the timers
+    #[wasm_bindgen(js_name = stepInstruction)]
+    pub fn step_instruction(&mut self) {
+        self.machine.step_n(1, &self.comp, &self.keys);
+    }
+    /// Restarts the program
+    pub fn reset(&mut self) {
+        self.machine.reset();
+    }
+
+    /// The screen as RGBA bytes, `width()` by `height()` pixels. Lores pixels are two by two.
+    #[wasm_bindgen(js_name = frameBuffer)]
+    pub fn frame_buffer(&self) -> Vec<u8> {
+        let mut buffer = vec![0; WIDTH * HEIGHT * 4];
+        self.machine.screen().render_to_pixel_buffer(&mut buffer, &self.palette);
+        buffer
+    }
+
+    #[wasm_bindgen(js_name = keyDown)]
+    pub fn key_down(&mut self, key: u8) {
+        if key < 16 {
+            self.keys.set_key(key, true);
+        }
+    }
+    #[wasm_bindgen(js_name = keyUp)]
+    pub fn key_up(&mut self, key: u8) {
+        if key < 16 {
+            self.keys.set_key(key, false);
+        }
+    }
+
+    /// Whether the buzzer should sound
+    #[wasm_bindgen(js_name = isSoundPlaying)]
+    pub fn is_sound_playing(&self) -> bool {
+        self.machine.is_sound_playing()
+    }
+    /// The XO-Chip audio patterns the voices loop, 16 bytes each, to be mixed together.
+    /// Empty while the buzzer plays the plain tone.
+    #[wasm_bindgen(js_name = audioPatterns)]
+    pub fn audio_patterns(&self) -> Vec<u8> {
+        self.machine.audio_patterns().concat()
+    }
+    /// Samples per second to play the audio pattern at, from the XO-Chip pitch register
+    #[wasm_bindgen(js_name = audioRate)]
+    pub fn audio_rate(&self) -> f32 {
+        crate::audio::pitch_rate(self.machine.pitch())
+    }
+    /// Whether the program ran a SuperChip exit instruction
+    #[wasm_bindgen(js_name = hasExited)]
+    pub fn has_exited(&self) -> bool {
+        self.machine.has_exited()
+    }
+
+    #[wasm_bindgen(js_name = setInstructionsPerFrame)]
+    pub fn set_instructions_per_frame(&mut self, instructions: usize) {
+        self.instructions_per_frame = instructions;
+    }
+    /// Switches the quirks to those of `"chip8"`, `"superchip"`, `"eti660"` or `"default"`.
+    /// Presets loading programs elsewhere move the program there and restart it.
+    #[wasm_bindgen(js_name = setPreset)]
+    pub fn set_preset(&mut self, preset: &str) -> Result<(), JsError> {
+        let builder = match preset {
+            "chip8" => CompBuilder::chip8_preset(),
+            "superchip" => CompBuilder::superchip_preset(),
+            "eti660" => CompBuilder::eti660_preset(),
+            "default" => CompBuilder::new(),
+            _ => return Err(JsError::new(&format!("unknown preset {}", preset))),
+        };
+        let comp = builder.build();
+        if comp.program_start != self.comp.program_start {
+            let program = self.machine.program().to_vec();
+            self.machine.load_program(&program, comp.program_start as usize).map_err(|e| JsError::new(&e.to_string()))?;
+            self.machine.reset();
+        }
+        self.comp = comp;
+        Ok(())
+    }
+    /// Sets one of the four palette colors from a `0xRRGGBB` value
+    #[wasm_bindgen(js_name = setColor)]
+    pub fn set_color(&mut self, index: usize, rgb: u32) {
+        if let Some(color) = self.palette.0.get_mut(index) {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            *color = [r, g, b];
+        }
+    }
+}